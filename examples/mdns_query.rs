@@ -5,7 +5,6 @@ use mdns::{config::*, conn::*};
 use clap::{App, AppSettings, Arg};
 use std::net::SocketAddr;
 use std::str::FromStr;
-use tokio::sync::mpsc;
 use util::Error;
 
 // For interop with webrtc-rs/mdns_server
@@ -65,11 +64,12 @@ async fn main() -> Result<(), Error> {
 
     log::info!("querying dns");
 
-    let (_a, b) = mpsc::channel(1);
-
-    let (answer, src) = server.query(local_name, b).await.unwrap();
+    let res = server.query(local_name).await.unwrap();
     log::info!("dns queried");
-    println!("answer = {}, src = {}", answer, src);
+    println!(
+        "answer = {}, ip = {:?}, src = {}",
+        res.answer, res.ip, res.addr
+    );
 
     server.close().await.unwrap();
 
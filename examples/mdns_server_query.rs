@@ -1,5 +1,5 @@
 use std::net::{Ipv4Addr, SocketAddr};
-use tokio::sync::mpsc;
+use std::time::Duration;
 use webrtc_mdns::{config::*, conn::*};
 
 #[tokio::main]
@@ -28,25 +28,23 @@ async fn main() {
     )
     .unwrap();
 
-    let (a, b) = mpsc::channel(1);
-
-    tokio::spawn(async move {
-        tokio::time::sleep(tokio::time::Duration::from_secs(20)).await;
-        a.send(()).await
-    });
-
-    let (answer, src) = server_b.query("webrtc-rs-mdns-1.local", b).await.unwrap();
-    println!("webrtc-rs-mdns-1.local answer = {}, src = {}", answer, src);
-
-    let (a, b) = mpsc::channel(1);
-
-    tokio::spawn(async move {
-        tokio::time::sleep(tokio::time::Duration::from_secs(20)).await;
-        a.send(()).await
-    });
-
-    let (answer, src) = server_b.query("webrtc-rs-mdns-2.local", b).await.unwrap();
-    println!("webrtc-rs-mdns-2.local answer = {}, src = {}", answer, src);
+    let res = server_b
+        .query_with_timeout("webrtc-rs-mdns-1.local", Duration::from_secs(20))
+        .await
+        .unwrap();
+    println!(
+        "webrtc-rs-mdns-1.local answer = {}, ip = {:?}, src = {}",
+        res.answer, res.ip, res.addr
+    );
+
+    let res = server_b
+        .query_with_timeout("webrtc-rs-mdns-2.local", Duration::from_secs(20))
+        .await
+        .unwrap();
+    println!(
+        "webrtc-rs-mdns-2.local answer = {}, ip = {:?}, src = {}",
+        res.answer, res.ip, res.addr
+    );
 
     server_a.close().await.unwrap();
     server_b.close().await.unwrap();
@@ -1,14 +1,226 @@
+use crate::message::resource::Resource;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// AnswerAddresses is which addresses the local names are answered with.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum AnswerAddresses {
+    /// Querier answers with the address the question came from, as earlier
+    /// versions did, which lets WebRTC peers learn their reflexive address
+    #[default]
+    Querier,
+    /// FacingQuerier answers with the address of the local interface that
+    /// the querier is reached through, for classic host name resolution
+    FacingQuerier,
+    /// Fixed answers with the given addresses
+    Fixed(Vec<IpAddr>),
+    /// Interfaces answers with every address of the local interfaces on the
+    /// querier's subnet, or of all of them but loopback if there are none,
+    /// for hosts with several interfaces
+    Interfaces,
+}
+
+/// IpVersion is which versions of IP mDNS is run over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpVersion {
+    /// V4 runs mDNS over IPv4 only
+    V4,
+    /// V6 runs mDNS over IPv6 only
+    V6,
+    /// Dual runs mDNS over IPv4 and IPv6 side by side, with a socket for
+    /// each
+    Dual,
+}
+
+/// Subnet is a range of IP addresses: those sharing the first prefix_len
+/// bits of addr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subnet {
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl Subnet {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Self {
+        Subnet { addr, prefix_len }
+    }
+
+    /// contains reports whether ip is in the subnet. An IPv4 address is
+    /// never in an IPv6 subnet, nor the other way around.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(addr), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len.min(32)))
+                    .unwrap_or(0);
+                u32::from(addr) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(addr), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len.min(128)))
+                    .unwrap_or(0);
+                u128::from(addr) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// InterfaceId names a network interface, by name or by index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceId {
+    Name(String),
+    Index(u32),
+}
+
+impl InterfaceId {
+    /// matches reports whether it names the interface with the given name
+    /// and index.
+    pub fn matches(&self, name: &str, index: u32) -> bool {
+        match self {
+            InterfaceId::Name(n) => n == name,
+            InterfaceId::Index(i) => *i == index,
+        }
+    }
+}
 
 // Config is used to configure a mDNS client or server.
 #[derive(Default, Debug)]
 pub struct Config {
-    // query_interval controls how often we sends Queries until we
-    // get a response for the requested name
+    // query_interval is how long we wait before sending a Query again
+    // when we get no response for the requested name. The wait doubles
+    // after each retransmission, up to max_query_interval.
     pub query_interval: Duration,
 
+    // max_query_interval caps the wait between retransmissions. Zero
+    // means one hour.
+    pub max_query_interval: Duration,
+
+    // max_query_attempts is how many times a query is sent before it fails
+    // with ERR_QUERY_NOT_FOUND, one query interval after the last. Zero
+    // means no limit. Browses are never limited.
+    pub max_query_attempts: u32,
+
+    // query_timeout is how long a query waits for an answer before it
+    // fails with ERR_QUERY_TIMEOUT. Zero means no timeout.
+    pub query_timeout: Duration,
+
     // local_names are the names that we will generate answers for
     // when we get questions
     pub local_names: Vec<String>,
+
+    // probe_local_names probes for each local name before answering for it,
+    // which takes about 750ms, and renames it if another host turns out to
+    // use it; once claimed, the name is announced, and defended against
+    // hosts answering for it (RFC 6762 Sections 8 and 9). Off, the local
+    // names are answered for at once and never renamed. Names are never
+    // probed for with the Querier answer addresses, which have no records
+    // to propose.
+    pub probe_local_names: bool,
+
+    // answer_addresses are the addresses the local names are answered
+    // with: an A record for each IPv4 address and an AAAA record for each
+    // IPv6 address, as asked for, in a single message
+    pub answer_addresses: AnswerAddresses,
+
+    // response_ttl is the TTL of the answers for the local names, and of
+    // the records without one of their own. It is rounded down to whole
+    // seconds. Zero means 120 seconds.
+    pub response_ttl: Duration,
+
+    // records are served as they are for the questions asking for their
+    // name, keyed by name like local_names. The name and type of each
+    // record are taken from the key and body; a zero class means IN and a
+    // zero TTL response_ttl. Records without the cache-flush bit are shared,
+    // so multicast answers with them are delayed by 20-120 ms.
+    pub records: HashMap<String, Vec<Resource>>,
+
+    // follow_cname makes queries follow a CNAME answer for the requested
+    // name and resolve its target instead of ignoring it
+    pub follow_cname: bool,
+
+    // ip_version is which versions of IP mDNS is run over. The socket of
+    // the version of the address the server is bound to is bound to it, and
+    // that of the other to the unspecified address and the same port. None
+    // means the version of that address.
+    pub ip_version: Option<IpVersion>,
+
+    // multicast_addr is the multicast group joined, and the group and port
+    // that queries and answers are sent to, for its version of IP; the
+    // other uses its default group and the same port. None means
+    // 224.0.0.251:5353 for IPv4 and [ff02::fb]:5353 for IPv6.
+    pub multicast_addr: Option<SocketAddr>,
+
+    // socket_per_interface opens a socket for each interface, joined to the
+    // multicast group on that interface only and sending out of it, instead
+    // of one socket joined to the group on every interface. Each question
+    // is then known to have come in on its socket's interface, and is
+    // answered out of that interface with its addresses.
+    pub socket_per_interface: bool,
+
+    // allowed_interfaces, if not empty, are the only interfaces the
+    // multicast group is joined on, packets are accepted from and whose
+    // addresses we answer with
+    pub allowed_interfaces: Vec<InterfaceId>,
+
+    // denied_interfaces are interfaces mDNS is never run on, even if they
+    // are in allowed_interfaces
+    pub denied_interfaces: Vec<InterfaceId>,
+
+    // bind_device binds the sockets to the device with this name
+    // (SO_BINDTODEVICE), so that mDNS is sent and received over it alone,
+    // and never leaks onto a VPN or container interface. It is only
+    // supported on Linux.
+    pub bind_device: Option<String>,
+
+    // interface_rescan_interval is how often the interfaces are checked for
+    // ones that have come up or gone down since, to join or leave the
+    // multicast group on. Zero means they are only looked at on start.
+    pub interface_rescan_interval: Duration,
+
+    // membership_refresh_interval is how often the multicast group is left
+    // and joined again on every interface, reporting the membership afresh
+    // to switches snooping on IGMP or MLD that may have dropped it. Zero
+    // means it never is.
+    pub membership_refresh_interval: Duration,
+
+    // max_message_size is the largest message we send, in bytes. Larger
+    // queries and responses are split over several messages. Zero means
+    // 1472, which fits an Ethernet MTU.
+    pub max_message_size: usize,
+
+    // responder_only disables queries, leaving a server that only answers
+    // questions for the local names
+    pub responder_only: bool,
+
+    // querier_only disables answering, leaving a server that only queries:
+    // the local names and records are ignored, and no question is answered
+    pub querier_only: bool,
+
+    // validate_source drops the packets that did not come from the local
+    // link: those with an IP TTL other than 255, where the platform reports
+    // it, and those from an address on none of the subnets of our
+    // interfaces, unless it is a link-local or loopback one
+    pub validate_source: bool,
+
+    // allowed_sources, if not empty, are the only subnets the questions and
+    // answers we accept may come from
+    pub allowed_sources: Vec<Subnet>,
+
+    // denied_sources are subnets the questions and answers from which are
+    // dropped, even if they are in allowed_sources
+    pub denied_sources: Vec<Subnet>,
+
+    // max_questions_per_source is how many questions a second are answered
+    // for any one source address; the queries asking more are dropped. Zero
+    // means no limit.
+    pub max_questions_per_source: u32,
+
+    // cancellation_token, if set, shuts the server down when cancelled,
+    // like close
+    pub cancellation_token: Option<CancellationToken>,
     //LoggerFactory logging.LoggerFactory
 }
@@ -1,43 +1,3963 @@
 #[cfg(test)]
 mod test {
-    use crate::{config::Config, conn::*};
+    use crate::message::resource::{ptr::*, srv::*, txt::*};
+    use crate::{
+        config::Config,
+        conn::{querier::*, responder::*, *},
+    };
+    use std::convert::TryFrom;
     use tokio::time::timeout;
     use util::Error;
 
+    fn responder(socket: &Arc<UdpSocket>, local_names: &[&str], dst_addr: SocketAddr) -> Responder {
+        Responder {
+            local_names: Arc::new(Mutex::new(
+                local_names.iter().map(|n| n.to_string()).collect(),
+            )),
+            probe_local_names: true,
+            probes: Arc::new(Mutex::new(vec![])),
+            goodbyes: Arc::new(Mutex::new(vec![])),
+            announcements: Mutex::new(vec![]),
+            defended: Mutex::new(vec![]),
+            records: Arc::new(vec![]),
+            querier_only: false,
+            answer_addresses: AnswerAddresses::Querier,
+            on_question_handler: Arc::new(Mutex::new(None)),
+            on_name_conflict_handler: Arc::new(Mutex::new(None)),
+            links: vec![Arc::new(Link::new(Arc::clone(socket), dst_addr))],
+            interface_links: Mutex::new(vec![]),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            response_ttl: RESPONSE_TTL,
+            send_buf: Mutex::new(vec![]),
+            sent: Mutex::new(vec![]),
+            validate_source: false,
+            allowed_sources: vec![],
+            denied_sources: vec![],
+            max_questions_per_source: 0,
+            source_questions: Mutex::new(HashMap::new()),
+            question_counters: Arc::new(Mutex::new(QuestionCounters::default())),
+            interfaces: InterfaceFilter::default(),
+            multicast_groups: Arc::new(std::sync::Mutex::new(vec![])),
+            interface_rescan_interval: Duration::ZERO,
+            membership_refresh_interval: Duration::ZERO,
+        }
+    }
+
+    // config is the default Config of the tests. It multicasts to a port no
+    // mDNS host listens on, keeping the tests off the network's mDNS traffic.
+    fn config() -> Config {
+        Config {
+            multicast_addr: Some(SocketAddr::new(Ipv4Addr::new(224, 0, 0, 251).into(), 5399)),
+            ..Default::default()
+        }
+    }
+
+    // loopback is an ephemeral port on the loopback address.
+    fn loopback() -> SocketAddr {
+        SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), 0)
+    }
+
+    fn query(
+        name: Name,
+        types: Vec<DNSType>,
+        query_result_chan: mpsc::Sender<BrowseEvent>,
+        multiple_answers: bool,
+    ) -> Query {
+        Query {
+            name_with_suffix: name,
+            types,
+            query_result_chans: vec![query_result_chan],
+            multiple_answers,
+            cache: None,
+            next_query: Instant::now(),
+            interval: DEFAULT_QUERY_INTERVAL,
+            max_interval: DEFAULT_QUERY_INTERVAL,
+            started: Instant::now(),
+            attempts: 1,
+            max_attempts: 0,
+        }
+    }
+
+    fn query_result(answer: Resource, addr: SocketAddr) -> QueryResult {
+        QueryResult {
+            ip: answer.ip(),
+            ttl: Duration::from_secs(answer.header.ttl.into()),
+            received: std::time::Instant::now(),
+            answer,
+            addr,
+        }
+    }
+
+    fn answer_event(event: Option<BrowseEvent>) -> QueryResult {
+        match event.expect("expected a query result") {
+            BrowseEvent::Answer(res) => res,
+            event => panic!("expected an answer, got {:?}", event),
+        }
+    }
+
     #[tokio::test]
     async fn test_multiple_close() -> Result<(), Error> {
-        let server_a = DNSConn::server(
-            SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 5353),
-            Config::default(),
-        )?;
+        let server_a = DNSConn::server(loopback(), config())?;
 
+        assert!(!server_a.is_closed());
         server_a.close().await?;
+        assert!(server_a.is_closed());
 
         if let Err(err) = server_a.close().await {
             assert_eq!(err, *ERR_CONNECTION_CLOSED);
         } else {
-            assert!(false, "expected error, but got ok");
+            panic!("expected error, but got ok");
         }
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_query_respect_timeout() -> Result<(), Error> {
+    async fn test_local_addr() -> Result<(), Error> {
+        let server_a = DNSConn::server(
+            SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 0),
+            config(),
+        )?;
+
+        // the port bound stands in for port 0
+        assert!(server_a.local_addr().ip().is_unspecified());
+        assert_ne!(server_a.local_addr().port(), 0);
+        assert!(!server_a.multicast_groups().is_empty());
+        for (group, _) in server_a.multicast_groups() {
+            assert_eq!(group, IpAddr::from([224, 0, 0, 251]));
+        }
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multicast_addr() -> Result<(), Error> {
+        let dst_addr: SocketAddr = "224.0.0.252:5354".parse()?;
         let server_a = DNSConn::server(
-            SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 5353),
-            Config::default(),
+            SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 5354),
+            Config {
+                multicast_addr: Some(dst_addr),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(server_a.addrs.lock().unwrap()[0].1, dst_addr);
+        assert!(!server_a.multicast_groups().is_empty());
+        for (group, _) in server_a.multicast_groups() {
+            assert_eq!(group, IpAddr::from([224, 0, 0, 252]));
+        }
+        // a restart joins the same group
+        server_a.restart().await?;
+        for (group, _) in server_a.multicast_groups() {
+            assert_eq!(group, IpAddr::from([224, 0, 0, 252]));
+        }
+        server_a.close().await?;
+
+        let res = DNSConn::server(
+            SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 5354),
+            Config {
+                multicast_addr: Some("10.0.0.1:5353".parse()?),
+                ..Default::default()
+            },
+        );
+        if let Err(err) = res {
+            assert_eq!(err, *ERR_INVALID_MULTICAST_ADDR);
+        } else {
+            panic!("expected error, but got ok");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_close_releases_socket() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+        let addr = server_a.local_addr();
+
+        server_a.close().await?;
+
+        // without address reuse, binding only succeeds once the port is free
+        std::net::UdpSocket::bind(addr)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_drop_releases_socket() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+        let addr = server_a.local_addr();
+
+        drop(server_a);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        std::net::UdpSocket::bind(addr)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restart() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+        let addr = server_a.local_addr();
+
+        server_a.restart().await?;
+        assert!(!server_a.is_closed());
+        assert_eq!(server_a.local_addr(), addr);
+        assert!(std::net::UdpSocket::bind(addr).is_err());
+
+        // restarting a closed server serves again
+        server_a.close().await?;
+        server_a.restart().await?;
+        assert!(!server_a.is_closed());
+
+        server_a.close().await?;
+        std::net::UdpSocket::bind(addr)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_runtime_local_names() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let mut responder = responder(&socket, &[], multicast.local_addr()?);
+        responder.local_names = Arc::clone(&server_a.local_names);
+
+        let question = Question {
+            name: Name::new("added.local.")?,
+            typ: DNSType::A,
+            class: DNSCLASS_INET,
+            ..Default::default()
+        };
+
+        server_a.add_local_name("added.local").await?;
+        server_a.add_local_name("added.local").await?;
+        assert_eq!(server_a.local_names.lock().await.len(), 1);
+        responder
+            .answer_questions(
+                &responder.links[0],
+                std::slice::from_ref(&question),
+                &[],
+                src,
+            )
+            .await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        assert_eq!(msg.answers[0].header.name.data, "added.local.");
+
+        assert!(server_a.remove_local_name("added.local").await);
+        assert!(!server_a.remove_local_name("added.local").await);
+        responder
+            .answer_questions(&responder.links[0], &[question], &[], src)
+            .await;
+        assert!(
+            timeout(Duration::from_millis(100), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no answer for a removed name"
+        );
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_probing() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut responder = responder(&socket, &[], multicast.local_addr()?);
+        let ip = IpAddr::from([10, 0, 0, 1]);
+        responder.answer_addresses = AnswerAddresses::Fixed(vec![ip]);
+
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+        let mut probe = Probe::new("myhost.local.".to_owned());
+        probe.next_probe = Instant::now();
+        probe.result_chans.push(result_tx);
+        responder.probes.lock().await.push(probe);
+
+        // three probes, the first asking for unicast answers
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for i in 0..PROBE_COUNT {
+            responder.handle_due_probes().await;
+            let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected a probe")?;
+            let msg = Message::try_from(&b[..n])?;
+            assert!(!msg.header.response);
+            assert_eq!(msg.questions.len(), 1);
+            assert_eq!(msg.questions[0].name.data, "myhost.local.");
+            assert_eq!(msg.questions[0].typ, DNSType::ALL);
+            assert_eq!(msg.questions[0].unicast_response, i == 0);
+            assert_eq!(msg.authorities.len(), 1);
+            assert_eq!(msg.authorities[0].ip(), Some(ip));
+
+            // not answered for while probing
+            assert!(responder.local_names.lock().await.is_empty());
+            let next_probe = responder.probes.lock().await[0].next_probe;
+            assert!(next_probe > Instant::now() + PROBE_INTERVAL - Duration::from_millis(50));
+            responder.probes.lock().await[0].next_probe = Instant::now();
+        }
+
+        // then claimed
+        responder.handle_due_probes().await;
+        assert_eq!(result_rx.try_recv().ok(), Some("myhost.local.".to_owned()));
+        assert!(responder.probes.lock().await.is_empty());
+        assert_eq!(
+            *responder.local_names.lock().await,
+            vec!["myhost.local.".to_owned()]
+        );
+        assert_eq!(responder.announcements.lock().await.len(), 1);
+        responder.announcements.lock().await.clear();
+
+        // a name another host answers for meanwhile is renamed and probed
+        // for again
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+        let mut probe = Probe::new("taken.local.".to_owned());
+        probe.result_chans.push(result_tx);
+        responder.probes.lock().await.push(probe);
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: vec![answer("Taken.local.", IpAddr::from([10, 0, 0, 2]), 120)?],
+            ..Default::default()
+        };
+        let raw = msg.pack()?;
+        let mut p = Parser::default();
+        p.start(&raw)?;
+        run(
+            &mut p,
+            &responder,
+            &responder.links[0],
+            multicast.local_addr()?,
+            &Arc::new(Mutex::new(vec![])),
+            &Arc::new(Mutex::new(vec![])),
+            false,
+            true,
+        )
+        .await;
+        assert!(result_rx.try_recv().is_err());
+        let probes = responder.probes.lock().await;
+        assert_eq!(probes.len(), 1);
+        assert_eq!(probes[0].name, "taken-2.local.");
+        assert_eq!(probes[0].sent, 0);
+        assert_eq!(probes[0].result_chans.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_probe_local_names() -> Result<(), Error> {
+        // the local names are answered for at once by default
+        let server = DNSConn::server(
+            loopback(),
+            Config {
+                local_names: vec!["myhost.local".to_owned()],
+                ..config()
+            },
+        )?;
+        assert!(server.probes.lock().await.is_empty());
+        assert_eq!(*server.local_names.lock().await, vec!["myhost.local."]);
+        assert_eq!(
+            timeout(
+                Duration::from_millis(100),
+                server.add_local_name("added.local")
+            )
+            .await
+            .expect("expected the name claimed at once")?,
+            "added.local"
+        );
+        server.close().await?;
+
+        // and with the Querier answer addresses, which have nothing to probe
+        // for
+        let server = DNSConn::server(
+            loopback(),
+            Config {
+                local_names: vec!["myhost.local".to_owned()],
+                probe_local_names: true,
+                ..config()
+            },
         )?;
+        assert!(server.probes.lock().await.is_empty());
+        assert_eq!(*server.local_names.lock().await, vec!["myhost.local."]);
+        server.close().await?;
 
-        let (a, b) = mpsc::channel(1);
+        let server = DNSConn::server(
+            loopback(),
+            Config {
+                local_names: vec!["myhost.local".to_owned()],
+                probe_local_names: true,
+                answer_addresses: AnswerAddresses::Fixed(vec![IpAddr::from([10, 0, 0, 1])]),
+                ..config()
+            },
+        )?;
+        assert_eq!(server.probes.lock().await.len(), 1);
+        assert!(server.local_names.lock().await.is_empty());
+        server.close().await?;
+
+        Ok(())
+    }
 
-        timeout(Duration::from_millis(100), a.send(()))
+    #[tokio::test]
+    async fn test_probe_link_addresses() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut responder = responder(&socket, &[], multicast.local_addr()?);
+        let mut link = Link::new(Arc::clone(&socket), multicast.local_addr()?);
+        let addrs = vec![
+            IpAddr::from([10, 0, 0, 7]),
+            IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 7]),
+        ];
+        link.interface = Some(LinkInterface {
+            name: "eth0".to_owned(),
+            index: 0,
+            addrs: addrs.clone(),
+        });
+        responder.links = vec![Arc::new(link)];
+        responder.answer_addresses = AnswerAddresses::Interfaces;
+
+        // the records probed for are those the link's answers carry
+        responder
+            .send_probes(vec![("myhost.local.".to_owned(), false)])
+            .await;
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
             .await
-            .unwrap()
-            .unwrap();
+            .expect("expected a probe")?;
+        let msg = Message::try_from(&b[..n])?;
+        let probed: Vec<IpAddr> = msg.authorities.iter().filter_map(|a| a.ip()).collect();
+        assert_eq!(probed, addrs);
+        let answered = responder
+            .addresses(&responder.links[0], "10.0.0.9:5353".parse()?)
+            .await;
+        assert_eq!(answered, addrs);
 
-        let res = server_a.query("invalid-host", b).await;
-        assert!(res.is_err(), "server_a.query expects timeout!");
+        // and the Querier answer addresses have none
+        responder.answer_addresses = AnswerAddresses::Querier;
+        assert!(responder
+            .link_addresses(&responder.links[0])
+            .await
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_name_conflict_defense_and_rename() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        responder.answer_addresses = AnswerAddresses::Fixed(vec![IpAddr::from([10, 0, 0, 1])]);
+
+        let (renamed_tx, mut renamed_rx) = mpsc::channel(1);
+        let handler: OnNameConflictHdlrFn = Box::new(move |name: String, new_name: String| {
+            let renamed_tx = renamed_tx.clone();
+            Box::pin(async move {
+                let _ = renamed_tx.send((name, new_name)).await;
+            })
+        });
+        *responder.on_name_conflict_handler.lock().await = Some(Arc::new(handler));
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for (src, a, defended, renamed) in [
+            // our own address, or our own answer, is no conflict
+            ("192.0.2.7:5353", [10, 0, 0, 1], false, false),
+            ("127.0.0.1:5353", [10, 0, 0, 2], false, false),
+            // another address is defended against at first
+            ("192.0.2.7:5353", [10, 0, 0, 2], true, false),
+            ("192.0.2.7:5353", [10, 0, 0, 2], false, true),
+        ] {
+            let mut msg = Message {
+                header: Header {
+                    response: true,
+                    authoritative: true,
+                    ..Default::default()
+                },
+                answers: vec![answer("myhost.local.", IpAddr::from(a), 120)?],
+                ..Default::default()
+            };
+            let raw = msg.pack()?;
+            let mut p = Parser::default();
+            p.start(&raw)?;
+            run(
+                &mut p,
+                &responder,
+                &responder.links[0],
+                src.parse()?,
+                &Arc::new(Mutex::new(vec![])),
+                &Arc::new(Mutex::new(vec![])),
+                false,
+                true,
+            )
+            .await;
+            assert_eq!(responder.local_names.lock().await.is_empty(), renamed);
+
+            let res = timeout(Duration::from_millis(100), multicast.recv_from(&mut b)).await;
+            assert_eq!(res.is_ok(), defended);
+            if let Ok(res) = res {
+                let msg = Message::try_from(&b[..res?.0])?;
+                assert_eq!(msg.answers.len(), 1);
+                assert_eq!(msg.answers[0].ip(), Some(IpAddr::from([10, 0, 0, 1])));
+                assert!(msg.answers[0].header.cache_flush);
+            }
+        }
+
+        assert_eq!(
+            renamed_rx.try_recv().ok(),
+            Some(("myhost.local".to_owned(), "myhost-2.local".to_owned()))
+        );
+        let probes = responder.probes.lock().await;
+        assert_eq!(probes.len(), 1);
+        assert_eq!(probes[0].name, "myhost-2.local.");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_name_conflict_defense_not_sent() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        // with no address to assert, a defense sends nothing
+        responder.answer_addresses = AnswerAddresses::Fixed(vec![]);
+
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: vec![answer("myhost.local.", IpAddr::from([10, 0, 0, 2]), 120)?],
+            ..Default::default()
+        };
+        let raw = msg.pack()?;
+        for _ in 0..2 {
+            responder
+                .check_conflicts(&responder.links[0], &raw, "192.0.2.7:5353".parse()?)
+                .await;
+        }
+
+        // so the name is not renamed for the other host answering again
+        assert!(responder.defended.lock().await.is_empty());
+        assert_eq!(*responder.local_names.lock().await, vec!["myhost.local."]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_renamed() {
+        assert_eq!(renamed("myhost.local."), "myhost-2.local.");
+        assert_eq!(renamed("myhost-2.local."), "myhost-3.local.");
+        assert_eq!(renamed("my-host.local."), "my-host-2.local.");
+        assert_eq!(renamed("host-1.local."), "host-1-2.local.");
+        assert_eq!(renamed("my\\.host.local."), "my\\.host-2.local.");
+        assert_eq!(renamed("my\\.host-2.local."), "my\\.host-3.local.");
+    }
+
+    #[tokio::test]
+    async fn test_simultaneous_probes() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut responder = responder(&socket, &[], multicast.local_addr()?);
+        responder.answer_addresses = AnswerAddresses::Fixed(vec![IpAddr::from([10, 0, 0, 5])]);
+
+        for (theirs, lost) in [
+            ([10, 0, 0, 1], false),
+            ([10, 0, 0, 5], false),
+            ([10, 0, 0, 9], true),
+        ] {
+            let mut probe = Probe::new("myhost.local.".to_owned());
+            probe.sent = 2;
+            let next_probe = probe.next_probe;
+            *responder.probes.lock().await = vec![probe];
+
+            let mut qs = questions(&Name::new("myhost.local.")?, &[DNSType::ALL]);
+            qs[0].unicast_response = true;
+            let mut msg = Message {
+                questions: qs,
+                authorities: vec![answer("myhost.local.", IpAddr::from(theirs), 120)?],
+                ..Default::default()
+            };
+            let raw = msg.pack()?;
+            let mut p = Parser::default();
+            p.start(&raw)?;
+            run(
+                &mut p,
+                &responder,
+                &responder.links[0],
+                multicast.local_addr()?,
+                &Arc::new(Mutex::new(vec![])),
+                &Arc::new(Mutex::new(vec![])),
+                false,
+                true,
+            )
+            .await;
+
+            let probes = responder.probes.lock().await;
+            if lost {
+                assert_eq!(probes[0].sent, 0);
+                assert!(probes[0].next_probe > Instant::now() + PROBE_DEFER / 2);
+            } else {
+                assert_eq!(probes[0].sent, 2);
+                assert_eq!(probes[0].next_probe, next_probe);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tie_break() -> Result<(), Error> {
+        use std::cmp::Ordering;
+
+        let a = |ip: [u8; 4]| answer("myhost.local.", IpAddr::from(ip), 120);
+        let aaaa = answer(
+            "myhost.local.",
+            IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 1]),
+            120,
+        )?;
+        assert_eq!(
+            tie_break(&[a([10, 0, 0, 1])?], &[a([10, 0, 0, 2])?]),
+            Ordering::Less
+        );
+        // records are compared in order, whatever order they come in
+        assert_eq!(
+            tie_break(
+                &[aaaa.clone(), a([10, 0, 0, 1])?],
+                &[a([10, 0, 0, 1])?, aaaa.clone()]
+            ),
+            Ordering::Equal
+        );
+        // the type comes before the rdata
+        assert_eq!(
+            tie_break(std::slice::from_ref(&aaaa), &[a([192, 168, 0, 1])?]),
+            Ordering::Greater
+        );
+        // and a set running out first is the earlier
+        assert_eq!(
+            tie_break(&[a([10, 0, 0, 1])?], &[a([10, 0, 0, 1])?, aaaa]),
+            Ordering::Less
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_announcements() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        let ip = IpAddr::from([10, 0, 0, 1]);
+        responder.answer_addresses = AnswerAddresses::Fixed(vec![ip]);
+        responder.announcements.lock().await.push(Announcement {
+            name: "myhost.local.".to_owned(),
+            sent: 0,
+            next_announcement: Instant::now(),
+        });
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for i in 0..ANNOUNCE_COUNT {
+            responder.handle_due_announcements().await;
+            let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected an announcement")?;
+            let msg = Message::try_from(&b[..n])?;
+            assert!(msg.header.response);
+            assert!(msg.questions.is_empty());
+            assert_eq!(msg.answers.len(), 1);
+            assert_eq!(msg.answers[0].header.name.data, "myhost.local.");
+            assert_eq!(msg.answers[0].ip(), Some(ip));
+            assert!(msg.answers[0].header.cache_flush);
+
+            // the next one is a second later
+            responder.handle_due_announcements().await;
+            assert!(
+                timeout(Duration::from_millis(100), multicast.recv_from(&mut b))
+                    .await
+                    .is_err(),
+                "expected no announcement before a second"
+            );
+            let mut announcements = responder.announcements.lock().await;
+            if i + 1 < ANNOUNCE_COUNT {
+                let next = announcements[0].next_announcement;
+                assert!(next > Instant::now() + ANNOUNCE_INTERVAL - Duration::from_millis(200));
+                announcements[0].next_announcement = Instant::now();
+                // as if the last announcement was a second ago
+                responder.links[0].recent_multicasts.lock().await.clear();
+            } else {
+                assert!(announcements.is_empty());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_goodbyes() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut responder = responder(
+            &socket,
+            &["myhost.local.", "other.local."],
+            multicast.local_addr()?,
+        );
+        let ip = IpAddr::from([10, 0, 0, 1]);
+        responder.answer_addresses = AnswerAddresses::Fixed(vec![ip]);
+        responder.records = Arc::new(vec![answer(
+            "printer.local.",
+            IpAddr::from([10, 0, 0, 9]),
+            RESPONSE_TTL,
+        )?]);
+
+        async fn goodbye(multicast: &UdpSocket) -> Result<Vec<String>, Error> {
+            let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+            let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected a goodbye")?;
+            let msg = Message::try_from(&b[..n])?;
+            assert!(msg.header.response);
+            assert!(msg.answers.iter().all(|a| a.header.ttl == 0));
+            Ok(msg
+                .answers
+                .into_iter()
+                .map(|a| a.header.name.data)
+                .collect())
+        }
+
+        // for a removed local name
+        responder
+            .local_names
+            .lock()
+            .await
+            .retain(|l| l != "other.local.");
+        responder
+            .send_goodbyes(&["other.local.".to_owned()], false)
+            .await;
+        assert_eq!(goodbye(&multicast).await?, vec!["other.local."]);
+
+        // and for everything left on shutdown
+        responder.say_goodbye().await;
+        assert_eq!(
+            goodbye(&multicast).await?,
+            vec!["myhost.local.", "printer.local."]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_on_question() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let mut responder = responder(
+            &socket,
+            &["allowed.local.", "denied.local."],
+            multicast.local_addr()?,
+        );
+        responder.on_question_handler = Arc::clone(&server_a.on_question_handler);
+
+        let (seen_tx, mut seen_rx) = mpsc::channel(4);
+        server_a
+            .on_question(Box::new(move |q: Question, addr: SocketAddr| {
+                let seen_tx = seen_tx.clone();
+                Box::pin(async move {
+                    let allowed = q.name.data != "denied.local.";
+                    let _ = seen_tx.send((q.name.data, addr)).await;
+                    allowed
+                })
+            }))
+            .await;
+
+        let questions = vec![
+            Question {
+                name: Name::new("denied.local.")?,
+                typ: DNSType::A,
+                class: DNSCLASS_INET,
+                ..Default::default()
+            },
+            Question {
+                name: Name::new("allowed.local.")?,
+                typ: DNSType::A,
+                class: DNSCLASS_INET,
+                ..Default::default()
+            },
+        ];
+        responder
+            .answer_questions(&responder.links[0], &questions, &[], src)
+            .await;
+
+        assert_eq!(
+            seen_rx.recv().await,
+            Some(("denied.local.".to_owned(), src))
+        );
+        assert_eq!(
+            seen_rx.recv().await,
+            Some(("allowed.local.".to_owned(), src))
+        );
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        assert_eq!(msg.answers.len(), 1);
+        assert_eq!(msg.answers[0].header.name.data, "allowed.local.");
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_on_question_reentrant() -> Result<(), Error> {
+        let server_a = Arc::new(DNSConn::server(loopback(), config())?);
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let mut responder = responder(&socket, &[], multicast.local_addr()?);
+        responder.on_question_handler = Arc::clone(&server_a.on_question_handler);
+        responder.local_names = Arc::clone(&server_a.local_names);
+        server_a.add_local_name("myhost.local").await?;
+
+        // the handler calls back into the conn, replacing itself and
+        // changing the local names
+        let conn = Arc::downgrade(&server_a);
+        server_a
+            .on_question(Box::new(move |_: Question, _: SocketAddr| {
+                let conn = conn.clone();
+                Box::pin(async move {
+                    if let Some(conn) = conn.upgrade() {
+                        let _ = conn.add_local_name("added.local").await;
+                        conn.remove_local_name("myhost.local").await;
+                        conn.on_question(Box::new(|_: Question, _: SocketAddr| {
+                            Box::pin(async { false })
+                        }))
+                        .await;
+                    }
+                    true
+                })
+            }))
+            .await;
+
+        let qs = vec![Question {
+            name: Name::new("added.local.")?,
+            typ: DNSType::A,
+            class: DNSCLASS_INET,
+            ..Default::default()
+        }];
+        timeout(
+            Duration::from_secs(1),
+            responder.answer_questions(&responder.links[0], &qs, &[], src),
+        )
+        .await
+        .expect("expected the handler not to deadlock the conn");
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        assert_eq!(msg.answers[0].header.name.data, "added.local.");
+        assert_eq!(*server_a.local_names.lock().await, vec!["added.local."]);
+
+        // and the handler it set in its place is the one called next
+        timeout(
+            Duration::from_secs(1),
+            responder.answer_questions(&responder.links[0], &qs, &[], src),
+        )
+        .await
+        .expect("expected the handler not to deadlock the conn");
+        assert!(
+            timeout(Duration::from_millis(100), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no answer once the handler was replaced"
+        );
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_static_records() -> Result<(), Error> {
+        let txt = Resource {
+            header: ResourceHeader {
+                ttl: 4500,
+                cache_flush: true,
+                ..Default::default()
+            },
+            body: Some(ResourceData::TXT(TXTResource {
+                txt: vec!["rp=printers/1".to_owned()],
+            })),
+        };
+        let a = Resource {
+            header: ResourceHeader {
+                cache_flush: true,
+                ..Default::default()
+            },
+            body: Some(ResourceData::A(AResource { a: [10, 0, 0, 9] })),
+        };
+        let mut records = HashMap::new();
+        records.insert("printer.local".to_owned(), vec![a, txt]);
+
+        let server_a = DNSConn::server(
+            loopback(),
+            Config {
+                records,
+                ..config()
+            },
+        )?;
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let mut responder = responder(&socket, &[], multicast.local_addr()?);
+        responder.records = Arc::clone(&server_a.records);
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for (typ, expected) in [
+            (DNSType::A, vec![DNSType::A]),
+            (DNSType::ALL, vec![DNSType::A, DNSType::TXT]),
+        ] {
+            // as if the last answer was multicast over a second ago
+            responder.links[0].recent_multicasts.lock().await.clear();
+            let question = Question {
+                name: Name::new("Printer.local.")?,
+                typ,
+                class: DNSCLASS_INET,
+                ..Default::default()
+            };
+            responder
+                .answer_questions(&responder.links[0], &[question], &[], src)
+                .await;
+
+            let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected an answer")?;
+            let msg = Message::try_from(&b[..n])?;
+            let types: Vec<DNSType> = msg.answers.iter().map(|a| a.header.typ).collect();
+            assert_eq!(types, expected);
+            for answer in &msg.answers {
+                assert_eq!(answer.header.name.data, "printer.local.");
+                assert_eq!(answer.header.class, DNSCLASS_INET);
+            }
+            assert_eq!(msg.answers[0].header.ttl, RESPONSE_TTL);
+        }
+
+        let question = Question {
+            name: Name::new("printer.local.")?,
+            typ: DNSType::AAAA,
+            class: DNSCLASS_INET,
+            ..Default::default()
+        };
+        responder
+            .answer_questions(&responder.links[0], &[question], &[], src)
+            .await;
+        assert!(
+            timeout(Duration::from_millis(100), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no answer for a type without records"
+        );
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shared_records_delayed_and_aggregated() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let ptr = |instance: &str| -> Result<Resource, Error> {
+            Ok(Resource {
+                header: ResourceHeader {
+                    name: Name::new("_http._tcp.local.")?,
+                    typ: DNSType::PTR,
+                    class: DNSCLASS_INET,
+                    ttl: RESPONSE_TTL,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::PTR(PTRResource {
+                    ptr: Name::new(instance)?,
+                })),
+            })
+        };
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        responder.records = Arc::new(vec![
+            ptr("a._http._tcp.local.")?,
+            ptr("b._http._tcp.local.")?,
+        ]);
+
+        // unique records are answered at once
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let questions_a = questions(&Name::new("myhost.local.")?, &[DNSType::A]);
+        responder
+            .answer_questions(&responder.links[0], &questions_a, &[], src)
+            .await;
+        timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+
+        // shared ones wait 20-120ms, collecting the answers due meanwhile
+        responder.links[0].recent_multicasts.lock().await.clear();
+        let started = Instant::now();
+        let questions_ptr = questions(&Name::new("_http._tcp.local.")?, &[DNSType::PTR]);
+        responder
+            .answer_questions(&responder.links[0], &questions_ptr, &[], src)
+            .await;
+        let mut qs = questions_ptr;
+        qs.extend(questions_a);
+        responder
+            .answer_questions(&responder.links[0], &qs, &[], src)
+            .await;
+        let deadline = responder.links[0]
+            .delayed
+            .lock()
+            .await
+            .as_ref()
+            .map(|d| d.deadline)
+            .expect("expected delayed answers");
+        assert!(deadline >= started + Duration::from_millis(20));
+        assert!(deadline <= Instant::now() + Duration::from_millis(120));
+        assert!(
+            timeout(Duration::from_millis(10), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no answer before the delay"
+        );
+
+        tokio::time::sleep_until(deadline).await;
+        responder.send_delayed_answers().await;
+        assert!(responder.links[0].delayed.lock().await.is_none());
+
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected the delayed answers")?;
+        let msg = Message::try_from(&b[..n])?;
+        let types: Vec<DNSType> = msg.answers.iter().map(|a| a.header.typ).collect();
+        assert_eq!(types, vec![DNSType::PTR, DNSType::PTR, DNSType::A]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multicast_rate_limit() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = client.local_addr()?;
+        let responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let mut qs = questions(&Name::new("myhost.local.")?, &[DNSType::A]);
+        responder
+            .answer_questions(&responder.links[0], &qs, &[], src)
+            .await;
+        timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+
+        // the same record is not multicast again within a second
+        responder
+            .answer_questions(&responder.links[0], &qs, &[], src)
+            .await;
+        assert!(
+            timeout(Duration::from_millis(100), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no answer within a second"
+        );
+
+        // but is still sent to a querier asking for a unicast answer
+        qs[0].unicast_response = true;
+        responder
+            .answer_questions(&responder.links[0], &qs, &[], src)
+            .await;
+        timeout(Duration::from_secs(1), client.recv_from(&mut b))
+            .await
+            .expect("expected a unicast answer")?;
+
+        qs[0].unicast_response = false;
+        for (_, at) in responder.links[0].recent_multicasts.lock().await.iter_mut() {
+            *at = Instant::now() - MIN_MULTICAST_INTERVAL;
+        }
+        responder
+            .answer_questions(&responder.links[0], &qs, &[], src)
+            .await;
+        timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer after a second")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_subnet_contains() {
+        let subnet = Subnet::new(IpAddr::from([192, 168, 1, 0]), 24);
+        assert!(subnet.contains(IpAddr::from([192, 168, 1, 7])));
+        assert!(!subnet.contains(IpAddr::from([192, 168, 2, 7])));
+        assert!(!subnet.contains("::1".parse().unwrap()));
+
+        assert!(Subnet::new(IpAddr::from([10, 0, 0, 1]), 0).contains(IpAddr::from([8, 8, 8, 8])));
+        let host = Subnet::new(IpAddr::from([10, 0, 0, 1]), 32);
+        assert!(host.contains(IpAddr::from([10, 0, 0, 1])));
+        assert!(!host.contains(IpAddr::from([10, 0, 0, 2])));
+
+        let subnet = Subnet::new("fe80::".parse().unwrap(), 10);
+        assert!(subnet.contains("fe80::1".parse().unwrap()));
+        assert!(!subnet.contains("2001:db8::1".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_source_allowed() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let mut responder = responder(&socket, &[], socket.local_addr()?);
+        assert!(responder.source_allowed(IpAddr::from([203, 0, 113, 1])));
+
+        responder.allowed_sources = vec![Subnet::new(IpAddr::from([192, 168, 1, 0]), 24)];
+        responder.denied_sources = vec![Subnet::new(IpAddr::from([192, 168, 1, 66]), 32)];
+        assert!(responder.source_allowed(IpAddr::from([192, 168, 1, 7])));
+        assert!(!responder.source_allowed(IpAddr::from([192, 168, 1, 66])));
+        assert!(!responder.source_allowed(IpAddr::from([203, 0, 113, 1])));
+
+        responder.allowed_sources.clear();
+        assert!(responder.source_allowed(IpAddr::from([203, 0, 113, 1])));
+        assert!(!responder.source_allowed(IpAddr::from([192, 168, 1, 66])));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_defense_not_rate_limited() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = multicast.local_addr()?;
+        let mut responder = responder(&socket, &["myhost.local."], src);
+        responder.answer_addresses = AnswerAddresses::Fixed(vec![IpAddr::from([10, 0, 0, 1])]);
+        // a shared record, which would otherwise hold the answers back
+        responder.records = Arc::new(vec![Resource {
+            header: ResourceHeader {
+                name: Name::new("myhost.local.")?,
+                typ: DNSType::TXT,
+                class: DNSCLASS_INET,
+                ttl: RESPONSE_TTL,
+                ..Default::default()
+            },
+            body: Some(ResourceData::TXT(TXTResource {
+                txt: vec!["k=v".to_owned()],
+            })),
+        }]);
+
+        // defending a name twice within a second sends both defenses
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for _ in 0..2 {
+            responder.assert_names(&["myhost.local.".to_owned()]).await;
+            timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected a defense")?;
+        }
+
+        // as are the answers to probes for it
+        for _ in 0..2 {
+            let mut msg = Message {
+                questions: questions(&Name::new("myhost.local.")?, &[DNSType::ALL]),
+                authorities: vec![answer("myhost.local.", IpAddr::from([10, 0, 0, 9]), 120)?],
+                ..Default::default()
+            };
+            let raw = msg.pack()?;
+            let mut p = Parser::default();
+            p.start(&raw)?;
+            run(
+                &mut p,
+                &responder,
+                &responder.links[0],
+                src,
+                &Arc::new(Mutex::new(vec![])),
+                &Arc::new(Mutex::new(vec![])),
+                false,
+                true,
+            )
+            .await;
+            timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected an answer to the probe")?;
+        }
+        assert!(responder.links[0].delayed.lock().await.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_multicast_not_rate_limited() -> Result<(), Error> {
+        // an IPv4 socket can't send to the IPv6 group, so every send fails
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        let dst: SocketAddr = "[::1]:5353".parse().unwrap();
+        let responder = responder(&socket, &["myhost.local."], dst);
+
+        let qs = questions(&Name::new("myhost.local.")?, &[DNSType::A]);
+        responder
+            .answer_questions(&responder.links[0], &qs, &[], client.local_addr()?)
+            .await;
+        assert!(responder.links[0].recent_multicasts.lock().await.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_names_case_insensitive() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let mut responder = responder(&socket, &[], multicast.local_addr()?);
+        responder.local_names = Arc::clone(&server_a.local_names);
+        server_a.add_local_name("myhost.local").await?;
+
+        let question = Question {
+            name: Name::new("MyHost.local.")?,
+            typ: DNSType::A,
+            class: DNSCLASS_INET,
+            ..Default::default()
+        };
+        responder
+            .answer_questions(&responder.links[0], &[question], &[], src)
+            .await;
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        assert!(msg.answers[0]
+            .header
+            .name
+            .data
+            .eq_ignore_ascii_case("myhost.local."));
+
+        // the name is already a local name, whatever its case, and keeps
+        // its spelling
+        assert_eq!(
+            server_a.add_local_name("MYHOST.local").await?,
+            "myhost.local"
+        );
+        assert_eq!(server_a.local_names.lock().await.len(), 1);
+        assert!(server_a.probes.lock().await.is_empty());
+        assert!(server_a.remove_local_name("MyHost.local").await);
+        assert!(server_a.local_names.lock().await.is_empty());
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_question_rate_limit() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let mut responder = responder(&socket, &[], socket.local_addr()?);
+        let flooder = IpAddr::from([192, 168, 1, 66]);
+        let other = IpAddr::from([192, 168, 1, 7]);
+        for _ in 0..10 {
+            assert!(responder.question_rate_allowed(flooder, 1).await);
+        }
+
+        responder.max_questions_per_source = 3;
+        assert!(responder.question_rate_allowed(flooder, 2).await);
+        assert!(responder.question_rate_allowed(flooder, 1).await);
+        assert!(!responder.question_rate_allowed(flooder, 1).await);
+        assert!(responder.question_rate_allowed(other, 3).await);
+        // responses carry no questions and are never limited
+        assert!(responder.question_rate_allowed(flooder, 0).await);
+
+        for (_, start) in responder.source_questions.lock().await.values_mut() {
+            *start = Instant::now() - QUESTION_RATE_WINDOW;
+        }
+        assert!(responder.question_rate_allowed(flooder, 1).await);
+
+        assert_eq!(
+            *responder.question_counters.lock().await,
+            QuestionCounters {
+                received: 18,
+                dropped: 1,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_own_packet() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        let local_addr = socket.local_addr()?;
+
+        let qs = questions(&Name::new("otherhost.local.")?, &[DNSType::A]);
+        send_questions(&responder, qs, vec![]).await;
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected a query")?;
+
+        assert!(responder.own_packet(&b[..n], local_addr).await);
+        // the same packet from another host is not ours
+        let remote = SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 5353);
+        assert!(!responder.own_packet(&b[..n], remote).await);
+        // nor is another packet from this one
+        let mut other = b[..n].to_vec();
+        other[0] ^= 1;
+        assert!(!responder.own_packet(&other, local_addr).await);
+
+        for (_, at) in responder.sent.lock().await.iter_mut() {
+            *at = Instant::now() - OWN_PACKET_WINDOW;
+        }
+        assert!(!responder.own_packet(&b[..n], local_addr).await);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_token() -> Result<(), Error> {
+        let token = CancellationToken::new();
+        let server_a = DNSConn::server(
+            loopback(),
+            Config {
+                cancellation_token: Some(token.child_token()),
+                ..config()
+            },
+        )?;
+
+        let query_token = CancellationToken::new();
+        let (res, _) = tokio::join!(
+            server_a.query_with_cancel("invalid-host", query_token.clone()),
+            async { query_token.cancel() }
+        );
+        if let Err(err) = res {
+            assert_eq!(err, *ERR_QUERY_CANCELLED);
+        } else {
+            panic!("expected error, but got ok");
+        }
+        assert!(!server_a.is_closed());
+
+        token.cancel();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(server_a.is_closed());
+        std::net::UdpSocket::bind(server_a.local_addr())?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_querier_and_responder() -> Result<(), Error> {
+        let querier = MdnsQuerier::new(
+            loopback(),
+            Config {
+                local_names: vec!["ignored.local".to_owned()],
+                ..config()
+            },
+        )?;
+        let responder = MdnsResponder::new(
+            loopback(),
+            Config {
+                local_names: vec!["answered.local".to_owned()],
+                probe_local_names: true,
+                answer_addresses: AnswerAddresses::Fixed(vec![IpAddr::from([10, 0, 0, 1])]),
+                ..config()
+            },
+        )?;
+
+        assert!(querier.conn.probes.lock().await.is_empty());
+        assert!(responder.conn.responder_only);
+        let probes: Vec<String> = responder
+            .conn
+            .probes
+            .lock()
+            .await
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+        assert_eq!(probes, vec!["answered.local.".to_owned()]);
+
+        // each is shut down on its own
+        querier.close().await?;
+        assert!(querier.is_closed());
+        assert!(!responder.is_closed());
+        querier.restart().await?;
+        assert!(!querier.is_closed());
+
+        querier.close().await?;
+        responder.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_responder_only() -> Result<(), Error> {
+        let server_a = DNSConn::server(
+            loopback(),
+            Config {
+                responder_only: true,
+                ..config()
+            },
+        )?;
+
+        if let Err(err) = server_a.query("invalid-host").await {
+            assert_eq!(err, *ERR_RESPONDER_ONLY);
+        } else {
+            panic!("expected error, but got ok");
+        }
+        assert!(server_a.browse("invalid-host", DNSType::A).await.is_err());
+        assert!(server_a.queries.lock().await.is_empty());
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_querier_only() -> Result<(), Error> {
+        let mut records = HashMap::new();
+        records.insert(
+            "printer.local".to_owned(),
+            vec![Resource {
+                body: Some(ResourceData::A(AResource { a: [10, 0, 0, 9] })),
+                ..Default::default()
+            }],
+        );
+        let querier = MdnsQuerier::new(
+            loopback(),
+            Config {
+                local_names: vec!["ignored.local".to_owned()],
+                records,
+                ..config()
+            },
+        )?;
+        assert!(querier.conn.querier_only);
+        assert!(querier.conn.local_names.lock().await.is_empty());
+        assert!(querier.conn.records.is_empty());
+
+        if let Err(err) = querier.conn.add_local_name("ignored").await {
+            assert_eq!(err, *ERR_QUERIER_ONLY);
+        } else {
+            panic!("expected error, but got ok");
+        }
+
+        // questions are not answered, even for the local names
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        responder.querier_only = true;
+
+        let mut msg = Message {
+            questions: vec![Question {
+                name: Name::new("myhost.local.")?,
+                typ: DNSType::A,
+                class: DNSCLASS_INET,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+        for src in [client.local_addr()?, multicast.local_addr()?] {
+            let mut p = Parser::default();
+            p.start(&b)?;
+            run(
+                &mut p,
+                &responder,
+                &responder.links[0],
+                src,
+                &Arc::new(Mutex::new(vec![])),
+                &Arc::new(Mutex::new(vec![])),
+                false,
+                false,
+            )
+            .await;
+        }
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        assert!(
+            timeout(Duration::from_millis(100), client.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no legacy unicast answer"
+        );
+        assert!(
+            timeout(Duration::from_millis(100), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no multicast answer"
+        );
+
+        querier.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_one_shot_querier() -> Result<(), Error> {
+        let querier = MdnsQuerier::one_shot(config())?;
+        assert_ne!(querier.local_addr().port(), 5353);
+        assert_ne!(querier.local_addr().port(), 0);
+        assert!(querier.multicast_groups().is_empty());
+
+        // answers sent back to its port resolve its queries
+        let responder = UdpSocket::bind("127.0.0.1:0").await?;
+        let dst = SocketAddr::new(
+            Ipv4Addr::new(127, 0, 0, 1).into(),
+            querier.local_addr().port(),
+        );
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: vec![answer(
+                "one-shot.local.",
+                Ipv4Addr::new(10, 0, 0, 1).into(),
+                RESPONSE_TTL,
+            )?],
+            ..Default::default()
+        };
+        let raw = msg.pack()?;
+        let (res, sent) = tokio::join!(
+            querier.query_with_timeout("one-shot.local", Duration::from_secs(1)),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                responder.send_to(&raw, dst).await
+            }
+        );
+        sent?;
+        assert_eq!(res?.ip, Some(IpAddr::from([10, 0, 0, 1])));
+
+        querier.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_respect_timeout() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let res = timeout(Duration::from_millis(100), server_a.query("invalid-host")).await;
+        assert!(res.is_err(), "server_a.query expects timeout!");
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_with_timeout() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let res = server_a
+            .query_with_timeout("invalid-host", Duration::from_millis(100))
+            .await;
+        if let Err(err) = res {
+            assert_eq!(err, *ERR_QUERY_TIMEOUT);
+        } else {
+            panic!("expected error, but got ok");
+        }
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_dropped_is_deregistered() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let res = timeout(Duration::from_millis(100), server_a.query("invalid-host")).await;
+        assert!(res.is_err(), "server_a.query expects timeout!");
+        assert_eq!(server_a.queries.lock().await.len(), 0);
+
+        // dropping one query leaves the others registered
+        let browse = server_a.browse("other-host", DNSType::A).await?;
+        let res = timeout(Duration::from_millis(100), server_a.query("other-host")).await;
+        assert!(res.is_err(), "server_a.query expects timeout!");
+        {
+            let queries = server_a.queries.lock().await;
+            assert_eq!(queries.len(), 1);
+            assert!(queries[0].cache.is_some());
+        }
+        drop(browse);
+        assert_eq!(server_a.queries.lock().await.len(), 0);
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_all_window() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let res = server_a
+            .query_all("invalid-host", &[DNSType::A], Duration::from_millis(100), 0)
+            .await?;
+        assert!(res.is_empty());
+
+        if let Err(err) = server_a
+            .query_all(
+                "invalid-host",
+                &[DNSType::OPT],
+                Duration::from_millis(100),
+                0,
+            )
+            .await
+        {
+            assert_eq!(err, *ERR_UNSUPPORTED_QUERY_TYPE);
+        } else {
+            panic!("expected error, but got ok");
+        }
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_all_dedups_records() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let ptr = |target: &str| -> Result<Resource, Error> {
+            Ok(Resource {
+                header: ResourceHeader {
+                    name: Name::new("_http._tcp.local.")?,
+                    typ: DNSType::PTR,
+                    class: DNSCLASS_INET,
+                    ttl: RESPONSE_TTL,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::PTR(PTRResource {
+                    ptr: Name::new(target)?,
+                })),
+            })
+        };
+        let answers = vec![
+            ptr("a._http._tcp.local.")?,
+            ptr("b._http._tcp.local.")?,
+            ptr("a._http._tcp.local.")?,
+        ];
+        let src = SocketAddr::new(Ipv4Addr::new(10, 0, 0, 7).into(), 5353);
+
+        let (res, _) = tokio::join!(
+            server_a.query_all("_http._tcp", &[DNSType::PTR], Duration::from_millis(200), 0),
+            async {
+                loop {
+                    let queries = server_a.queries.lock().await;
+                    if let Some(q) = queries.first() {
+                        for answer in &answers {
+                            q.query_result_chans[0]
+                                .send(BrowseEvent::Answer(query_result(answer.clone(), src)))
+                                .await
+                                .unwrap();
+                        }
+                        break;
+                    }
+                    drop(queries);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        );
+        let targets: Vec<String> = res?
+            .iter()
+            .filter_map(|r| r.answer.as_ptr().map(|p| p.ptr.data.clone()))
+            .collect();
+        assert_eq!(targets, vec!["a._http._tcp.local.", "b._http._tcp.local."]);
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_multiple_answers_stays_registered() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+
+        let (query_tx, mut query_rx) = mpsc::channel(2);
+        let queries = Arc::new(Mutex::new(vec![query(
+            Name::new("multi.local.")?,
+            vec![DNSType::A],
+            query_tx,
+            true,
+        )]));
+
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: [[10, 0, 0, 1], [10, 0, 0, 2]]
+                .iter()
+                .map(|a| -> Result<Resource, Error> {
+                    Ok(Resource {
+                        header: ResourceHeader {
+                            name: Name::new("multi.local.")?,
+                            class: DNSCLASS_INET,
+                            ttl: RESPONSE_TTL,
+                            ..Default::default()
+                        },
+                        body: Some(ResourceData::A(AResource { a: *a })),
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder(&socket, &[], src),
+            &Link::new(Arc::clone(&socket), src),
+            src,
+            &queries,
+            &Arc::new(Mutex::new(vec![])),
+            false,
+            false,
+        )
+        .await;
+
+        let first = answer_event(query_rx.try_recv().ok());
+        let second = answer_event(query_rx.try_recv().ok());
+        assert_eq!(first.ip, Some(IpAddr::from([10, 0, 0, 1])));
+        assert_eq!(second.ip, Some(IpAddr::from([10, 0, 0, 2])));
+        assert_eq!(queries.lock().await.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_browse() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let mut browse = server_a.browse("browse-host", DNSType::A).await?;
+
+        let answer = ResourceHeader {
+            name: Name::new("browse-host.")?,
+            typ: DNSType::A,
+            class: DNSCLASS_INET,
+            ttl: RESPONSE_TTL,
+            ..Default::default()
+        };
+        let src = SocketAddr::new(Ipv4Addr::new(10, 0, 0, 7).into(), 5353);
+        {
+            let queries = server_a.queries.lock().await;
+            assert_eq!(queries.len(), 1);
+            assert!(queries[0].multiple_answers);
+            for i in 1..=2u8 {
+                queries[0].query_result_chans[0]
+                    .send(BrowseEvent::Answer(query_result(
+                        Resource {
+                            header: answer.clone(),
+                            body: Some(ResourceData::A(AResource { a: [10, 0, 0, i] })),
+                        },
+                        src,
+                    )))
+                    .await
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(
+            answer_event(browse.next().await).ip,
+            Some(IpAddr::from([10, 0, 0, 1]))
+        );
+        assert_eq!(
+            answer_event(browse.next().await).ip,
+            Some(IpAddr::from([10, 0, 0, 2]))
+        );
+
+        drop(browse);
+        assert!(server_a.queries.lock().await.is_empty());
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_schedule_override() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let slow = QuerySchedule {
+            interval: Duration::from_secs(30),
+            max_interval: Duration::from_secs(600),
+        };
+        let _browse = server_a
+            .browse_scheduled("slow-host", DNSType::A, slow)
+            .await?;
+        let fast = QuerySchedule {
+            interval: Duration::from_millis(100),
+            max_interval: Duration::from_millis(50),
+        };
+        let (res, _) = tokio::join!(
+            timeout(
+                Duration::from_millis(200),
+                server_a.query_scheduled("fast-host", &[DNSType::A], fast),
+            ),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let queries = server_a.queries.lock().await;
+                assert_eq!(queries[0].max_interval, slow.max_interval);
+                // the cap is never below the first interval
+                assert_eq!(queries[1].interval, fast.interval);
+                assert_eq!(queries[1].max_interval, fast.interval);
+            }
+        );
+        assert!(res.is_err(), "server_a.query_scheduled expects timeout!");
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_joined_query_schedule() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let slow = QuerySchedule {
+            interval: Duration::from_secs(30),
+            max_interval: Duration::from_secs(600),
+        };
+        let fast = QuerySchedule {
+            interval: Duration::from_millis(100),
+            max_interval: Duration::from_secs(1),
+        };
+        let (slow_res, fast_res, _) = tokio::join!(
+            timeout(
+                Duration::from_millis(300),
+                server_a.query_scheduled("joined-host", &[DNSType::A], slow),
+            ),
+            timeout(Duration::from_millis(300), async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                server_a
+                    .query_scheduled("joined-host", &[DNSType::A], fast)
+                    .await
+            }),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let queries = server_a.queries.lock().await;
+                assert_eq!(queries.len(), 1);
+                assert_eq!(queries[0].query_result_chans.len(), 2);
+                assert!(queries[0].interval <= fast.interval);
+                assert_eq!(queries[0].max_interval, fast.max_interval);
+                assert!(queries[0].next_query <= Instant::now() + fast.interval);
+            }
+        );
+        // each caller keeps its own timeout
+        assert!(slow_res.is_err());
+        assert!(fast_res.is_err());
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_observe() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+
+        let mut observe = server_a.observe().await?;
+        let dropped = server_a.observe().await?;
+        drop(dropped);
+
+        let record = Resource {
+            header: ResourceHeader {
+                name: Name::new("unqueried.local.")?,
+                class: DNSCLASS_INET,
+                ttl: RESPONSE_TTL,
+                ..Default::default()
+            },
+            body: Some(ResourceData::A(AResource { a: [10, 0, 0, 1] })),
+        };
+        for response in [false, true] {
+            // the answers of a query are only known answers, not observed
+            let mut msg = Message {
+                header: Header {
+                    response,
+                    ..Default::default()
+                },
+                answers: vec![record.clone()],
+                ..Default::default()
+            };
+            let b = msg.pack()?;
+            let mut p = Parser::default();
+            p.start(&b)?;
+            run(
+                &mut p,
+                &responder(&socket, &[], src),
+                &Link::new(Arc::clone(&socket), src),
+                src,
+                &server_a.queries,
+                &server_a.observers,
+                false,
+                false,
+            )
+            .await;
+        }
+
+        let res = timeout(Duration::from_secs(1), observe.next())
+            .await
+            .expect("expected an answer")
+            .expect("expected an answer");
+        assert_eq!(res.answer.header.name.data, "unqueried.local.");
+        assert_eq!(res.ip, Some(IpAddr::from([10, 0, 0, 1])));
+        assert_eq!(res.addr, src);
+        assert!(timeout(Duration::from_millis(100), observe.next())
+            .await
+            .is_err());
+        assert_eq!(server_a.observers.lock().await.len(), 1);
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_due_questions_share_a_packet() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let dst = UdpSocket::bind("127.0.0.1:0").await?;
+
+        let (a_tx, _a_rx) = mpsc::channel(1);
+        let (b_tx, _b_rx) = mpsc::channel(1);
+        let (c_tx, _c_rx) = mpsc::channel(1);
+        let mut later = query(Name::new("later.local.")?, vec![DNSType::A], c_tx, false);
+        later.next_query = Instant::now() + Duration::from_secs(10);
+        let queries = Arc::new(Mutex::new(vec![
+            query(Name::new("a.local.")?, vec![DNSType::A], a_tx, false),
+            query(
+                Name::new("b.local.")?,
+                vec![DNSType::A, DNSType::AAAA],
+                b_tx,
+                false,
+            ),
+            later,
+        ]));
+
+        handle_due_queries(&responder(&socket, &[], dst.local_addr()?), &queries).await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), dst.recv_from(&mut b))
+            .await
+            .expect("expected a query packet")?;
+        let mut p = Parser::default();
+        p.start(&b[..n])?;
+        let names: Vec<_> = p
+            .all_questions()?
+            .iter()
+            .map(|q| (q.name.data.clone(), q.typ))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("a.local.".to_owned(), DNSType::A),
+                ("b.local.".to_owned(), DNSType::A),
+                ("b.local.".to_owned(), DNSType::AAAA),
+            ]
+        );
+
+        // sent queries are rescheduled, the later one is left alone
+        let qs = queries.lock().await;
+        assert!(qs[0].next_query > Instant::now());
+        assert!(qs[2].next_query > qs[0].next_query);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_backoff() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let dst = UdpSocket::bind("127.0.0.1:0").await?;
+
+        let (tx, _rx) = mpsc::channel(1);
+        let mut q = query(Name::new("a.local.")?, vec![DNSType::A], tx, false);
+        q.max_interval = Duration::from_secs(3);
+        let queries = Arc::new(Mutex::new(vec![q]));
+        let responder = responder(&socket, &[], dst.local_addr()?);
+
+        let mut intervals = vec![];
+        for _ in 0..3 {
+            queries.lock().await[0].next_query = Instant::now();
+            handle_due_queries(&responder, &queries).await;
+            intervals.push(queries.lock().await[0].interval);
+        }
+        assert_eq!(
+            intervals,
+            vec![
+                Duration::from_secs(2),
+                Duration::from_secs(3),
+                Duration::from_secs(3)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_max_attempts() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let dst = UdpSocket::bind("127.0.0.1:0").await?;
+
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut q = query(Name::new("a.local.")?, vec![DNSType::A], tx, false);
+        q.attempts = 0;
+        q.max_attempts = 2;
+        let queries = Arc::new(Mutex::new(vec![q]));
+        let responder = responder(&socket, &[], dst.local_addr()?);
+
+        for attempts in 1..=2 {
+            queries.lock().await[0].next_query = Instant::now();
+            handle_due_queries(&responder, &queries).await;
+            assert_eq!(queries.lock().await[0].attempts, attempts);
+        }
+
+        // not sent again, and given up on once due
+        queries.lock().await[0].next_query = Instant::now() + Duration::from_millis(100);
+        handle_due_queries(&responder, &queries).await;
+        assert_eq!(queries.lock().await[0].attempts, 2);
+        queries.lock().await[0].next_query = Instant::now();
+        handle_due_queries(&responder, &queries).await;
+        assert!(queries.lock().await.is_empty());
+        assert!(rx.recv().await.is_none());
+
+        let server_a = DNSConn::server(
+            loopback(),
+            Config {
+                query_interval: Duration::from_millis(50),
+                max_query_attempts: 2,
+                ..config()
+            },
+        )?;
+        let res = timeout(Duration::from_secs(1), server_a.query("invalid-host"))
+            .await
+            .expect("expected the query to give up");
+        if let Err(err) = res {
+            assert_eq!(err, *ERR_QUERY_NOT_FOUND);
+        } else {
+            panic!("expected error, but got ok");
+        }
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_config_query_timeout() -> Result<(), Error> {
+        let server_a = DNSConn::server(
+            loopback(),
+            Config {
+                query_timeout: Duration::from_millis(100),
+                ..config()
+            },
+        )?;
+
+        let res = timeout(Duration::from_secs(1), server_a.query("invalid-host"))
+            .await
+            .expect("expected the query to time out");
+        if let Err(err) = res {
+            assert_eq!(err, *ERR_QUERY_TIMEOUT);
+        } else {
+            panic!("expected error, but got ok");
+        }
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_browse_refresh_and_expiry() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let dst = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+        let responder = responder(&socket, &[], dst.local_addr()?);
+
+        let (query_tx, mut query_rx) = mpsc::channel(4);
+        let mut q = query(Name::new("host.local.")?, vec![DNSType::A], query_tx, true);
+        q.cache = Some(vec![]);
+        q.next_query = Instant::now() + Duration::from_secs(60);
+        let queries = Arc::new(Mutex::new(vec![q]));
+        let observers = Arc::new(Mutex::new(vec![]));
+
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: vec![Resource {
+                header: ResourceHeader {
+                    name: Name::new("host.local.")?,
+                    class: DNSCLASS_INET,
+                    ttl: 100,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::A(AResource { a: [10, 0, 0, 1] })),
+            }],
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+        for _ in 0..2 {
+            let mut p = Parser::default();
+            p.start(&b)?;
+            run(
+                &mut p,
+                &responder,
+                &responder.links[0],
+                src,
+                &queries,
+                &observers,
+                false,
+                false,
+            )
+            .await;
+            answer_event(query_rx.try_recv().ok());
+        }
+
+        {
+            let mut qs = queries.lock().await;
+            let cache = qs[0].cache.as_mut().unwrap();
+            // the same answer twice is cached once
+            assert_eq!(cache.len(), 1);
+            let received = cache[0].expires - Duration::from_secs(100);
+            assert_eq!(cache[0].refreshes.len(), 4);
+            for (r, percent) in cache[0].refreshes.iter().zip(&[80, 85, 90, 95]) {
+                let at = *r - received;
+                assert!(at >= Duration::from_secs(*percent));
+                assert!(at <= Duration::from_secs(*percent + 2));
+            }
+            cache[0].refreshes[0] = Instant::now();
+        }
+
+        // a due refresh queries again without waiting for the schedule
+        handle_due_queries(&responder, &queries).await;
+        let mut buf = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), dst.recv_from(&mut buf))
+            .await
+            .expect("expected a refresh query")?;
+        let mut p = Parser::default();
+        p.start(&buf[..n])?;
+        assert_eq!(p.all_questions()?[0].name.data, "host.local.");
+        // the cached answer goes along as a known answer with its TTL left
+        let known_answers = p.all_answers()?;
+        assert_eq!(known_answers.len(), 1);
+        assert_eq!(known_answers[0].header.name.data, "host.local.");
+        assert!((98..=100).contains(&known_answers[0].header.ttl));
+        {
+            let mut qs = queries.lock().await;
+            let cache = qs[0].cache.as_mut().unwrap();
+            assert_eq!(cache[0].refreshes.len(), 3);
+            // but not once less than half of it is left
+            let now = Instant::now();
+            cache[0].expires = now + Duration::from_secs(49);
+            assert!(cache[0].known_answer(now).is_none());
+        }
+
+        queries.lock().await[0].cache.as_mut().unwrap()[0].expires = Instant::now();
+        handle_due_queries(&responder, &queries).await;
+        match query_rx.try_recv() {
+            Ok(BrowseEvent::Expired(res)) => {
+                assert_eq!(res.ip, Some(IpAddr::from([10, 0, 0, 1])))
+            }
+            event => panic!("expected an expiry, got {:?}", event),
+        }
+        assert!(queries.lock().await[0].cache.as_ref().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unread_browse_does_not_stall() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+        let responder = responder(&socket, &[], src);
+
+        let (query_tx, mut query_rx) = mpsc::channel(1);
+        let q = query(Name::new("host.local.")?, vec![DNSType::A], query_tx, true);
+        let queries = Arc::new(Mutex::new(vec![q]));
+
+        // answers for a browse nobody reads are dropped once its channel is
+        // full, instead of holding up the server
+        for a in 1..=3 {
+            let mut msg = Message {
+                header: Header {
+                    response: true,
+                    authoritative: true,
+                    ..Default::default()
+                },
+                answers: vec![answer("host.local.", IpAddr::from([10, 0, 0, a]), 120)?],
+                ..Default::default()
+            };
+            let b = msg.pack()?;
+            let mut p = Parser::default();
+            p.start(&b)?;
+            timeout(
+                Duration::from_secs(1),
+                run(
+                    &mut p,
+                    &responder,
+                    &responder.links[0],
+                    src,
+                    &queries,
+                    &Arc::new(Mutex::new(vec![])),
+                    false,
+                    false,
+                ),
+            )
+            .await
+            .expect("expected the answer to be handled without waiting");
+        }
+
+        answer_event(query_rx.try_recv().ok());
+        assert!(query_rx.try_recv().is_err());
+        assert_eq!(queries.lock().await.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_queries_coalesce() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let res = timeout(Duration::from_millis(200), async {
+            tokio::join!(
+                server_a.query("same-host"),
+                server_a.query("Same-Host"),
+                async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    let queries = server_a.queries.lock().await;
+                    assert_eq!(queries.len(), 1);
+                    assert_eq!(queries[0].query_result_chans.len(), 2);
+
+                    let answer = query_result(
+                        Resource {
+                            header: ResourceHeader {
+                                name: Name::new("same-host.")?,
+                                typ: DNSType::A,
+                                class: DNSCLASS_INET,
+                                ttl: RESPONSE_TTL,
+                                ..Default::default()
+                            },
+                            body: Some(ResourceData::A(AResource { a: [10, 0, 0, 1] })),
+                        },
+                        SocketAddr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 5353),
+                    );
+                    for chan in &queries[0].query_result_chans {
+                        chan.send(BrowseEvent::Answer(answer.clone()))
+                            .await
+                            .unwrap();
+                    }
+                    Ok::<(), Error>(())
+                }
+            )
+        })
+        .await
+        .expect("expected both queries to be answered");
+
+        assert_eq!(res.0?.ip, Some(IpAddr::from([10, 0, 0, 1])));
+        assert_eq!(res.1?.ip, Some(IpAddr::from([10, 0, 0, 1])));
+        res.2?;
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pending_queries() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let _browse = server_a.browse("browse-host", DNSType::PTR).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let pending = server_a.pending_queries().await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].name.data, "browse-host.");
+        assert_eq!(pending[0].types, vec![DNSType::PTR]);
+        assert_eq!(pending[0].attempts, 1);
+
+        {
+            let mut queries = server_a.queries.lock().await;
+            queries[0].next_query = Instant::now();
+            queries[0].started -= Duration::from_secs(5);
+        }
+        server_a.query_added.notify_one();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let pending = server_a.pending_queries().await;
+        assert_eq!(pending[0].attempts, 2);
+        assert!(pending[0].age >= Duration::from_secs(5));
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resolve() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        if let Err(err) = server_a
+            .resolve("invalid-host", Duration::from_millis(100))
+            .await
+        {
+            assert_eq!(err, *ERR_QUERY_TIMEOUT);
+        } else {
+            panic!("expected error, but got ok");
+        }
+
+        let answer = |typ: DNSType, ip: IpAddr| -> Result<BrowseEvent, Error> {
+            Ok(BrowseEvent::Answer(query_result(
+                Resource {
+                    header: ResourceHeader {
+                        name: Name::new("resolve-host.")?,
+                        typ,
+                        class: DNSCLASS_INET,
+                        ttl: RESPONSE_TTL,
+                        ..Default::default()
+                    },
+                    body: Some(match ip {
+                        IpAddr::V4(ip) => ResourceData::A(AResource { a: ip.octets() }),
+                        IpAddr::V6(ip) => ResourceData::AAAA(AAAAResource { aaaa: ip.octets() }),
+                    }),
+                },
+                SocketAddr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 5353),
+            )))
+        };
+        let v4 = IpAddr::from([10, 0, 0, 1]);
+        let v6 = IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 1]);
+
+        let started = Instant::now();
+        let (ips, sent) = tokio::join!(
+            server_a.resolve("resolve-host", Duration::from_secs(5)),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let queries = server_a.queries.lock().await;
+                assert_eq!(queries[0].types, vec![DNSType::A, DNSType::AAAA]);
+                let chan = &queries[0].query_result_chans[0];
+                for event in [
+                    answer(DNSType::A, v4)?,
+                    answer(DNSType::AAAA, v6)?,
+                    answer(DNSType::A, v4)?,
+                ] {
+                    chan.send(event).await.unwrap();
+                }
+                Ok::<(), Error>(())
+            }
+        );
+        sent?;
+        assert_eq!(ips?, vec![v4, v6]);
+        // the answers settle well before the timeout
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_name() {
+        assert_eq!(
+            reverse_name(IpAddr::from([192, 168, 1, 20])),
+            "20.1.168.192.in-addr.arpa"
+        );
+        assert_eq!(
+            reverse_name(IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x0567])),
+            "7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_addr() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let (name, sent) = tokio::join!(
+            server_a.lookup_addr(IpAddr::from([10, 0, 0, 9]), Duration::from_secs(5)),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let queries = server_a.queries.lock().await;
+                assert_eq!(queries[0].name_with_suffix.data, "9.0.0.10.in-addr.arpa.");
+                assert_eq!(queries[0].types, vec![DNSType::PTR]);
+                queries[0].query_result_chans[0]
+                    .send(BrowseEvent::Answer(query_result(
+                        Resource {
+                            header: ResourceHeader {
+                                name: Name::new("9.0.0.10.in-addr.arpa.")?,
+                                typ: DNSType::PTR,
+                                class: DNSCLASS_INET,
+                                ttl: RESPONSE_TTL,
+                                ..Default::default()
+                            },
+                            body: Some(ResourceData::PTR(PTRResource {
+                                ptr: Name::new("host.local.")?,
+                            })),
+                        },
+                        SocketAddr::new(Ipv4Addr::new(10, 0, 0, 9).into(), 5353),
+                    )))
+                    .await
+                    .unwrap();
+                Ok::<(), Error>(())
+            }
+        );
+        sent?;
+        assert_eq!(name?.data, "host.local.");
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_types_unsupported() -> Result<(), Error> {
+        let server_a = DNSConn::server(loopback(), config())?;
+
+        let res = server_a.query_types("invalid-host", &[DNSType::OPT]).await;
+        if let Err(err) = res {
+            assert_eq!(err, *ERR_UNSUPPORTED_QUERY_TYPE);
+        } else {
+            panic!("expected error, but got ok");
+        }
+
+        server_a.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_follow_cname() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+
+        let (query_tx, mut query_rx) = mpsc::channel(1);
+        let queries = Arc::new(Mutex::new(vec![query(
+            Name::new("alias.local.")?,
+            vec![DNSType::A],
+            query_tx,
+            false,
+        )]));
+
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: vec![
+                Resource {
+                    header: ResourceHeader {
+                        name: Name::new("alias.local.")?,
+                        class: DNSCLASS_INET,
+                        ttl: RESPONSE_TTL,
+                        ..Default::default()
+                    },
+                    body: Some(ResourceData::CNAME(CNAMEResource {
+                        cname: Name::new("target.local.")?,
+                    })),
+                },
+                Resource {
+                    header: ResourceHeader {
+                        name: Name::new("target.local.")?,
+                        class: DNSCLASS_INET,
+                        ttl: RESPONSE_TTL,
+                        ..Default::default()
+                    },
+                    body: Some(ResourceData::A(AResource { a: [10, 0, 0, 1] })),
+                },
+            ],
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder(&socket, &[], src),
+            &Link::new(Arc::clone(&socket), src),
+            src,
+            &queries,
+            &Arc::new(Mutex::new(vec![])),
+            true,
+            false,
+        )
+        .await;
+
+        let res = answer_event(query_rx.try_recv().ok());
+        assert_eq!(res.answer.header.name.data, "target.local.");
+        assert_eq!(res.answer.header.typ, DNSType::A);
+        assert!(queries.lock().await.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_arbitrary_types() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+
+        let (ptr_tx, mut ptr_rx) = mpsc::channel(1);
+        let (any_tx, mut any_rx) = mpsc::channel(1);
+        let queries = Arc::new(Mutex::new(vec![
+            query(
+                Name::new("_http._tcp.local.")?,
+                vec![DNSType::PTR],
+                ptr_tx,
+                false,
+            ),
+            query(
+                Name::new("other.local.")?,
+                vec![DNSType::ALL],
+                any_tx,
+                false,
+            ),
+        ]));
+
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: vec![
+                Resource {
+                    header: ResourceHeader {
+                        name: Name::new("_http._tcp.local.")?,
+                        class: DNSCLASS_INET,
+                        ttl: RESPONSE_TTL,
+                        ..Default::default()
+                    },
+                    body: Some(ResourceData::PTR(PTRResource {
+                        ptr: Name::new("web._http._tcp.local.")?,
+                    })),
+                },
+                Resource {
+                    header: ResourceHeader {
+                        name: Name::new("other.local.")?,
+                        class: DNSCLASS_INET,
+                        ttl: RESPONSE_TTL,
+                        ..Default::default()
+                    },
+                    body: Some(ResourceData::TXT(TXTResource {
+                        txt: vec!["k=v".to_owned()],
+                    })),
+                },
+            ],
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder(&socket, &[], src),
+            &Link::new(Arc::clone(&socket), src),
+            src,
+            &queries,
+            &Arc::new(Mutex::new(vec![])),
+            false,
+            false,
+        )
+        .await;
+
+        let res = answer_event(ptr_rx.try_recv().ok());
+        assert_eq!(res.answer.header.typ, DNSType::PTR);
+        assert_eq!(
+            res.answer.as_ptr().map(|r| r.ptr.data.as_str()),
+            Some("web._http._tcp.local.")
+        );
+        assert_eq!(res.ip, None);
+        assert_eq!(res.expires(), res.received + res.ttl);
+        let res = answer_event(any_rx.try_recv().ok());
+        assert_eq!(res.answer.header.typ, DNSType::TXT);
+        assert!(queries.lock().await.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_answer_in_additionals() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+
+        let (query_tx, mut query_rx) = mpsc::channel(1);
+        let queries = Arc::new(Mutex::new(vec![query(
+            Name::new("host.local.")?,
+            vec![DNSType::A],
+            query_tx,
+            false,
+        )]));
+
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: vec![Resource {
+                header: ResourceHeader {
+                    name: Name::new("_http._tcp.local.")?,
+                    class: DNSCLASS_INET,
+                    ttl: RESPONSE_TTL,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::SRV(SRVResource {
+                    priority: 0,
+                    weight: 0,
+                    port: 80,
+                    target: Name::new("host.local.")?,
+                })),
+            }],
+            additionals: vec![Resource {
+                header: ResourceHeader {
+                    name: Name::new("host.local.")?,
+                    class: DNSCLASS_INET,
+                    ttl: RESPONSE_TTL,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::A(AResource { a: [10, 0, 0, 2] })),
+            }],
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder(&socket, &[], src),
+            &Link::new(Arc::clone(&socket), src),
+            src,
+            &queries,
+            &Arc::new(Mutex::new(vec![])),
+            false,
+            false,
+        )
+        .await;
+
+        let res = answer_event(query_rx.try_recv().ok());
+        assert_eq!(res.answer.header.name.data, "host.local.");
+        assert_eq!(res.answer.header.typ, DNSType::A);
+        assert_eq!(res.ip, Some(IpAddr::from([10, 0, 0, 2])));
+        assert_eq!(res.ttl, Duration::from_secs(RESPONSE_TTL.into()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_known_answers_do_not_resolve_queries() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+
+        let (query_tx, mut query_rx) = mpsc::channel(1);
+        let queries = Arc::new(Mutex::new(vec![query(
+            Name::new("host.local.")?,
+            vec![DNSType::A],
+            query_tx,
+            false,
+        )]));
+        let (observer_tx, mut observer_rx) = mpsc::channel(1);
+        let observers = Arc::new(Mutex::new(vec![observer_tx]));
+
+        // Another querier asking for the same name, with the address it
+        // already knows.
+        let mut msg = Message {
+            questions: vec![Question {
+                name: Name::new("host.local.")?,
+                typ: DNSType::A,
+                class: DNSCLASS_INET,
+                ..Default::default()
+            }],
+            answers: vec![Resource {
+                header: ResourceHeader {
+                    name: Name::new("host.local.")?,
+                    class: DNSCLASS_INET,
+                    ttl: RESPONSE_TTL,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::A(AResource { a: [10, 0, 0, 2] })),
+            }],
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder(&socket, &[], src),
+            &Link::new(Arc::clone(&socket), src),
+            src,
+            &queries,
+            &observers,
+            false,
+            false,
+        )
+        .await;
+
+        assert!(query_rx.try_recv().is_err());
+        assert!(observer_rx.try_recv().is_err());
+        assert_eq!(queries.lock().await.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_answer_case_insensitive() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+
+        let (query_tx, mut query_rx) = mpsc::channel(1);
+        let queries = Arc::new(Mutex::new(vec![query(
+            Name::new("myhost.local.")?,
+            vec![DNSType::A],
+            query_tx,
+            false,
+        )]));
+
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers: vec![Resource {
+                header: ResourceHeader {
+                    name: Name::new("MyHost.local.")?,
+                    class: DNSCLASS_INET,
+                    ttl: RESPONSE_TTL,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::A(AResource { a: [10, 0, 0, 3] })),
+            }],
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder(&socket, &[], src),
+            &Link::new(Arc::clone(&socket), src),
+            src,
+            &queries,
+            &Arc::new(Mutex::new(vec![])),
+            false,
+            false,
+        )
+        .await;
+
+        let res = answer_event(query_rx.try_recv().ok());
+        assert_eq!(res.answer.header.name.data, "MyHost.local.");
+        assert!(queries.lock().await.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_answer_past_third_record() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let src = socket.local_addr()?;
+
+        let (query_tx, mut query_rx) = mpsc::channel(1);
+        let queries = Arc::new(Mutex::new(vec![query(
+            Name::new("host5.local.")?,
+            vec![DNSType::A],
+            query_tx,
+            false,
+        )]));
+
+        let mut answers = vec![];
+        for i in 1..=5u8 {
+            answers.push(Resource {
+                header: ResourceHeader {
+                    name: Name::new(&format!("host{}.local.", i))?,
+                    class: DNSCLASS_INET,
+                    ttl: RESPONSE_TTL,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::A(AResource { a: [10, 0, 0, i] })),
+            });
+        }
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers,
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder(&socket, &[], src),
+            &Link::new(Arc::clone(&socket), src),
+            src,
+            &queries,
+            &Arc::new(Mutex::new(vec![])),
+            false,
+            false,
+        )
+        .await;
+
+        let res = answer_event(query_rx.try_recv().ok());
+        assert_eq!(res.answer.header.name.data, "host5.local.");
+        assert_eq!(res.ip, Some(IpAddr::from([10, 0, 0, 5])));
+        assert!(queries.lock().await.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_truncated_query_waits_for_known_answers() -> Result<(), Error> {
+        let server = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+
+        let responder = responder(&server, &["myhost.local."], multicast.local_addr()?);
+        let (packets_tx, packets_rx) = mpsc::channel(recv::PACKET_CHANNEL_SIZE);
+        let _readers = recv::Readers::spawn(&responder.links, &packets_tx);
+        let (close_tx, close_rx) = mpsc::channel(1);
+        tokio::spawn(DNSConn::start(
+            close_rx,
+            packets_rx,
+            CancellationToken::new(),
+            Arc::new(atomic::AtomicBool::new(false)),
+            responder,
+            Arc::new(Mutex::new(vec![])),
+            Arc::new(Notify::new()),
+            Arc::new(Mutex::new(vec![])),
+            false,
+            false,
+        ));
+
+        let known_answer = |i: u8| -> Result<Resource, Error> {
+            Ok(Resource {
+                header: ResourceHeader {
+                    name: Name::new(&format!("other{}.local.", i))?,
+                    class: DNSCLASS_INET,
+                    ttl: RESPONSE_TTL,
+                    ..Default::default()
+                },
+                body: Some(ResourceData::A(AResource { a: [10, 0, 0, i] })),
+            })
+        };
+        let mut msg = Message {
+            questions: vec![Question {
+                name: Name::new("myhost.local.")?,
+                typ: DNSType::A,
+                class: DNSCLASS_INET,
+                ..Default::default()
+            }],
+            answers: (0..40).map(known_answer).collect::<Result<_, _>>()?,
+            ..Default::default()
+        };
+        let packets = msg.pack_split(512)?;
+        assert!(packets.len() > 1);
+
+        let server_addr = server.local_addr()?;
+        client.send_to(&packets[0], server_addr).await?;
+
+        // The responder holds back its answer while more known answers may
+        // follow.
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        assert!(
+            timeout(Duration::from_millis(300), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "answered a truncated query right away"
+        );
+
+        for packet in &packets[1..] {
+            client.send_to(packet, server_addr).await?;
+        }
+        let (n, _) = timeout(Duration::from_secs(2), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let got = Message::try_from(&b[..n])?;
+        assert_eq!(got.answers[0].header.name.data, "myhost.local.");
+
+        close_tx.send(()).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recv_error_keeps_serving() -> Result<(), Error> {
+        let server = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let responder = responder(&server, &["myhost.local."], multicast.local_addr()?);
+        let link = Arc::clone(&responder.links[0]);
+
+        let (packets_tx, packets_rx) = mpsc::channel(recv::PACKET_CHANNEL_SIZE);
+        let (close_tx, close_rx) = mpsc::channel(1);
+        let closed = Arc::new(atomic::AtomicBool::new(false));
+        tokio::spawn(DNSConn::start(
+            close_rx,
+            packets_rx,
+            CancellationToken::new(),
+            Arc::clone(&closed),
+            responder,
+            Arc::new(Mutex::new(vec![])),
+            Arc::new(Notify::new()),
+            Arc::new(Mutex::new(vec![])),
+            false,
+            false,
+        ));
+
+        // a reader failing, whether for a datagram or for good, leaves the
+        // server answering on the other links
+        for err in [
+            std::io::ErrorKind::ConnectionReset,
+            std::io::ErrorKind::PermissionDenied,
+        ] {
+            assert!(packets_tx.send(Err(err.into())).await.is_ok());
+        }
+        let mut msg = Message {
+            questions: questions(&Name::new("myhost.local.")?, &[DNSType::A]),
+            ..Default::default()
+        };
+        let packet = recv::Packet {
+            data: msg.pack()?,
+            src: multicast.local_addr()?,
+            ttl: None,
+            interface: None,
+            dst: None,
+            link,
+        };
+        assert!(packets_tx.send(Ok(packet)).await.is_ok());
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        assert_eq!(msg.answers[0].header.name.data, "myhost.local.");
+        assert!(!closed.load(atomic::Ordering::SeqCst));
+
+        close_tx.send(()).await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transient_recv_errors() {
+        for kind in [
+            std::io::ErrorKind::Interrupted,
+            std::io::ErrorKind::WouldBlock,
+            std::io::ErrorKind::ConnectionReset,
+        ] {
+            assert!(recv::transient(&kind.into()));
+        }
+        #[cfg(unix)]
+        assert!(recv::transient(&std::io::Error::from_raw_os_error(
+            libc::ENOBUFS
+        )));
+        assert!(!recv::transient(&std::io::ErrorKind::NotConnected.into()));
+    }
+
+    #[tokio::test]
+    async fn test_answers_split_at_max_message_size() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let local_names: Vec<String> = (0..20).map(|i| format!("myhost{}.local.", i)).collect();
+        let local_names: Vec<&str> = local_names.iter().map(|n| n.as_str()).collect();
+        let mut responder = responder(&socket, &local_names, multicast.local_addr()?);
+        responder.max_message_size = 128;
+
+        let questions = local_names
+            .iter()
+            .map(|n| {
+                Ok(Question {
+                    name: Name::new(n)?,
+                    typ: DNSType::A,
+                    class: DNSCLASS_INET,
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        responder
+            .answer_questions(&responder.links[0], &questions, &[], src)
+            .await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let mut names = vec![];
+        while names.len() < local_names.len() {
+            let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected more answers")?;
+            assert!(n <= 128, "message of {} bytes", n);
+            let msg = Message::try_from(&b[..n])?;
+            assert!(msg.header.response);
+            assert!(!msg.header.truncated);
+            names.extend(msg.answers.into_iter().map(|a| a.header.name.data));
+        }
+        assert_eq!(names, local_names);
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_answers_reuse_send_buf() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        responder.send_buf = Mutex::new(Vec::with_capacity(DEFAULT_MAX_MESSAGE_SIZE));
+        let ptr = responder.send_buf.lock().await.as_ptr();
+
+        let questions = [Question {
+            name: Name::new("myhost.local.")?,
+            typ: DNSType::A,
+            class: DNSCLASS_INET,
+            ..Default::default()
+        }];
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for _ in 0..3 {
+            responder.links[0].recent_multicasts.lock().await.clear();
+            responder
+                .answer_questions(&responder.links[0], &questions, &[], src)
+                .await;
+            let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected an answer")?;
+            let msg = Message::try_from(&b[..n])?;
+            assert_eq!(msg.answers.len(), 1);
+        }
+        assert_eq!(
+            responder.send_buf.lock().await.as_ptr(),
+            ptr,
+            "send buffer was reallocated"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_answer_addresses_by_family() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let v4 = IpAddr::from([192, 168, 1, 20]);
+        let v6 = IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 0x20]);
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        responder.answer_addresses = AnswerAddresses::Fixed(vec![v4, v6]);
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for (types, expected) in [
+            (vec![DNSType::A], vec![v4]),
+            (vec![DNSType::AAAA], vec![v6]),
+            (vec![DNSType::A, DNSType::AAAA], vec![v4, v6]),
+            (vec![DNSType::ALL], vec![v4, v6]),
+        ] {
+            // as if the last answer was multicast over a second ago
+            responder.links[0].recent_multicasts.lock().await.clear();
+            let questions = questions(&Name::new("myhost.local.")?, &types);
+            responder
+                .answer_questions(&responder.links[0], &questions, &[], src)
+                .await;
+
+            let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+                .await
+                .expect("expected an answer")?;
+            let msg = Message::try_from(&b[..n])?;
+            let ips: Vec<IpAddr> = msg.answers.iter().filter_map(|a| a.ip()).collect();
+            assert_eq!(ips, expected);
+        }
+
+        // the querier's address is only ever an IPv4 one
+        responder.answer_addresses = AnswerAddresses::Querier;
+        let questions = questions(&Name::new("myhost.local.")?, &[DNSType::AAAA]);
+        responder
+            .answer_questions(&responder.links[0], &questions, &[], src)
+            .await;
+        assert!(
+            timeout(Duration::from_millis(100), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no AAAA answer"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_subnet() {
+        let mask = IpAddr::from([255, 255, 255, 0]);
+        let ip = IpAddr::from([192, 168, 1, 20]);
+        assert!(same_subnet(ip, mask, IpAddr::from([192, 168, 1, 7])));
+        assert!(!same_subnet(ip, mask, IpAddr::from([192, 168, 2, 7])));
+        assert!(!same_subnet(
+            ip,
+            mask,
+            IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 7])
+        ));
+
+        let mask = IpAddr::from([0xffff, 0xffff, 0xffff, 0xffff, 0, 0, 0, 0]);
+        let ip = IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 0x20]);
+        assert!(same_subnet(
+            ip,
+            mask,
+            IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 7])
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_answer_legacy_unicast_query() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = client.local_addr()?;
+        let responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+
+        let mut msg = Message {
+            header: Header {
+                id: 0x1234,
+                ..Default::default()
+            },
+            questions: questions(&Name::new("myhost.local.")?, &[DNSType::A]),
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder,
+            &responder.links[0],
+            src,
+            &Arc::new(Mutex::new(vec![])),
+            &Arc::new(Mutex::new(vec![])),
+            false,
+            true,
+        )
+        .await;
+
+        // a query from a port other than the mDNS port is answered directly
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), client.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        assert_eq!(msg.header.id, 0x1234);
+        assert!(msg.header.response);
+        assert_eq!(msg.questions.len(), 1);
+        assert_eq!(msg.questions[0].name.data, "myhost.local.");
+        assert_eq!(msg.answers.len(), 1);
+        assert_eq!(msg.answers[0].header.ttl, LEGACY_UNICAST_TTL);
+        assert!(!msg.answers[0].header.cache_flush);
+        assert!(
+            timeout(Duration::from_millis(100), multicast.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no multicast answer"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_response_questions_ignored() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = client.local_addr()?;
+        let responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+
+        // a response echoing questions, from a port other than the mDNS
+        // port, is not answered
+        let mut msg = Message {
+            header: Header {
+                id: 0x1234,
+                response: true,
+                ..Default::default()
+            },
+            questions: questions(&Name::new("myhost.local.")?, &[DNSType::A]),
+            ..Default::default()
+        };
+        let b = msg.pack()?;
+        let mut p = Parser::default();
+        p.start(&b)?;
+        run(
+            &mut p,
+            &responder,
+            &responder.links[0],
+            src,
+            &Arc::new(Mutex::new(vec![])),
+            &Arc::new(Mutex::new(vec![])),
+            false,
+            true,
+        )
+        .await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        assert!(
+            timeout(Duration::from_millis(100), client.recv_from(&mut b))
+                .await
+                .is_err(),
+            "expected no answer"
+        );
+
+        Ok(())
+    }
+
+    // test_group_port_queries queries a server from the port of the tests'
+    // group, as a full mDNS querier would, so that it multicasts its answers,
+    // or sends them straight back for QU questions, instead of answering
+    // the query as a legacy unicast one.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_group_port_queries() -> Result<(), Error> {
+        let server = DNSConn::server(
+            loopback(),
+            Config {
+                local_names: vec!["group-port.local".to_owned()],
+                ..config()
+            },
+        )?;
+        let group = Ipv4Addr::new(224, 0, 0, 251);
+        let querier = socket2::Socket::new(
+            socket2::Domain::IPV4,
+            socket2::Type::DGRAM,
+            Some(socket2::Protocol::UDP),
+        )?;
+        querier.set_reuse_address(true)?;
+        querier.set_reuse_port(true)?;
+        querier.bind(&SockAddr::from(SocketAddr::new(
+            Ipv4Addr::UNSPECIFIED.into(),
+            5399,
+        )))?;
+        // The answers multicast from the server's loopback address go out
+        // of the loopback interface.
+        querier.join_multicast_v4(&group, &Ipv4Addr::LOCALHOST)?;
+        querier.set_nonblocking(true)?;
+        let querier = UdpSocket::from_std(querier.into())?;
+        recv::enable_pktinfo(&querier)?;
+
+        for unicast_response in [false, true] {
+            let mut qs = questions(&Name::new("group-port.local.")?, &[DNSType::A]);
+            qs[0].unicast_response = unicast_response;
+            let mut msg = Message {
+                questions: qs,
+                ..Default::default()
+            };
+            querier.send_to(&msg.pack()?, server.local_addr()).await?;
+
+            // the group may carry the queries of other tests too
+            let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+            let (msg, dst) = loop {
+                let (n, _, ancillary) =
+                    timeout(Duration::from_secs(1), recv::recv_from(&querier, &mut b))
+                        .await
+                        .expect("expected an answer")?;
+                let msg = Message::try_from(&b[..n])?;
+                if msg.header.response
+                    && msg
+                        .answers
+                        .iter()
+                        .any(|a| a.header.name.data == "group-port.local.")
+                {
+                    break (msg, ancillary.dst);
+                }
+            };
+            // not a legacy unicast answer, which repeats the question
+            assert!(msg.questions.is_empty());
+            let expected: IpAddr = if unicast_response {
+                Ipv4Addr::LOCALHOST.into()
+            } else {
+                group.into()
+            };
+            assert_eq!(dst, Some(expected));
+        }
+
+        server.close().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_answer_qu_questions_unicast() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = client.local_addr()?;
+        let responder = responder(
+            &socket,
+            &["qu.local.", "qm.local."],
+            multicast.local_addr()?,
+        );
+
+        let mut qs = questions(&Name::new("qu.local.")?, &[DNSType::A]);
+        qs[0].unicast_response = true;
+        qs.extend(questions(&Name::new("qm.local.")?, &[DNSType::A]));
+        responder
+            .answer_questions(&responder.links[0], &qs, &[], src)
+            .await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for (conn, name) in [(&client, "qu.local."), (&multicast, "qm.local.")] {
+            let (n, _) = timeout(Duration::from_secs(1), conn.recv_from(&mut b))
+                .await
+                .expect("expected an answer")?;
+            let msg = Message::try_from(&b[..n])?;
+            assert_eq!(msg.answers.len(), 1);
+            assert_eq!(msg.answers[0].header.name.data, name);
+            assert!(msg.answers[0].header.cache_flush);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ipv6() -> Result<(), Error> {
+        // the group of the tests' Config, over IPv6
+        let dst_addr: SocketAddr = "[ff02::fb]:5399".parse()?;
+        match DNSConn::server(
+            SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+            Config {
+                multicast_addr: Some(dst_addr),
+                ..Default::default()
+            },
+        ) {
+            Ok(server_a) => {
+                assert_eq!(server_a.addrs.lock().unwrap()[0].1, dst_addr);
+                for (group, _) in server_a.multicast_groups() {
+                    assert_eq!(group, dst_addr.ip());
+                }
+                server_a.close().await?;
+            }
+            // hosts without IPv6 interfaces have no group to join
+            Err(err) => assert_eq!(err, *ERR_JOINING_MULTICAST_GROUP),
+        }
+
+        let res = DNSConn::server(
+            SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+            Config {
+                multicast_addr: Some(DEFAULT_DEST_ADDR.parse()?),
+                ..Default::default()
+            },
+        );
+        if let Err(err) = res {
+            assert_eq!(err, *ERR_INVALID_MULTICAST_ADDR);
+        } else {
+            panic!("expected error, but got ok");
+        }
+
+        // AAAA questions over IPv6 are answered with the querier's address
+        let socket = Arc::new(UdpSocket::bind("[::1]:0").await?);
+        let multicast = UdpSocket::bind("[::1]:0").await?;
+        let src = multicast.local_addr()?;
+        let responder = responder(&socket, &["myhost.local."], src);
+        let qs = questions(&Name::new("myhost.local.")?, &[DNSType::AAAA]);
+        responder
+            .answer_questions(&responder.links[0], &qs, &[], src)
+            .await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        assert_eq!(msg.answers.len(), 1);
+        match &msg.answers[0].body {
+            Some(ResourceData::AAAA(aaaa)) => assert_eq!(IpAddr::from(aaaa.aaaa), src.ip()),
+            body => panic!("expected an AAAA record, got {:?}", body),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_socket_addrs() -> Result<(), Error> {
+        let v4: SocketAddr = "0.0.0.0:5353".parse()?;
+        let v6: SocketAddr = "[::]:5353".parse()?;
+        let group_v4: SocketAddr = DEFAULT_DEST_ADDR.parse()?;
+        let group_v6: SocketAddr = DEFAULT_DEST_ADDR_V6.parse()?;
+
+        assert_eq!(socket_addrs(v4, None, None)?, vec![(v4, group_v4)]);
+        assert_eq!(socket_addrs(v6, None, None)?, vec![(v6, group_v6)]);
+        assert_eq!(
+            socket_addrs(v4, Some(IpVersion::V6), None)?,
+            vec![(v6, group_v6)]
+        );
+        assert_eq!(
+            socket_addrs(v6, Some(IpVersion::Dual), None)?,
+            vec![(v6, group_v6), (v4, group_v4)]
+        );
+
+        // the multicast address sets the port of the other version's group
+        let v4: SocketAddr = "127.0.0.1:5354".parse()?;
+        let group: SocketAddr = "224.0.0.252:5354".parse()?;
+        assert_eq!(
+            socket_addrs(v4, Some(IpVersion::Dual), Some(group))?,
+            vec![
+                (v4, group),
+                ("[::]:5354".parse()?, "[ff02::fb]:5354".parse()?)
+            ]
+        );
+
+        for (ip_version, multicast_addr) in [
+            (Some(IpVersion::V6), group),
+            (None, "10.0.0.1:5353".parse()?),
+            (Some(IpVersion::Dual), "[2001:db8::1]:5353".parse()?),
+        ] {
+            match socket_addrs(v4, ip_version, Some(multicast_addr)) {
+                Err(err) => assert_eq!(err, *ERR_INVALID_MULTICAST_ADDR),
+                Ok(addrs) => panic!("expected error, but got {:?}", addrs),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack() -> Result<(), Error> {
+        let server = DNSConn::new(
+            "127.0.0.1:0".parse()?,
+            Config {
+                ip_version: Some(IpVersion::Dual),
+                responder_only: true,
+                ..Default::default()
+            },
+            false,
+        )?;
+        let addrs = server.local_addrs();
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(addrs[0], server.local_addr());
+        assert!(addrs[0].is_ipv4());
+        assert!(addrs[1].is_ipv6());
+        server.add_local_name("dual.local").await?;
+
+        // questions over either version of IP are answered on its socket
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for (client, server_addr, typ) in [
+            ("127.0.0.1:0", addrs[0], DNSType::A),
+            (
+                "[::1]:0",
+                SocketAddr::new(Ipv6Addr::LOCALHOST.into(), addrs[1].port()),
+                DNSType::AAAA,
+            ),
+        ] {
+            let client = UdpSocket::bind(client).await?;
+            let raw = Message {
+                questions: questions(&Name::new("dual.local.")?, &[typ]),
+                ..Default::default()
+            }
+            .pack()?;
+            client.send_to(&raw, server_addr).await?;
+
+            let (n, src) = timeout(Duration::from_secs(1), client.recv_from(&mut b))
+                .await
+                .expect("expected an answer")?;
+            assert_eq!(src.port(), server_addr.port());
+            let msg = Message::try_from(&b[..n])?;
+            assert_eq!(msg.answers.len(), 1);
+            assert_eq!(msg.answers[0].header.typ, typ);
+        }
+
+        server.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_socket_per_interface() -> Result<(), Error> {
+        for interface in link_interfaces(false)? {
+            assert!(interface.addrs.iter().any(|ip| ip.is_ipv4()));
+        }
+
+        // a link dedicated to an interface answers with its addresses
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let mut responder = responder(&socket, &["myhost.local."], socket.local_addr()?);
+        let v4 = IpAddr::from([192, 168, 7, 2]);
+        let v6 = IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 7]);
+        let mut link = Link::new(Arc::clone(&socket), responder.links[0].dst_addr);
+        link.interface = Some(LinkInterface {
+            name: "eth7".to_owned(),
+            index: 7,
+            addrs: vec![v4, v6],
+        });
+        let src: SocketAddr = "192.168.7.9:5353".parse()?;
+
+        responder.answer_addresses = AnswerAddresses::FacingQuerier;
+        assert_eq!(responder.addresses(&link, src).await, vec![v4]);
+        responder.answer_addresses = AnswerAddresses::Interfaces;
+        assert_eq!(responder.addresses(&link, src).await, vec![v4, v6]);
+        responder.answer_addresses = AnswerAddresses::Querier;
+        assert_eq!(responder.addresses(&link, src).await, vec![src.ip()]);
+
+        // a server with a socket per interface joins each interface once
+        let server = match DNSConn::server(
+            "0.0.0.0:0".parse()?,
+            Config {
+                socket_per_interface: true,
+                responder_only: true,
+                ..config()
+            },
+        ) {
+            Ok(server) => server,
+            // no interface to join on
+            Err(err) if err == *ERR_JOINING_MULTICAST_GROUP => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let groups = server.multicast_groups();
+        for (i, (group, _)) in groups.iter().enumerate() {
+            assert_eq!(*group, IpAddr::from([224, 0, 0, 251]));
+            assert!(!groups[..i].contains(&groups[i]));
+        }
+        server.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receiving_link() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let responder = responder(&socket, &["myhost.local."], socket.local_addr()?);
+        let shared = &responder.links[0];
+
+        // without a reported interface, or an interface we don't know, the
+        // packet is answered on the link it was received on
+        assert!(Arc::ptr_eq(
+            &responder.receiving_link(shared, None).await,
+            shared
+        ));
+        assert!(Arc::ptr_eq(
+            &responder.receiving_link(shared, Some(u32::MAX)).await,
+            shared
+        ));
+
+        let interface = match all_link_interfaces()?.into_iter().find(|i| i.index != 0) {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        let link = responder
+            .receiving_link(shared, Some(interface.index))
+            .await;
+        assert!(!Arc::ptr_eq(&link, shared));
+        assert!(Arc::ptr_eq(&link.socket, &shared.socket));
+        assert_eq!(link.interface.as_ref().unwrap().name, interface.name);
+        assert!(Arc::ptr_eq(
+            &responder
+                .receiving_link(shared, Some(interface.index))
+                .await,
+            &link
+        ));
+        assert_eq!(responder.all_links().await.len(), 2);
+
+        // a link on an interface already answers for it
+        assert!(Arc::ptr_eq(
+            &responder.receiving_link(&link, Some(u32::MAX)).await,
+            &link
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multicast_on_interface_link() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        let mut link = Link::new(Arc::clone(&socket), multicast.local_addr()?);
+        link.interface = Some(LinkInterface {
+            name: "lo".to_owned(),
+            index: interface_index("lo"),
+            addrs: vec![IpAddr::from([127, 0, 0, 1])],
+        });
+        let link = Arc::new(link);
+        responder
+            .interface_links
+            .lock()
+            .await
+            .push(Arc::clone(&link));
+
+        responder.multicast_on(&link, b"answer").await?;
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected the packet")?;
+        assert_eq!(&b[..n], b"answer");
+
+        // the shared socket goes back to sending out of any interface
+        assert_eq!(
+            socket2::SockRef::from(socket.as_ref()).multicast_if_v4()?,
+            Ipv4Addr::UNSPECIFIED
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_interface_filter() -> Result<(), Error> {
+        assert!(InterfaceFilter::default().allows("eth0"));
+
+        let filter = InterfaceFilter {
+            allowed: vec![InterfaceId::Name("eth0".to_owned())],
+            ..Default::default()
+        };
+        assert!(filter.allows("eth0"));
+        assert!(!filter.allows("docker0"));
+
+        let filter = InterfaceFilter {
+            denied: vec![InterfaceId::Name("docker0".to_owned())],
+            ..Default::default()
+        };
+        assert!(filter.allows("eth0"));
+        assert!(!filter.allows("docker0"));
+
+        // a device bound to is the only interface
+        let filter = InterfaceFilter {
+            device: Some("eth0".to_owned()),
+            ..Default::default()
+        };
+        assert!(filter.allows("eth0"));
+        assert!(!filter.allows("eth1"));
+
+        assert!(InterfaceId::Index(3).matches("eth0", 3));
+        assert!(!InterfaceId::Index(3).matches("eth0", 4));
+
+        // with every interface denied, there is none to join the group on
+        let result = DNSConn::server(
+            "0.0.0.0:0".parse()?,
+            Config {
+                allowed_interfaces: vec![InterfaceId::Name("no-such-interface".to_owned())],
+                ..Default::default()
+            },
+        );
+        assert_eq!(result.err(), Some(ERR_JOINING_MULTICAST_GROUP.to_owned()));
+
+        // nor are any of their addresses answered with
+        let ips = interface_addresses(
+            Ipv4Addr::UNSPECIFIED.into(),
+            &InterfaceFilter {
+                denied: all_link_interfaces()?
+                    .into_iter()
+                    .map(|i| InterfaceId::Name(i.name))
+                    .collect(),
+                ..Default::default()
+            },
+        )?;
+        assert!(ips.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bind_device() -> Result<(), Error> {
+        // there is no such device to bind to, nor any device at all off Linux
+        let result = DNSConn::server(
+            "0.0.0.0:0".parse()?,
+            Config {
+                bind_device: Some("no-such-device".to_owned()),
+                ..Default::default()
+            },
+        );
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rescan_interfaces() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("0.0.0.0:0").await?);
+        let responder = responder(&socket, &[], "224.0.0.251:5353".parse()?);
+        let group = IpAddr::from([224, 0, 0, 251]);
+        let (wanted, _) = memberships(group, &InterfaceFilter::default())?;
+
+        // the group is joined on the interfaces come up, and left on those
+        // gone
+        let gone = Membership {
+            group,
+            addr: IpAddr::from([192, 0, 2, 1]),
+            index: 0,
+        };
+        responder.links[0]
+            .memberships
+            .lock()
+            .await
+            .push(gone.clone());
+        responder.rescan_interfaces().await;
+        let joined = responder.links[0].memberships.lock().await.clone();
+        assert!(!joined.contains(&gone));
+        assert!(joined.iter().all(|m| wanted.contains(m)));
+        let groups: Vec<(IpAddr, IpAddr)> = joined.iter().map(|m| (m.group, m.addr)).collect();
+        assert_eq!(*responder.multicast_groups.lock().unwrap(), groups);
+
+        // and nothing changes while the interfaces don't
+        responder.rescan_interfaces().await;
+        assert_eq!(*responder.links[0].memberships.lock().await, joined);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_memberships() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("0.0.0.0:0").await?);
+        let responder = responder(&socket, &[], "224.0.0.251:5353".parse()?);
+        responder.rescan_interfaces().await;
+        let joined = responder.links[0].memberships.lock().await.clone();
+
+        // the memberships are left and joined again, so they stay the same
+        responder.refresh_memberships().await;
+        assert_eq!(*responder.links[0].memberships.lock().await, joined);
+
+        // one that can't be joined again is dropped
+        let gone = Membership {
+            group: IpAddr::from([224, 0, 0, 251]),
+            addr: IpAddr::from([192, 0, 2, 1]),
+            index: 0,
+        };
+        responder.links[0].memberships.lock().await.push(gone);
+        responder.refresh_memberships().await;
+        assert_eq!(*responder.links[0].memberships.lock().await, joined);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_port_sharing() -> Result<(), Error> {
+        let server_a = DNSConn::server(
+            "0.0.0.0:0".parse()?,
+            Config {
+                responder_only: true,
+                ..config()
+            },
+        )?;
+
+        // another responder can be bound to the same port meanwhile
+        let server_b = DNSConn::server(
+            server_a.local_addr(),
+            Config {
+                responder_only: true,
+                ..config()
+            },
+        )?;
+        assert_eq!(server_b.local_addr(), server_a.local_addr());
+
+        server_a.close().await?;
+        server_b.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_known_answer_suppression() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = multicast.local_addr()?;
+        let responder = responder(&socket, &["myhost.local."], src);
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for (ttl, answered) in [
+            (RESPONSE_TTL, false),
+            (RESPONSE_TTL / 2, false),
+            (RESPONSE_TTL / 2 - 1, true),
+        ] {
+            let mut msg = Message {
+                questions: questions(&Name::new("myhost.local.")?, &[DNSType::A]),
+                answers: vec![answer("myhost.local.", src.ip(), ttl)?],
+                ..Default::default()
+            };
+            let raw = msg.pack()?;
+            let mut p = Parser::default();
+            p.start(&raw)?;
+            run(
+                &mut p,
+                &responder,
+                &responder.links[0],
+                src,
+                &Arc::new(Mutex::new(vec![])),
+                &Arc::new(Mutex::new(vec![])),
+                false,
+                true,
+            )
+            .await;
+
+            let res = timeout(Duration::from_millis(100), multicast.recv_from(&mut b)).await;
+            assert_eq!(res.is_ok(), answered, "known answer with TTL {}", ttl);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_on_local_link() {
+        assert!(on_local_link(IpAddr::from([127, 0, 0, 1]), None));
+        assert!(on_local_link(IpAddr::from([127, 0, 0, 1]), Some(255)));
+        assert!(!on_local_link(IpAddr::from([127, 0, 0, 1]), Some(64)));
+        assert!(on_local_link(IpAddr::from([169, 254, 1, 2]), None));
+        assert!(on_local_link(
+            IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 7]),
+            Some(255)
+        ));
+        // TEST-NET-3 is on none of our subnets
+        assert!(!on_local_link(IpAddr::from([203, 0, 113, 7]), Some(255)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_recv_ttl() -> Result<(), Error> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        recv::enable_recv_ttl(&socket)?;
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0")?;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        for ttl in [64, 255] {
+            sender.set_ttl(ttl)?;
+            sender.send_to(b"mdns", socket.local_addr()?)?;
+            let (n, src, got) = timeout(Duration::from_secs(1), recv::recv_from(&socket, &mut b))
+                .await
+                .expect("expected a packet")?;
+            assert_eq!(&b[..n], b"mdns");
+            assert_eq!(src, sender.local_addr()?);
+            assert_eq!(got.ttl, Some(ttl as u8));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_answer_interface_addresses() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        responder.answer_addresses = AnswerAddresses::Interfaces;
+
+        // a querier on loopback is answered with the loopback address
+        let questions = questions(&Name::new("myhost.local.")?, &[DNSType::A]);
+        responder
+            .answer_questions(&responder.links[0], &questions, &[], src)
+            .await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        let ips: Vec<IpAddr> = msg.answers.iter().filter_map(|a| a.ip()).collect();
+        assert!(ips.contains(&IpAddr::from([127, 0, 0, 1])));
+        assert!(ips.iter().all(|ip| ip.is_loopback()));
+
+        // others are answered with every other address
+        let ips = interface_addresses(IpAddr::from([203, 0, 113, 7]), &InterfaceFilter::default())?;
+        assert!(ips.iter().all(|ip| !ip.is_loopback()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_answer_address_facing_querier() -> Result<(), Error> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+
+        assert_eq!(
+            interface_for_remote(src).await?,
+            IpAddr::from([127, 0, 0, 1])
+        );
+
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        responder.answer_addresses = AnswerAddresses::FacingQuerier;
+        let questions = questions(&Name::new("myhost.local.")?, &[DNSType::A]);
+        responder
+            .answer_questions(
+                &responder.links[0],
+                &questions,
+                &[],
+                "127.0.0.2:5353".parse()?,
+            )
+            .await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        // the local address, not the querier's
+        assert_eq!(msg.answers[0].ip(), Some(IpAddr::from([127, 0, 0, 1])));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_response_ttl() -> Result<(), Error> {
+        let mut records = HashMap::new();
+        records.insert(
+            "printer.local".to_owned(),
+            vec![
+                Resource {
+                    body: Some(ResourceData::A(AResource { a: [10, 0, 0, 9] })),
+                    ..Default::default()
+                },
+                Resource {
+                    header: ResourceHeader {
+                        ttl: 10,
+                        ..Default::default()
+                    },
+                    body: Some(ResourceData::AAAA(AAAAResource { aaaa: [1; 16] })),
+                },
+            ],
+        );
+        let server_a = DNSConn::server(
+            loopback(),
+            Config {
+                local_names: vec!["myhost.local".to_owned()],
+                records,
+                response_ttl: Duration::from_secs(2 * 60 * 60),
+                ..config()
+            },
+        )?;
+        assert_eq!(server_a.response_ttl, 7200);
+        let ttls: Vec<u32> = server_a.records.iter().map(|r| r.header.ttl).collect();
+        assert_eq!(ttls, vec![7200, 10]);
+
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let multicast = UdpSocket::bind("127.0.0.1:0").await?;
+        let src = socket.local_addr()?;
+        let mut responder = responder(&socket, &["myhost.local."], multicast.local_addr()?);
+        responder.response_ttl = server_a.response_ttl;
+
+        let questions = questions(&Name::new("myhost.local.")?, &[DNSType::A]);
+        responder
+            .answer_questions(&responder.links[0], &questions, &[], src)
+            .await;
+
+        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), multicast.recv_from(&mut b))
+            .await
+            .expect("expected an answer")?;
+        let msg = Message::try_from(&b[..n])?;
+        assert_eq!(msg.answers[0].header.ttl, 7200);
 
         server_a.close().await?;
 
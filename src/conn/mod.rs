@@ -1,145 +1,950 @@
 use crate::config::*;
 use crate::errors::*;
 use crate::message::name::*;
-use crate::message::{header::*, parser::*, question::*, resource::a::*, resource::*, *};
-
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use crate::message::{
+    header::*, parser::*, question::*, resource::a::*, resource::aaaa::*, resource::cname::*,
+    resource::*, *,
+};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
 use core::sync::atomic;
+use futures_core::Stream;
+use rand::Rng;
 use socket2::SockAddr;
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 
 use util::ifaces;
 use util::Error;
 
 mod conn_test;
+pub mod querier;
+mod recv;
+pub mod responder;
 
 pub const DEFAULT_DEST_ADDR: &str = "224.0.0.251:5353";
+pub const DEFAULT_DEST_ADDR_V6: &str = "[ff02::fb]:5353";
 
-const INBOUND_BUFFER_SIZE: usize = 512;
+// INBOUND_BUFFER_SIZE is the largest mDNS message we accept (RFC 6762
+// Section 17).
+const INBOUND_BUFFER_SIZE: usize = 9000;
+// DEFAULT_MAX_MESSAGE_SIZE keeps outgoing messages within an Ethernet MTU.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 1472;
 const DEFAULT_QUERY_INTERVAL: Duration = Duration::from_secs(1);
-const MAX_MESSAGE_RECORDS: usize = 3;
+// DEFAULT_MAX_QUERY_INTERVAL caps the doubling interval between
+// retransmissions of a query (RFC 6762 Section 5.2).
+const DEFAULT_MAX_QUERY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// BROWSE_CHANNEL_SIZE is how many answers a browse buffers for its reader.
+const BROWSE_CHANNEL_SIZE: usize = 16;
+// OBSERVE_CHANNEL_SIZE is how many answers an observer buffers before
+// further ones are dropped.
+const OBSERVE_CHANNEL_SIZE: usize = 64;
+// QUERY_AGGREGATION_WINDOW is how far ahead of schedule a retransmission may
+// be sent so it can share a packet with other due questions.
+const QUERY_AGGREGATION_WINDOW: Duration = Duration::from_millis(200);
+// RESPONSE_TTL is the TTL of our answers when the Config sets none.
 const RESPONSE_TTL: u32 = 120;
+// LEGACY_UNICAST_TTL caps the TTLs of the answers to legacy unicast queries
+// (RFC 6762 Section 6.7).
+const LEGACY_UNICAST_TTL: u32 = 10;
+// PROBE_COUNT is how many probes are sent for a local name, PROBE_INTERVAL
+// apart, before it is answered for (RFC 6762 Section 8.1).
+const PROBE_COUNT: u32 = 3;
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+// ANNOUNCE_COUNT is how many times the records of a claimed local name are
+// announced, ANNOUNCE_INTERVAL apart (RFC 6762 Section 8.3).
+const ANNOUNCE_COUNT: u32 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+// PROBE_DEFER is how long a host that lost a simultaneous probe tie-break
+// waits before probing again (RFC 6762 Section 8.2).
+const PROBE_DEFER: Duration = Duration::from_secs(1);
+// DEFENSE_WINDOW is how long after defending a local name against another
+// host answering for it a further conflict makes us give the name up.
+const DEFENSE_WINDOW: Duration = Duration::from_secs(10);
+// MIN_MULTICAST_INTERVAL is how long a record must wait before it is
+// multicast again (RFC 6762 Section 6).
+const MIN_MULTICAST_INTERVAL: Duration = Duration::from_secs(1);
+// OWN_PACKET_WINDOW is how long after multicasting a packet we expect it
+// may be looped back to us.
+const OWN_PACKET_WINDOW: Duration = Duration::from_secs(2);
+// QUESTION_RATE_WINDOW is the period max_questions_per_source counts the
+// questions of a source over.
+const QUESTION_RATE_WINDOW: Duration = Duration::from_secs(1);
+// RESOLVE_SETTLE_TIME is how long resolve keeps collecting addresses after
+// the first one arrives.
+const RESOLVE_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// OnQuestionHdlrFn is called with each question received and its source
+/// before it is answered; the question is only answered if it returns true.
+pub type OnQuestionHdlrFn = Box<
+    dyn (Fn(Question, SocketAddr) -> Pin<Box<dyn Future<Output = bool> + Send + 'static>>)
+        + Send
+        + Sync,
+>;
+
+/// OnNameConflictHdlrFn is called when another host turns out to use one of
+/// the local names, with the name and the one it was renamed to.
+pub type OnNameConflictHdlrFn = Box<
+    dyn (Fn(String, String) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>) + Send + Sync,
+>;
 
 // Conn represents a mDNS Server
 pub struct DNSConn {
-    socket: Arc<UdpSocket>,
-    dst_addr: SocketAddr,
-
-    query_interval: Duration,
+    // addrs are the address each socket is bound to and the multicast group
+    // and port it sends to, one socket for each version of IP
+    addrs: std::sync::Mutex<Vec<(SocketAddr, SocketAddr)>>,
+    local_names: Arc<Mutex<Vec<String>>>,
+    // probe_local_names is set if the local names are probed for before
+    // they are answered for
+    probe_local_names: bool,
+    // probes are the local names still being probed for
+    probes: Arc<Mutex<Vec<Probe>>>,
+    // goodbyes are the removed local names still to say goodbye for
+    goodbyes: Arc<Mutex<Vec<String>>>,
+    records: Arc<Vec<Resource>>,
+    on_question_handler: Arc<Mutex<Option<Arc<OnQuestionHdlrFn>>>>,
+    on_name_conflict_handler: Arc<Mutex<Option<Arc<OnNameConflictHdlrFn>>>>,
+    answer_addresses: AnswerAddresses,
+    max_message_size: usize,
+    response_ttl: u32,
+    // multicast_groups are the (group, interface) pairs joined
+    multicast_groups: Arc<std::sync::Mutex<Vec<(IpAddr, IpAddr)>>>,
+    // join_multicast is false for a one-shot querier, which only receives
+    // the answers sent straight back to its own port
+    join_multicast: bool,
+    socket_per_interface: bool,
+    interface_rescan_interval: Duration,
+    membership_refresh_interval: Duration,
+
+    query_schedule: QuerySchedule,
+    max_query_attempts: u32,
+    query_timeout: Duration,
     queries: Arc<Mutex<Vec<Query>>>,
+    query_added: Arc<Notify>,
+    // observers are the readers of every answer received
+    observers: Arc<Mutex<Vec<mpsc::Sender<QueryResult>>>>,
+    follow_cname: bool,
+    responder_only: bool,
+    querier_only: bool,
+    validate_source: bool,
+    allowed_sources: Vec<Subnet>,
+    denied_sources: Vec<Subnet>,
+    max_questions_per_source: u32,
+    question_counters: Arc<Mutex<QuestionCounters>>,
+    interfaces: InterfaceFilter,
 
     is_server_closed: Arc<atomic::AtomicBool>,
-    close_server: mpsc::Sender<()>,
+    close_server: std::sync::Mutex<mpsc::Sender<()>>,
+    cancellation_token: CancellationToken,
+    // server_task is the receive loop, which owns the socket
+    server_task: Mutex<Option<JoinHandle<Result<(), Error>>>>,
 }
 
 struct Query {
-    name_with_suffix: String,
-    query_result_chan: mpsc::Sender<QueryResult>,
+    name_with_suffix: Name,
+    types: Vec<DNSType>,
+    // query_result_chans are the callers waiting on the query; concurrent
+    // identical queries share a Query rather than each sending questions
+    query_result_chans: Vec<mpsc::Sender<BrowseEvent>>,
+    // multiple_answers keeps the query registered after its first answer
+    multiple_answers: bool,
+    // cache holds the answers of a browse, refreshed as they near expiry
+    cache: Option<Vec<CachedAnswer>>,
+    // next_query is when the question is due to be sent again; interval,
+    // doubled after each retransmission up to max_interval, is the delay
+    // before the one after.
+    next_query: Instant,
+    interval: Duration,
+    max_interval: Duration,
+    // started and attempts are when the query was registered and how many
+    // times its questions have been sent since
+    started: Instant,
+    attempts: u32,
+    // max_attempts is how many times the questions are sent before giving
+    // up, zero for no limit
+    max_attempts: u32,
 }
 
-struct QueryResult {
-    answer: ResourceHeader,
-    addr: SocketAddr,
+impl Query {
+    // matches_type reports whether an answer of type typ satisfies the
+    // query, DNSType::ALL standing for any type.
+    fn matches_type(&self, typ: DNSType) -> bool {
+        self.types.iter().any(|t| *t == typ || *t == DNSType::ALL)
+    }
+
+    // exhausted reports whether the questions have been sent max_attempts
+    // times.
+    fn exhausted(&self) -> bool {
+        self.max_attempts != 0 && self.attempts >= self.max_attempts
+    }
+
+    // next_event is when the query is next due to be retransmitted or have
+    // a cached answer refreshed or expired.
+    fn next_event(&self) -> Instant {
+        self.cache
+            .iter()
+            .flatten()
+            .flat_map(|c| c.refreshes.first().into_iter().chain(Some(&c.expires)))
+            .fold(self.next_query, |next, t| std::cmp::min(next, *t))
+    }
 }
 
-impl DNSConn {
-    /// server establishes a mDNS connection over an existing connection
-    pub fn server(addr: SocketAddr, config: Config) -> Result<Self, Error> {
-        let socket = socket2::Socket::new(
-            socket2::Domain::IPV4,
-            socket2::Type::DGRAM,
-            Some(socket2::Protocol::UDP),
-        )?;
+// A CachedAnswer is an answer to a browse, kept until its TTL runs out.
+struct CachedAnswer {
+    result: QueryResult,
+    rdata: Vec<u8>,
+    // refreshes are when to query again for the answer before it expires
+    refreshes: Vec<Instant>,
+    expires: Instant,
+}
 
-        socket.set_reuse_address(true)?;
+impl CachedAnswer {
+    // new schedules refresh queries at 80%, 85%, 90% and 95% of the TTL,
+    // each with up to 2% of jitter (RFC 6762 Section 5.2). An answer with
+    // a TTL of zero is a goodbye and expires after one second instead
+    // (RFC 6762 Section 10.1).
+    fn new(result: QueryResult, rdata: Vec<u8>) -> Self {
+        let now = Instant::now();
+        let ttl = result.ttl;
+        if ttl == Duration::from_secs(0) {
+            return CachedAnswer {
+                result,
+                rdata,
+                refreshes: vec![],
+                expires: now + Duration::from_secs(1),
+            };
+        }
 
-        //TODO: implement set_reuse_port for windows platform
-        #[cfg(target_family = "unix")]
-        socket.set_reuse_port(true)?;
+        let mut rng = rand::thread_rng();
+        let refreshes = [80u32, 85, 90, 95]
+            .iter()
+            .map(|&percent| now + ttl * percent / 100 + ttl * rng.gen_range(0..=20) / 1000)
+            .collect();
+        CachedAnswer {
+            result,
+            rdata,
+            refreshes,
+            expires: now + ttl,
+        }
+    }
 
-        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
-        socket.bind(&SockAddr::from(addr))?;
+    // known_answer returns the answer with its remaining TTL, to list in the
+    // next query for it, unless less than half of its TTL is left (RFC 6762
+    // Section 7.1).
+    fn known_answer(&self, now: Instant) -> Option<Resource> {
+        let remaining = self.expires.saturating_duration_since(now);
+        if remaining < self.result.ttl / 2 || remaining == Duration::from_secs(0) {
+            return None;
+        }
+        let mut answer = self.result.answer.clone();
+        answer.header.ttl = remaining.as_secs() as u32;
+        Some(answer)
+    }
+}
 
-        {
-            let mut join_error_count = 0;
-            let interfaces = match ifaces::ifaces() {
-                Ok(e) => e,
-                Err(e) => {
-                    log::error!("Error getting interfaces: {:?}", e);
-                    return Err(Error::new(e.to_string()));
-                }
-            };
+/// QuestionCounters count the questions received from other hosts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QuestionCounters {
+    /// received is how many questions have been received
+    pub received: u64,
+    /// dropped is how many of them were dropped for coming from a source
+    /// over max_questions_per_source
+    pub dropped: u64,
+}
 
-            for interface in &interfaces {
-                if let Some(SocketAddr::V4(e)) = interface.addr {
-                    if let Err(e) = socket.join_multicast_v4(&Ipv4Addr::new(224, 0, 0, 251), e.ip())
-                    {
-                        log::error!("Error connecting multicast, error: {:?}", e);
-                        join_error_count += 1;
-                        continue;
-                    }
+/// PendingQuery describes a query still waiting for answers.
+#[derive(Debug, Clone)]
+pub struct PendingQuery {
+    /// name is the fully qualified name queried for
+    pub name: Name,
+    /// types are the record types queried for
+    pub types: Vec<DNSType>,
+    /// age is how long ago the query was started
+    pub age: Duration,
+    /// attempts is how many times its questions have been sent
+    pub attempts: u32,
+}
 
-                    log::trace!("Connected to interface address {:?}", e);
-                }
-            }
+/// QuerySchedule is when a query is retransmitted: after interval at first,
+/// then with the interval doubling up to max_interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuerySchedule {
+    /// interval is the wait before the first retransmission
+    pub interval: Duration,
+    /// max_interval caps the wait between retransmissions
+    pub max_interval: Duration,
+}
+
+/// QueryResult is the answer to a query.
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    /// answer is the matching record
+    pub answer: Resource,
+    /// ip is the address of an A or AAAA answer, None for other types
+    pub ip: Option<IpAddr>,
+    /// ttl is how long the answer may be cached
+    pub ttl: Duration,
+    /// received is when the answer arrived
+    pub received: std::time::Instant,
+    /// addr is where the answer came from
+    pub addr: SocketAddr,
+}
+
+/// BrowseEvent is a change to the answers of a browse.
+#[derive(Debug, Clone)]
+pub enum BrowseEvent {
+    /// Answer is an answer received, either new or refreshing one
+    Answer(QueryResult),
+    /// Expired is an answer whose TTL ran out without being refreshed
+    Expired(QueryResult),
+}
+
+// QueryEvents receives the events of a registered query. Dropping it
+// deregisters the caller from the query at once, rather than whenever the
+// server next prunes the queries.
+struct QueryEvents {
+    events: mpsc::Receiver<BrowseEvent>,
+    queries: Arc<Mutex<Vec<Query>>>,
+    query_added: Arc<Notify>,
+}
+
+impl QueryEvents {
+    async fn recv(&mut self) -> Option<BrowseEvent> {
+        self.events.recv().await
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<BrowseEvent>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+impl Drop for QueryEvents {
+    fn drop(&mut self) {
+        self.events.close();
+        match self.queries.try_lock() {
+            Ok(mut queries) => prune_queries(&mut queries),
+            // The server holds the queries; wake it to prune them when done.
+            Err(_) => self.query_added.notify_one(),
+        }
+    }
+}
+
+impl QueryResult {
+    /// expires returns when the answer runs out of its TTL
+    pub fn expires(&self) -> std::time::Instant {
+        self.received + self.ttl
+    }
+}
+
+/// Browse is a Stream of the events of a continuous query started by
+/// DNSConn::browse. Dropping it stops the query.
+pub struct Browse {
+    events: QueryEvents,
+}
+
+impl Browse {
+    /// next waits for the next event
+    pub async fn next(&mut self) -> Option<BrowseEvent> {
+        self.events.recv().await
+    }
+}
+
+impl Stream for Browse {
+    type Item = BrowseEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<BrowseEvent>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// Observe is a Stream of every answer received, started by
+/// DNSConn::observe. Dropping it stops the observation.
+pub struct Observe {
+    answers: mpsc::Receiver<QueryResult>,
+}
+
+impl Observe {
+    /// next waits for the next answer
+    pub async fn next(&mut self) -> Option<QueryResult> {
+        self.answers.recv().await
+    }
+}
+
+impl Stream for Observe {
+    type Item = QueryResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<QueryResult>> {
+        self.answers.poll_recv(cx)
+    }
+}
+
+// A Probe is a local name not answered for until it has been probed for
+// without another host answering for it.
+struct Probe {
+    name: String,
+    // sent is how many probes have gone out, and next_probe when the next
+    // one is due, or the name is claimed after the last one
+    sent: u32,
+    next_probe: Instant,
+    // result_chans are told the name claimed, which is name renamed if
+    // another host turned out to use it
+    result_chans: Vec<mpsc::Sender<String>>,
+}
+
+impl Probe {
+    // new starts probing for name after a random delay of up to 250ms, so
+    // that hosts starting together don't probe in step.
+    fn new(name: String) -> Self {
+        Probe {
+            name,
+            sent: 0,
+            next_probe: Instant::now()
+                + Duration::from_millis(rand::thread_rng().gen_range(0..=250)),
+            result_chans: vec![],
+        }
+    }
+}
+
+// An Announcement is a claimed local name whose records are still to be
+// announced.
+struct Announcement {
+    name: String,
+    sent: u32,
+    next_announcement: Instant,
+}
+
+// A Responder answers questions for the local names.
+struct Responder {
+    local_names: Arc<Mutex<Vec<String>>>,
+    // probe_local_names is set if the local names are probed for, and
+    // defended or renamed when another host answers for them
+    probe_local_names: bool,
+    probes: Arc<Mutex<Vec<Probe>>>,
+    goodbyes: Arc<Mutex<Vec<String>>>,
+    announcements: Mutex<Vec<Announcement>>,
+    // defended are the local names last defended within DEFENSE_WINDOW, and
+    // when
+    defended: Mutex<Vec<(String, Instant)>>,
+    records: Arc<Vec<Resource>>,
+    // querier_only leaves every question unanswered
+    querier_only: bool,
+    on_question_handler: Arc<Mutex<Option<Arc<OnQuestionHdlrFn>>>>,
+    on_name_conflict_handler: Arc<Mutex<Option<Arc<OnNameConflictHdlrFn>>>>,
+    answer_addresses: AnswerAddresses,
+    links: Vec<Arc<Link>>,
+    // interface_links are the links for the interfaces packets have been
+    // received on over the sockets of links shared by every interface
+    interface_links: Mutex<Vec<Arc<Link>>>,
+    max_message_size: usize,
+    response_ttl: u32,
+    // send_buf is packed into for every answer that fits in one message, so
+    // answering doesn't allocate a buffer each time
+    send_buf: Mutex<Vec<u8>>,
+    // sent are the hashes of the packets multicast in the last
+    // OWN_PACKET_WINDOW, and when
+    sent: Mutex<Vec<(u64, Instant)>>,
+    // validate_source drops the packets from off the local link
+    validate_source: bool,
+    allowed_sources: Vec<Subnet>,
+    denied_sources: Vec<Subnet>,
+    max_questions_per_source: u32,
+    // source_questions are how many questions each source has asked since
+    // the start of its current QUESTION_RATE_WINDOW, and when that was
+    source_questions: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+    question_counters: Arc<Mutex<QuestionCounters>>,
+    interfaces: InterfaceFilter,
+    multicast_groups: Arc<std::sync::Mutex<Vec<(IpAddr, IpAddr)>>>,
+    // interface_rescan_interval is zero if the interfaces are not rescanned
+    interface_rescan_interval: Duration,
+    // membership_refresh_interval is zero if the memberships are not
+    // refreshed
+    membership_refresh_interval: Duration,
+}
+
+// A Link is a socket mDNS is sent and received on, and the multicast group
+// and port it sends to.
+struct Link {
+    socket: Arc<UdpSocket>,
+    dst_addr: SocketAddr,
+    // interface is the interface the link is on, if just the one
+    interface: Option<LinkInterface>,
+    // memberships are the interfaces the socket has joined the multicast
+    // group on
+    memberships: Mutex<Vec<Membership>>,
+    delayed: Mutex<Option<DelayedAnswers>>,
+    // recent_multicasts are the records multicast on the link in the last
+    // MIN_MULTICAST_INTERVAL, and when
+    recent_multicasts: Mutex<Vec<(Resource, Instant)>>,
+}
+
+impl Link {
+    fn new(socket: Arc<UdpSocket>, dst_addr: SocketAddr) -> Self {
+        Link {
+            socket,
+            dst_addr,
+            interface: None,
+            memberships: Mutex::new(vec![]),
+            delayed: Mutex::new(None),
+            recent_multicasts: Mutex::new(vec![]),
+        }
+    }
+}
+
+// An InterfaceFilter is which interfaces mDNS is run on: those that are none
+// of denied and, if any are allowed, one of them.
+#[derive(Debug, Clone, Default)]
+struct InterfaceFilter {
+    allowed: Vec<InterfaceId>,
+    denied: Vec<InterfaceId>,
+    // device, if set, is the only interface, the sockets being bound to it
+    device: Option<String>,
+}
+
+impl InterfaceFilter {
+    // allows reports whether mDNS is run on the interface named name.
+    fn allows(&self, name: &str) -> bool {
+        if self.device.as_deref().is_some_and(|d| d != name) {
+            return false;
+        }
+        if self.allowed.is_empty() && self.denied.is_empty() {
+            return true;
+        }
+        let index = interface_index(name);
+        if self.denied.iter().any(|i| i.matches(name, index)) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|i| i.matches(name, index))
+    }
+}
+
+// A LinkInterface is a network interface a socket is dedicated to.
+#[derive(Debug, Clone)]
+struct LinkInterface {
+    name: String,
+    index: u32,
+    addrs: Vec<IpAddr>,
+}
+
+// A Membership is a multicast group joined on an interface: by its address
+// for IPv4, or its index for IPv6.
+#[derive(Debug, Clone, PartialEq)]
+struct Membership {
+    group: IpAddr,
+    addr: IpAddr,
+    index: u32,
+}
 
-            if join_error_count >= interfaces.len() {
-                return Err(ERR_JOINING_MULTICAST_GROUP.to_owned());
+impl Membership {
+    // same reports whether m is of the same group on the same interface.
+    fn same(&self, m: &Membership) -> bool {
+        self.group == m.group
+            && match self.group {
+                IpAddr::V4(_) => self.addr == m.addr,
+                IpAddr::V6(_) => self.index == m.index,
             }
+    }
+
+    fn join(&self, socket: socket2::SockRef<'_>) -> std::io::Result<()> {
+        match (self.group, self.addr) {
+            (IpAddr::V4(group), IpAddr::V4(addr)) => socket.join_multicast_v4(&group, &addr),
+            (IpAddr::V6(group), _) => socket.join_multicast_v6(&group, self.index),
+            _ => Err(std::io::ErrorKind::InvalidInput.into()),
         }
+    }
 
-        let socket = UdpSocket::from_std(socket.into())?;
+    fn leave(&self, socket: socket2::SockRef<'_>) -> std::io::Result<()> {
+        match (self.group, self.addr) {
+            (IpAddr::V4(group), IpAddr::V4(addr)) => socket.leave_multicast_v4(&group, &addr),
+            (IpAddr::V6(group), _) => socket.leave_multicast_v6(&group, self.index),
+            _ => Err(std::io::ErrorKind::InvalidInput.into()),
+        }
+    }
+}
 
-        let local_names = config
-            .local_names
-            .iter()
-            .map(|l| l.to_string() + ".")
-            .collect();
+// DelayedAnswers are multicast answers with shared records, held back for a
+// random 20-120ms so that the answers of every responder sharing them don't
+// go out at once, and sent together in one message (RFC 6762 Section 6).
+struct DelayedAnswers {
+    answers: Vec<Resource>,
+    deadline: Instant,
+}
 
-        let dst_addr: SocketAddr = DEFAULT_DEST_ADDR.parse()?;
+// A TruncatedQuery is a query with the TC bit set, held back while its known
+// answers continue in further packets from the same source.
+struct TruncatedQuery {
+    link: Arc<Link>,
+    questions: Vec<Question>,
+    known_answers: Vec<Resource>,
+    deadline: Instant,
+}
+
+impl DNSConn {
+    /// server establishes a mDNS connection over an existing connection
+    pub fn server(addr: SocketAddr, config: Config) -> Result<Self, Error> {
+        DNSConn::new(addr, config, true)
+    }
+
+    fn new(addr: SocketAddr, config: Config, join_multicast: bool) -> Result<Self, Error> {
+        let response_ttl = if config.response_ttl != Duration::from_secs(0) {
+            config.response_ttl.as_secs() as u32
+        } else {
+            RESPONSE_TTL
+        };
+        let records = if !config.querier_only {
+            static_records(&config.records, response_ttl)?
+        } else {
+            vec![]
+        };
+        let interfaces = InterfaceFilter {
+            allowed: config.allowed_interfaces,
+            denied: config.denied_interfaces,
+            device: config.bind_device,
+        };
+        let mut addrs = socket_addrs(addr, config.ip_version, config.multicast_addr)?;
+        let (links, multicast_groups) = bind_links(
+            &mut addrs,
+            join_multicast,
+            config.socket_per_interface,
+            &interfaces,
+        )?;
 
-        let is_server_closed = Arc::new(atomic::AtomicBool::new(false));
+        // The Querier mode answers each querier with its own address, so
+        // there are no records to probe for.
+        let probe_local_names =
+            config.probe_local_names && config.answer_addresses != AnswerAddresses::Querier;
+        let local_names: Vec<String> = if !config.querier_only {
+            config
+                .local_names
+                .iter()
+                .map(|l| l.to_string() + ".")
+                .collect()
+        } else {
+            vec![]
+        };
+        let (local_names, probes) = if probe_local_names {
+            (vec![], local_names.into_iter().map(Probe::new).collect())
+        } else {
+            (local_names, vec![])
+        };
 
         let (close_server_send, close_server_rcv) = mpsc::channel(1);
 
-        let c = DNSConn {
-            query_interval: if config.query_interval != Duration::from_secs(0) {
-                config.query_interval
+        let mut c = DNSConn {
+            addrs: std::sync::Mutex::new(addrs),
+            local_names: Arc::new(Mutex::new(local_names)),
+            probe_local_names,
+            probes: Arc::new(Mutex::new(probes)),
+            goodbyes: Arc::new(Mutex::new(vec![])),
+            records: Arc::new(records),
+            on_question_handler: Arc::new(Mutex::new(None)),
+            on_name_conflict_handler: Arc::new(Mutex::new(None)),
+            answer_addresses: config.answer_addresses,
+            max_message_size: if config.max_message_size != 0 {
+                config.max_message_size
             } else {
-                DEFAULT_QUERY_INTERVAL
+                DEFAULT_MAX_MESSAGE_SIZE
+            },
+            response_ttl,
+            query_schedule: QuerySchedule {
+                interval: if config.query_interval != Duration::from_secs(0) {
+                    config.query_interval
+                } else {
+                    DEFAULT_QUERY_INTERVAL
+                },
+                max_interval: if config.max_query_interval != Duration::from_secs(0) {
+                    config.max_query_interval
+                } else {
+                    DEFAULT_MAX_QUERY_INTERVAL
+                },
             },
+            max_query_attempts: config.max_query_attempts,
+            query_timeout: config.query_timeout,
 
             queries: Arc::new(Mutex::new(vec![])),
-            socket: Arc::new(socket),
-            dst_addr,
-            is_server_closed: Arc::clone(&is_server_closed),
-            close_server: close_server_send,
+            query_added: Arc::new(Notify::new()),
+            observers: Arc::new(Mutex::new(vec![])),
+            follow_cname: config.follow_cname,
+            responder_only: config.responder_only,
+            querier_only: config.querier_only,
+            validate_source: config.validate_source,
+            allowed_sources: config.allowed_sources,
+            denied_sources: config.denied_sources,
+            max_questions_per_source: config.max_questions_per_source,
+            question_counters: Arc::new(Mutex::new(QuestionCounters::default())),
+            interfaces,
+            multicast_groups: Arc::new(std::sync::Mutex::new(multicast_groups)),
+            join_multicast,
+            socket_per_interface: config.socket_per_interface,
+            interface_rescan_interval: config.interface_rescan_interval,
+            membership_refresh_interval: config.membership_refresh_interval,
+            is_server_closed: Arc::new(atomic::AtomicBool::new(false)),
+            close_server: std::sync::Mutex::new(close_server_send),
+            cancellation_token: config.cancellation_token.unwrap_or_default(),
+            server_task: Mutex::new(None),
         };
+        c.server_task = Mutex::new(Some(c.spawn_server(links, close_server_rcv)?));
+
+        Ok(c)
+    }
+
+    /// restart closes the sockets and binds new ones to the same addresses,
+    /// joining the multicast groups again, for when the network has been
+    /// reset. Pending queries are kept and carry on with the new sockets.
+    pub async fn restart(&self) -> Result<(), Error> {
+        if !self.is_closed() {
+            if let Err(err) = self.close().await {
+                log::warn!("Failed to close server before restart: {}", err);
+            }
+        }
+        // the lock is held until the new server runs, so that concurrent
+        // restarts bind one after the other
+        let mut server_task = self.server_task.lock().await;
+        if let Some(server_task) = server_task.take() {
+            server_task.abort();
+        }
+
+        let mut addrs = self.addrs.lock().unwrap().clone();
+        let (links, multicast_groups) = bind_links(
+            &mut addrs,
+            self.join_multicast,
+            self.socket_per_interface,
+            &self.interfaces,
+        )?;
+        *self.addrs.lock().unwrap() = addrs;
+        *self.multicast_groups.lock().unwrap() = multicast_groups;
+
+        let (close_server_send, close_server_rcv) = mpsc::channel(1);
+        *self.close_server.lock().unwrap() = close_server_send;
+        self.is_server_closed.store(false, atomic::Ordering::SeqCst);
+        *server_task = Some(self.spawn_server(links, close_server_rcv)?);
+
+        Ok(())
+    }
 
-        let queries = c.queries.clone();
-        let socket = Arc::clone(&c.socket);
+    fn spawn_server(
+        &self,
+        links: Vec<Link>,
+        close_server_rcv: mpsc::Receiver<()>,
+    ) -> Result<JoinHandle<Result<(), Error>>, Error> {
+        for link in &links {
+            if self.validate_source {
+                recv::enable_recv_ttl(&link.socket)?;
+            }
+            if link.interface.is_none() {
+                recv::enable_pktinfo(&link.socket)?;
+            }
+        }
+        let links = links.into_iter().map(Arc::new).collect();
+        let responder = Responder {
+            local_names: Arc::clone(&self.local_names),
+            probe_local_names: self.probe_local_names,
+            probes: Arc::clone(&self.probes),
+            goodbyes: Arc::clone(&self.goodbyes),
+            announcements: Mutex::new(vec![]),
+            defended: Mutex::new(vec![]),
+            records: Arc::clone(&self.records),
+            querier_only: self.querier_only,
+            on_question_handler: Arc::clone(&self.on_question_handler),
+            on_name_conflict_handler: Arc::clone(&self.on_name_conflict_handler),
+            answer_addresses: self.answer_addresses.clone(),
+            links,
+            interface_links: Mutex::new(vec![]),
+            max_message_size: self.max_message_size,
+            response_ttl: self.response_ttl,
+            send_buf: Mutex::new(Vec::with_capacity(self.max_message_size)),
+            sent: Mutex::new(vec![]),
+            validate_source: self.validate_source,
+            allowed_sources: self.allowed_sources.clone(),
+            denied_sources: self.denied_sources.clone(),
+            max_questions_per_source: self.max_questions_per_source,
+            source_questions: Mutex::new(HashMap::new()),
+            question_counters: Arc::clone(&self.question_counters),
+            interfaces: self.interfaces.clone(),
+            multicast_groups: Arc::clone(&self.multicast_groups),
+            interface_rescan_interval: if self.join_multicast {
+                self.interface_rescan_interval
+            } else {
+                Duration::ZERO
+            },
+            membership_refresh_interval: if self.join_multicast {
+                self.membership_refresh_interval
+            } else {
+                Duration::ZERO
+            },
+        };
 
-        tokio::spawn(async move {
-            DNSConn::start(
+        let is_server_closed = Arc::clone(&self.is_server_closed);
+        let queries = Arc::clone(&self.queries);
+        let query_added = Arc::clone(&self.query_added);
+        let observers = Arc::clone(&self.observers);
+        let follow_cname = self.follow_cname;
+        let responder_only = self.responder_only;
+        let cancellation_token = self.cancellation_token.clone();
+        let probes = Arc::clone(&self.probes);
+        Ok(tokio::spawn(async move {
+            // The sender kept here holds the channel open, whatever becomes
+            // of the readers.
+            let (packets_tx, packets_rcv) = mpsc::channel(recv::PACKET_CHANNEL_SIZE);
+            let readers = recv::Readers::spawn(&responder.links, &packets_tx);
+            let result = DNSConn::start(
                 close_server_rcv,
+                packets_rcv,
+                cancellation_token,
                 is_server_closed,
-                socket,
-                local_names,
-                dst_addr,
+                responder,
                 queries,
+                query_added,
+                observers,
+                follow_cname,
+                responder_only,
             )
-            .await
-        });
+            .await;
+            // the sockets are only released once the readers are gone
+            readers.stop().await;
+
+            // Nobody probes while the server is down; the names are probed
+            // for again after a restart.
+            for p in probes.lock().await.iter_mut() {
+                p.result_chans.clear();
+            }
+            result
+        }))
+    }
 
-        Ok(c)
+    /// add_local_name starts answering questions for name, in addition to
+    /// the local names from the Config, returning the name claimed. If the
+    /// Config has the local names probed for, name is probed for first,
+    /// and if another host answers for it it is renamed, name.local
+    /// becoming name-2.local, and probed for again (RFC 6762 Section 9);
+    /// the name claimed is then returned about a second later. It fails
+    /// with ERR_QUERIER_ONLY in querier-only mode.
+    pub async fn add_local_name(&self, name: &str) -> Result<String, Error> {
+        let name = Name::new(&(name.to_owned() + "."))?;
+        if self.querier_only {
+            return Err(ERR_QUERIER_ONLY.to_owned());
+        }
+        if self.is_closed() {
+            return Err(ERR_CONNECTION_CLOSED.to_owned());
+        }
+        {
+            let mut local_names = self.local_names.lock().await;
+            // names are compared case-insensitively (RFC 6762 Section 16)
+            if let Some(l) = local_names
+                .iter()
+                .find(|l| l.eq_ignore_ascii_case(&name.data))
+            {
+                return Ok(l.trim_end_matches('.').to_owned());
+            }
+            if !self.probe_local_names {
+                local_names.push(name.data.clone());
+                return Ok(name.data.trim_end_matches('.').to_owned());
+            }
+        }
+
+        let (result_tx, mut result_rx) = mpsc::channel(1);
+        {
+            let mut probes = self.probes.lock().await;
+            match probes
+                .iter_mut()
+                .find(|p| p.name.eq_ignore_ascii_case(&name.data))
+            {
+                Some(p) => p.result_chans.push(result_tx),
+                None => {
+                    let mut p = Probe::new(name.data);
+                    p.result_chans.push(result_tx);
+                    probes.push(p);
+                }
+            }
+        }
+        self.query_added.notify_one();
+
+        match result_rx.recv().await {
+            Some(name) => Ok(name.trim_end_matches('.').to_owned()),
+            None if self.is_closed() => Err(ERR_CONNECTION_CLOSED.to_owned()),
+            None => Err(ERR_PROBE_CANCELLED.to_owned()),
+        }
+    }
+
+    /// remove_local_name stops answering questions for name, or probing for
+    /// it, reporting whether it was a local name. Peers are told to forget
+    /// the records of a claimed name.
+    pub async fn remove_local_name(&self, name: &str) -> bool {
+        let name = name.to_owned() + ".";
+        let mut probes = self.probes.lock().await;
+        let probing = probes.len();
+        probes.retain(|p| !p.name.eq_ignore_ascii_case(&name));
+
+        let mut local_names = self.local_names.lock().await;
+        let i = local_names
+            .iter()
+            .position(|l| l.eq_ignore_ascii_case(&name));
+        let removed = i.map(|i| local_names.remove(i));
+        let was_local = removed.is_some();
+        if let Some(name) = removed {
+            self.goodbyes.lock().await.push(name);
+            self.query_added.notify_one();
+        }
+        was_local || probes.len() != probing
+    }
+
+    /// on_question sets a handler called with each question received and its
+    /// source before it is answered, to log questions or decide which to
+    /// answer
+    pub async fn on_question(&self, f: OnQuestionHdlrFn) {
+        let mut handler = self.on_question_handler.lock().await;
+        *handler = Some(Arc::new(f));
+    }
+
+    /// on_name_conflict sets a handler called when another host turns out
+    /// to use one of the local names, which is then renamed
+    pub async fn on_name_conflict(&self, f: OnNameConflictHdlrFn) {
+        let mut handler = self.on_name_conflict_handler.lock().await;
+        *handler = Some(Arc::new(f));
+    }
+
+    /// local_addr returns the address the server is bound to, that of the
+    /// socket of the version of IP of the address it was started with
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addrs.lock().unwrap()[0].0
+    }
+
+    /// local_addrs returns the addresses of every socket of the server, one
+    /// for each version of IP it runs mDNS over
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.addrs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(addr, _)| *addr)
+            .collect()
     }
 
-    /// Close closes the mDNS Conn
+    /// multicast_groups returns the multicast groups joined, as pairs of
+    /// group and interface address
+    pub fn multicast_groups(&self) -> Vec<(IpAddr, IpAddr)> {
+        self.multicast_groups.lock().unwrap().clone()
+    }
+
+    /// is_closed reports whether the mDNS Conn has been closed
+    pub fn is_closed(&self) -> bool {
+        self.is_server_closed.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Close closes the mDNS Conn, returning once the server has stopped
+    /// and released its socket
     pub async fn close(&self) -> Result<(), Error> {
         {
             log::info!("Closing connection");
@@ -149,266 +954,2497 @@ impl DNSConn {
         }
 
         log::info!("Sending close command to server");
-        match self.close_server.send(()).await {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                log::warn!("error sending close command to server: {:?}", e);
-                Err(ERR_CONNECTION_CLOSED.to_owned())
+        let close_server = self.close_server.lock().unwrap().clone();
+        if let Err(e) = close_server.send(()).await {
+            log::warn!("error sending close command to server: {:?}", e);
+            return Err(ERR_CONNECTION_CLOSED.to_owned());
+        }
+
+        if let Some(server_task) = self.server_task.lock().await.take() {
+            if let Err(err) = server_task.await {
+                return Err(Error::new(err.to_string()));
             }
         }
+
+        Ok(())
+    }
+
+    /// Query sends mDNS Queries for the following name until we get a
+    /// result, retransmitting after the query interval and then with the
+    /// interval doubling up to the max query interval (RFC 6762 Section
+    /// 5.2). It fails with ERR_QUERY_NOT_FOUND or ERR_QUERY_TIMEOUT once the
+    /// max query attempts or query timeout of the Config are reached, if
+    /// set. Dropping the returned future cancels the query.
+    pub async fn query(&self, name: &str) -> Result<QueryResult, Error> {
+        self.query_types(name, &[DNSType::A]).await
+    }
+
+    /// query_with_timeout is like query, but gives up with ERR_QUERY_TIMEOUT
+    /// if no answer is received within the given duration
+    pub async fn query_with_timeout(
+        &self,
+        name: &str,
+        timeout: Duration,
+    ) -> Result<QueryResult, Error> {
+        match tokio::time::timeout(timeout, self.query(name)).await {
+            Ok(res) => res,
+            Err(_) => Err(ERR_QUERY_TIMEOUT.to_owned()),
+        }
     }
 
-    /// Query sends mDNS Queries for the following name until
-    /// either there's a close signalling or we get a result
-    pub async fn query(
+    /// query_with_cancel is like query, but gives up with ERR_QUERY_CANCELLED
+    /// when the token is cancelled
+    pub async fn query_with_cancel(
         &self,
         name: &str,
-        mut close_query_signal: mpsc::Receiver<()>,
-    ) -> Result<(ResourceHeader, SocketAddr), Error> {
-        {
-            if self.is_server_closed.load(atomic::Ordering::SeqCst) {
-                return Err(ERR_CONNECTION_CLOSED.to_owned());
-            }
+        cancellation_token: CancellationToken,
+    ) -> Result<QueryResult, Error> {
+        tokio::select! {
+            res = self.query(name) => res,
+            _ = cancellation_token.cancelled() => Err(ERR_QUERY_CANCELLED.to_owned()),
         }
+    }
 
-        let name_with_suffix = name.to_owned() + ".";
+    /// query_types is like query, but asks for each of the given record
+    /// types in a single packet; DNSType::ALL asks for every type. The
+    /// first matching answer of any of them is returned.
+    pub async fn query_types(&self, name: &str, types: &[DNSType]) -> Result<QueryResult, Error> {
+        self.query_scheduled(name, types, self.query_schedule).await
+    }
 
-        let (query_tx, mut query_rx) = mpsc::channel(1);
-        {
-            let mut queries = self.queries.lock().await;
-            queries.push(Query {
-                name_with_suffix: name_with_suffix.clone(),
-                query_result_chan: query_tx,
-            });
-        }
+    /// query_scheduled is like query_types, but retransmits on the given
+    /// schedule instead of the one from the Config. Identical concurrent
+    /// queries share their questions, which are then sent on the more
+    /// frequent of their schedules.
+    pub async fn query_scheduled(
+        &self,
+        name: &str,
+        types: &[DNSType],
+        schedule: QuerySchedule,
+    ) -> Result<QueryResult, Error> {
+        let mut query_rx = self
+            .register_query(name, types, false, false, schedule)
+            .await?;
+
+        let answer = async {
+            loop {
+                match query_rx.recv().await {
+                    Some(BrowseEvent::Answer(res)) => {
+                        log::info!("Received query result");
+                        return Ok(res);
+                    }
+                    Some(BrowseEvent::Expired(_)) => {}
+                    // the server gave up on the query
+                    None if !self.is_closed() => return Err(ERR_QUERY_NOT_FOUND.to_owned()),
+                    None => return Err(ERR_CONNECTION_CLOSED.to_owned()),
+                }
+            }
+        };
 
-        log::trace!("Sending query");
-        self.send_question(&name_with_suffix).await;
+        if self.query_timeout == Duration::from_secs(0) {
+            return answer.await;
+        }
+        match tokio::time::timeout(self.query_timeout, answer).await {
+            Ok(res) => res,
+            Err(_) => Err(ERR_QUERY_TIMEOUT.to_owned()),
+        }
+    }
 
+    /// query_all is like query_types, but keeps collecting answers until the
+    /// window elapses or max_results distinct records have been received.
+    /// A max_results of 0 means no limit.
+    pub async fn query_all(
+        &self,
+        name: &str,
+        types: &[DNSType],
+        window: Duration,
+        max_results: usize,
+    ) -> Result<Vec<QueryResult>, Error> {
+        let mut query_rx = self
+            .register_query(name, types, true, false, self.query_schedule)
+            .await?;
+
+        let mut results: Vec<QueryResult> = vec![];
+        let deadline = tokio::time::sleep(window);
+        tokio::pin!(deadline);
         loop {
             tokio::select! {
-                _ = tokio::time::sleep(self.query_interval) => {
-                    log::trace!("Sending query");
-                    self.send_question(&name_with_suffix).await
-                },
-
-                _ = close_query_signal.recv() => {
-                    log::info!("Query close signal received.");
-                    return Err(ERR_CONNECTION_CLOSED.to_owned())
-                },
+                _ = &mut deadline => break,
 
                 res_opt = query_rx.recv() =>{
                     log::info!("Received query result");
-                    if let Some(res) = res_opt{
-                        return Ok((res.answer, res.addr));
+                    match res_opt {
+                        Some(BrowseEvent::Answer(res)) => {
+                            if !results.iter().any(|r| same_record(&r.answer, &res.answer)) {
+                                results.push(res);
+                            }
+                            if max_results != 0 && results.len() >= max_results {
+                                break;
+                            }
+                        }
+                        Some(BrowseEvent::Expired(_)) => {}
+                        None => break,
                     }
                 }
             }
         }
-    }
 
-    async fn send_question(&self, name: &str) {
-        let packed_name = match Name::new(name) {
-            Ok(pn) => pn,
-            Err(err) => {
-                log::warn!("Failed to construct mDNS packet: {}", err);
-                return;
-            }
-        };
+        Ok(results)
+    }
 
-        let raw_query = {
-            let mut msg = Message {
-                header: Header::default(),
-                questions: vec![Question {
-                    typ: DNSType::A,
-                    class: DNSCLASS_INET,
-                    name: packed_name,
-                }],
-                ..Default::default()
-            };
+    /// resolve looks up the IPv4 and IPv6 addresses of name, returning those
+    /// received shortly after the first answer, or ERR_QUERY_TIMEOUT if none
+    /// arrives within timeout
+    pub async fn resolve(&self, name: &str, timeout: Duration) -> Result<Vec<IpAddr>, Error> {
+        let mut query_rx = self
+            .register_query(
+                name,
+                &[DNSType::A, DNSType::AAAA],
+                true,
+                false,
+                self.query_schedule,
+            )
+            .await?;
 
-            match msg.pack() {
-                Ok(v) => v,
-                Err(err) => {
-                    log::error!("Failed to construct mDNS packet {}", err);
-                    return;
+        let mut ips: Vec<IpAddr> = vec![];
+        let deadline = tokio::time::sleep(timeout);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+
+                res_opt = query_rx.recv() => {
+                    match res_opt {
+                        Some(BrowseEvent::Answer(res)) => {
+                            if ips.is_empty() {
+                                let settled = Instant::now() + RESOLVE_SETTLE_TIME;
+                                if settled < deadline.deadline() {
+                                    deadline.as_mut().reset(settled);
+                                }
+                            }
+                            if let Some(ip) = res.ip {
+                                if !ips.contains(&ip) {
+                                    ips.push(ip);
+                                }
+                            }
+                        }
+                        Some(BrowseEvent::Expired(_)) => {}
+                        None => break,
+                    }
                 }
             }
-        };
+        }
 
-        log::trace!("{:?} sending {:?}...", self.socket.local_addr(), raw_query);
-        if let Err(err) = self.socket.send_to(&raw_query, self.dst_addr).await {
-            log::error!("Failed to send mDNS packet {}", err);
+        if ips.is_empty() {
+            return Err(ERR_QUERY_TIMEOUT.to_owned());
         }
+        Ok(ips)
     }
 
-    async fn start(
-        mut closed_rx: mpsc::Receiver<()>,
-        close_server: Arc<atomic::AtomicBool>,
-        socket: Arc<UdpSocket>,
-        local_names: Vec<String>,
-        dst_addr: SocketAddr,
-        queries: Arc<Mutex<Vec<Query>>>,
-    ) -> Result<(), Error> {
-        log::info!("enter loop and listening {:?}", socket.local_addr());
+    /// lookup_addr looks up the host name of ip with a PTR query for its
+    /// reverse name in in-addr.arpa or ip6.arpa, giving up with
+    /// ERR_QUERY_TIMEOUT if no answer is received within timeout. An answer
+    /// without a PTR body fails with ERR_UNEXPECTED_ANSWER.
+    pub async fn lookup_addr(&self, ip: IpAddr, timeout: Duration) -> Result<Name, Error> {
+        let name = reverse_name(ip);
+        let query = self.query_scheduled(&name, &[DNSType::PTR], self.query_schedule);
+        match tokio::time::timeout(timeout, query).await {
+            Ok(res) => match res?.answer.body {
+                Some(ResourceData::PTR(ptr)) => Ok(ptr.ptr),
+                _ => Err(ERR_UNEXPECTED_ANSWER.to_owned()),
+            },
+            Err(_) => Err(ERR_QUERY_TIMEOUT.to_owned()),
+        }
+    }
 
-        let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
-        let (mut n, mut src);
+    /// browse continuously queries for the given name and type, yielding
+    /// every answer received. Answers are queried for again as they near
+    /// the end of their TTL, and an Expired event is yielded for those that
+    /// are not refreshed in time. Dropping the returned Browse stops the
+    /// queries.
+    pub async fn browse(&self, name: &str, typ: DNSType) -> Result<Browse, Error> {
+        self.browse_scheduled(name, typ, self.query_schedule).await
+    }
 
-        loop {
-            tokio::select! {
-                _ = closed_rx.recv() => {
-                    log::info!("Closing server connection");
+    /// browse_scheduled is like browse, but retransmits on the given schedule
+    /// instead of the one from the Config, such as a slower one for
+    /// discovery in the background
+    pub async fn browse_scheduled(
+        &self,
+        name: &str,
+        typ: DNSType,
+        schedule: QuerySchedule,
+    ) -> Result<Browse, Error> {
+        let events = self
+            .register_query(name, &[typ], true, true, schedule)
+            .await?;
+
+        Ok(Browse { events })
+    }
+
+    /// observe yields every answer received in a response, whether or not it
+    /// matches a query, so the records on the network can be listened to
+    /// passively. Answers are dropped while the reader falls behind.
+    /// Dropping the returned Observe stops it.
+    pub async fn observe(&self) -> Result<Observe, Error> {
+        if self.responder_only {
+            return Err(ERR_RESPONDER_ONLY.to_owned());
+        }
+        if self.is_server_closed.load(atomic::Ordering::SeqCst) {
+            return Err(ERR_CONNECTION_CLOSED.to_owned());
+        }
+
+        let (answers_tx, answers) = mpsc::channel(OBSERVE_CHANNEL_SIZE);
+        let mut observers = self.observers.lock().await;
+        observers.retain(|o| !o.is_closed());
+        observers.push(answers_tx);
+
+        Ok(Observe { answers })
+    }
+
+    /// question_counters returns how many questions have been received from
+    /// other hosts, and how many of them dropped by the per-source limit
+    pub async fn question_counters(&self) -> QuestionCounters {
+        *self.question_counters.lock().await
+    }
+
+    /// pending_queries lists the queries still waiting for answers, including
+    /// the browses in progress
+    pub async fn pending_queries(&self) -> Vec<PendingQuery> {
+        let mut queries = self.queries.lock().await;
+        prune_queries(&mut queries);
+
+        queries
+            .iter()
+            .map(|q| PendingQuery {
+                name: q.name_with_suffix.clone(),
+                types: q.types.clone(),
+                age: q.started.elapsed(),
+                attempts: q.attempts,
+            })
+            .collect()
+    }
+
+    async fn register_query(
+        &self,
+        name: &str,
+        types: &[DNSType],
+        multiple_answers: bool,
+        cache_answers: bool,
+        schedule: QuerySchedule,
+    ) -> Result<QueryEvents, Error> {
+        if self.responder_only {
+            return Err(ERR_RESPONDER_ONLY.to_owned());
+        }
+        if types.is_empty() || types.contains(&DNSType::OPT) {
+            return Err(ERR_UNSUPPORTED_QUERY_TYPE.to_owned());
+        }
+
+        {
+            if self.is_server_closed.load(atomic::Ordering::SeqCst) {
+                return Err(ERR_CONNECTION_CLOSED.to_owned());
+            }
+        }
+
+        let name_with_suffix = Name::new(&(name.to_owned() + "."))?;
+
+        let (query_tx, events) = mpsc::channel(if multiple_answers {
+            BROWSE_CHANNEL_SIZE
+        } else {
+            1
+        });
+        let query_rx = QueryEvents {
+            events,
+            queries: Arc::clone(&self.queries),
+            query_added: Arc::clone(&self.query_added),
+        };
+        {
+            let mut queries = self.queries.lock().await;
+            prune_queries(&mut queries);
+
+            if !cache_answers {
+                if let Some(q) = queries.iter_mut().find(|q| {
+                    q.cache.is_none()
+                        && q.multiple_answers == multiple_answers
+                        && q.types == types
+                        && q.name_with_suffix.eq_ignore_ascii_case(&name_with_suffix)
+                }) {
+                    log::trace!("Joining pending query for {}", name_with_suffix);
+                    q.query_result_chans.push(query_tx);
+                    // the shared questions go out as often as the most
+                    // eager caller wants
+                    q.interval = std::cmp::min(q.interval, schedule.interval);
+                    q.max_interval = std::cmp::min(
+                        q.max_interval,
+                        std::cmp::max(schedule.max_interval, schedule.interval),
+                    );
+                    q.next_query = std::cmp::min(q.next_query, Instant::now() + q.interval);
+                    return Ok(query_rx);
+                }
+            }
+
+            queries.push(Query {
+                name_with_suffix: name_with_suffix.clone(),
+                types: types.to_vec(),
+                query_result_chans: vec![query_tx],
+                multiple_answers,
+                cache: if cache_answers { Some(vec![]) } else { None },
+                // due at once, the server loop sends the first question
+                next_query: Instant::now(),
+                interval: schedule.interval,
+                max_interval: std::cmp::max(schedule.max_interval, schedule.interval),
+                started: Instant::now(),
+                attempts: 0,
+                max_attempts: if cache_answers {
+                    0
+                } else {
+                    self.max_query_attempts
+                },
+            });
+        }
+        self.query_added.notify_one();
+
+        Ok(query_rx)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn start(
+        mut closed_rx: mpsc::Receiver<()>,
+        mut packets: mpsc::Receiver<std::io::Result<recv::Packet>>,
+        cancellation_token: CancellationToken,
+        close_server: Arc<atomic::AtomicBool>,
+        responder: Responder,
+        queries: Arc<Mutex<Vec<Query>>>,
+        query_added: Arc<Notify>,
+        observers: Arc<Mutex<Vec<mpsc::Sender<QueryResult>>>>,
+        follow_cname: bool,
+        responder_only: bool,
+    ) -> Result<(), Error> {
+        for link in &responder.links {
+            log::info!("enter loop and listening {:?}", link.socket.local_addr());
+        }
+
+        let mut truncated: HashMap<SocketAddr, TruncatedQuery> = HashMap::new();
+        let rescan_interval = responder.interface_rescan_interval;
+        let mut next_rescan =
+            (!rescan_interval.is_zero()).then(|| Instant::now() + rescan_interval);
+        let refresh_interval = responder.membership_refresh_interval;
+        let mut next_refresh =
+            (!refresh_interval.is_zero()).then(|| Instant::now() + refresh_interval);
+
+        loop {
+            let goodbyes: Vec<String> = responder.goodbyes.lock().await.drain(..).collect();
+            if !goodbyes.is_empty() {
+                responder.send_goodbyes(&goodbyes, false).await;
+            }
+
+            let next_deadline = truncated.values().map(|t| t.deadline).min();
+            let mut next_answer = None;
+            for link in &responder.all_links().await {
+                if let Some(d) = &*link.delayed.lock().await {
+                    next_answer =
+                        Some(next_answer.map_or(d.deadline, |n: Instant| n.min(d.deadline)));
+                }
+            }
+            let next_probe = responder
+                .probes
+                .lock()
+                .await
+                .iter()
+                .map(|p| p.next_probe)
+                .chain(
+                    responder
+                        .announcements
+                        .lock()
+                        .await
+                        .iter()
+                        .map(|a| a.next_announcement),
+                )
+                .min();
+            let next_query = if responder_only {
+                None
+            } else {
+                queries.lock().await.iter().map(|q| q.next_event()).min()
+            };
+
+            let packet;
+            tokio::select! {
+                // None, when the DNSConn is dropped, shuts down too
+                _ = closed_rx.recv() => {
+                    log::info!("Closing server connection");
+                    responder.say_goodbye().await;
+                    close_server.store(true, atomic::Ordering::SeqCst);
+
+                    return Ok(());
+                }
+
+                _ = cancellation_token.cancelled() => {
+                    log::info!("Closing server connection");
+                    responder.say_goodbye().await;
                     close_server.store(true, atomic::Ordering::SeqCst);
 
                     return Ok(());
                 }
 
-                result = socket.recv_from(&mut b) => {
-                    match result{
-                        Ok((len, addr)) => {
-                            n = len;
-                            src = addr;
+                _ = tokio::time::sleep_until(next_deadline.unwrap_or_else(Instant::now)), if next_deadline.is_some() => {
+                    let now = Instant::now();
+                    let expired: Vec<SocketAddr> = truncated
+                        .iter()
+                        .filter(|(_, t)| t.deadline <= now)
+                        .map(|(addr, _)| *addr)
+                        .collect();
+                    for addr in expired {
+                        if let Some(t) = truncated.remove(&addr) {
+                            responder.answer_questions(&t.link, &t.questions, &t.known_answers, addr).await;
+                        }
+                    }
+                    continue;
+                }
+
+                _ = tokio::time::sleep_until(next_probe.unwrap_or_else(Instant::now)), if next_probe.is_some() => {
+                    responder.handle_due_probes().await;
+                    responder.handle_due_announcements().await;
+                    continue;
+                }
+
+                _ = tokio::time::sleep_until(next_answer.unwrap_or_else(Instant::now)), if next_answer.is_some() => {
+                    responder.send_delayed_answers().await;
+                    continue;
+                }
+
+                _ = tokio::time::sleep_until(next_query.unwrap_or_else(Instant::now)), if next_query.is_some() => {
+                    handle_due_queries(&responder, &queries).await;
+                    continue;
+                }
+
+                _ = tokio::time::sleep_until(next_rescan.unwrap_or_else(Instant::now)), if next_rescan.is_some() => {
+                    responder.rescan_interfaces().await;
+                    next_rescan = Some(Instant::now() + rescan_interval);
+                    continue;
+                }
+
+                _ = tokio::time::sleep_until(next_refresh.unwrap_or_else(Instant::now)), if next_refresh.is_some() => {
+                    responder.refresh_memberships().await;
+                    next_refresh = Some(Instant::now() + refresh_interval);
+                    continue;
+                }
+
+                _ = query_added.notified() => continue,
+
+                result = packets.recv() => {
+                    match result {
+                        Some(Ok(p)) => {
+                            let addr = p.src;
                             log::info!("Received new connection from {:?}", addr);
+                            if responder.validate_source && !on_local_link(addr.ip(), p.ttl) {
+                                log::trace!("Dropping packet from {} off the local link", addr);
+                                continue;
+                            }
+                            if !responder.source_allowed(addr.ip()) {
+                                log::trace!("Dropping packet from {}, which is not an allowed source", addr);
+                                continue;
+                            }
+                            if responder.own_packet(&p.data, addr).await {
+                                log::trace!("Dropping our own packet looped back from {}", addr);
+                                continue;
+                            }
+                            packet = p;
                         },
 
-                        Err(err) => {
-                            log::error!("Error receiving from socket connection: {:?}", err);
-                            return Err(Error::new(err.to_string()))
+                        // Only the reader of the link that failed has
+                        // stopped; the other links are still served.
+                        Some(Err(err)) => {
+                            log::error!("Error receiving from socket connection, no longer receiving on it: {:?}", err);
+                            continue;
                         },
+
+                        None => continue,
                     }
                 }
             }
 
-            log::trace!("recv bytes {:?} from {}", &b[..n], src);
+            let src = packet.src;
+            let link = &responder
+                .receiving_link(&packet.link, packet.interface)
+                .await;
+            if let Some(interface) = &link.interface {
+                if !responder.interfaces.allows(&interface.name) {
+                    log::trace!("Dropping packet from {} on {}", src, interface.name);
+                    continue;
+                }
+            }
+            log::trace!(
+                "recv bytes {:?} from {} to {:?} on interface {:?}",
+                &packet.data,
+                src,
+                packet.dst,
+                packet.interface
+            );
 
             let mut p = Parser::default();
-            if let Err(err) = p.start(&b[..n]) {
-                log::error!("Failed to parse mDNS packet {}", err);
+            let header = match p.start(&packet.data) {
+                Ok(h) => h,
+                Err(err) => {
+                    log::error!("Failed to parse mDNS packet {}", err);
+                    continue;
+                }
+            };
+
+            if !header.response
+                && !responder
+                    .question_rate_allowed(src.ip(), p.header.questions)
+                    .await
+            {
+                log::trace!(
+                    "Dropping query from {}, which is asking too many questions",
+                    src
+                );
                 continue;
             }
 
-            run(&mut p, &socket, &local_names, src, dst_addr, &queries).await
+            if !header.response {
+                if let Some(mut t) = truncated.remove(&src) {
+                    if p.header.questions == 0 {
+                        // A continuation of the known answers of a truncated
+                        // query.
+                        match p.skip_all_questions().and_then(|_| p.all_answers()) {
+                            Ok(answers) => t.known_answers.extend(answers),
+                            Err(err) => log::warn!("Failed to parse mDNS packet {}", err),
+                        }
+                        if header.truncated {
+                            t.deadline = truncated_query_deadline();
+                        }
+                        truncated.insert(src, t);
+                        continue;
+                    }
+
+                    // A new query; the truncated one won't see more packets.
+                    responder
+                        .answer_questions(&t.link, &t.questions, &t.known_answers, src)
+                        .await;
+                }
+
+                if header.truncated {
+                    match p.all_questions().and_then(|q| Ok((q, p.all_answers()?))) {
+                        Ok((questions, known_answers)) => {
+                            log::trace!(
+                                "Waiting for known answers of truncated query from {}",
+                                src
+                            );
+                            truncated.insert(
+                                src,
+                                TruncatedQuery {
+                                    link: Arc::clone(link),
+                                    questions,
+                                    known_answers,
+                                    deadline: truncated_query_deadline(),
+                                },
+                            );
+                        }
+                        Err(err) => log::warn!("Failed to parse mDNS packet {}", err),
+                    }
+                    continue;
+                }
+            }
+
+            run(
+                &mut p,
+                &responder,
+                link,
+                src,
+                &queries,
+                &observers,
+                follow_cname,
+                responder_only,
+            )
+            .await
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run(
     p: &mut Parser<'_>,
-    socket: &Arc<UdpSocket>,
-    local_names: &[String],
+    responder: &Responder,
+    link: &Link,
     src: SocketAddr,
-    dst_addr: SocketAddr,
     queries: &Arc<Mutex<Vec<Query>>>,
+    observers: &Arc<Mutex<Vec<mpsc::Sender<QueryResult>>>>,
+    follow_cname: bool,
+    responder_only: bool,
 ) {
-    for _ in 0..=MAX_MESSAGE_RECORDS {
-        let q = match p.question() {
-            Ok(q) => q,
+    let mut questions = vec![];
+    for _ in 0..p.header.questions {
+        match p.question() {
+            Ok(q) => questions.push(q),
             Err(err) => {
-                if err == *ERR_SECTION_DONE {
-                    log::trace!("Parsing has completed");
-                    break;
-                } else {
-                    log::error!("Failed to parse mDNS packet {}", err);
-                    return;
-                }
+                log::error!("Failed to parse mDNS packet {}", err);
+                return;
             }
         };
+    }
+    let response = p.header.header().response;
+    if response {
+        responder.check_conflicts(link, p.msg, src).await;
+    } else if p.header.authorities > 0 {
+        responder.check_simultaneous_probes(link, p.msg, src).await;
+    }
+    // The questions of a response are ignored (RFC 6762 Section 6).
+    if !response && !responder.querier_only {
+        if src.port() != link.dst_addr.port() {
+            responder
+                .answer_legacy_unicast(link, p.header.id, &questions, src)
+                .await;
+        } else if !questions.is_empty() && p.header.authorities > 0 {
+            // A probe, whose answers defend our names and are not held back
+            // (RFC 6762 Section 6).
+            responder.answer_probe(link, &questions, src).await;
+        } else if !questions.is_empty() {
+            let known_answers = answer_section(p.msg);
+            responder
+                .answer_questions(link, &questions, &known_answers, src)
+                .await;
+        }
+    }
+    // The answers of a query are the querier's known answers, which must not
+    // resolve our own queries for the same records.
+    if responder_only || !response {
+        return;
+    }
 
-        for local_name in local_names {
-            if local_name == &q.name.data {
-                log::trace!("Found local name: {} to send answer", local_name);
-                if let Err(e) = send_answer(socket, &q.name.data, src.ip(), dst_addr).await {
-                    log::error!("Error sending answer to client: {:?}", e);
-                    continue;
-                };
+    // The parser only moves on to the next section once the current one is
+    // exhausted.
+    if let Err(err) = p.skip_all_questions() {
+        log::warn!("Failed to parse mDNS packet {}", err);
+        return;
+    }
 
-                log::trace!(
-                    "Sent answer to local name: {} to dst addr {:?}",
-                    local_name,
-                    dst_addr
-                );
+    for _ in 0..p.header.answers {
+        let a = match p.answer_header() {
+            Ok(a) => a,
+            Err(err) => {
+                log::warn!("Failed to parse mDNS packet {}", err);
+                return;
             }
+        };
+
+        if let Err(err) = handle_answer(p, &a, src, queries, observers, follow_cname).await {
+            log::warn!("Failed to parse mDNS packet {}", err);
+            return;
+        }
+
+        if let Err(err) = p.skip_answer() {
+            log::warn!("Failed to parse mDNS packet {}", err);
+            return;
         }
     }
 
-    for _ in 0..=MAX_MESSAGE_RECORDS {
-        let a = match p.answer_header() {
+    // Responders often put the address records for an SRV or PTR answer in
+    // the additional section, so look there as well.
+    if let Err(err) = p.skip_all_answers().and_then(|_| p.skip_all_authorities()) {
+        log::warn!("Failed to parse mDNS packet {}", err);
+        return;
+    }
+
+    for _ in 0..p.header.additionals {
+        let a = match p.additional_header() {
             Ok(a) => a,
             Err(err) => {
-                if err == *ERR_SECTION_DONE {
-                    return;
-                } else {
-                    log::warn!("Failed to parse mDNS packet {}", err);
-                    return;
-                }
+                log::warn!("Failed to parse mDNS packet {}", err);
+                return;
             }
         };
 
-        if a.typ != DNSType::A && a.typ != DNSType::AAAA {
-            continue;
+        if let Err(err) = handle_answer(p, &a, src, queries, observers, follow_cname).await {
+            log::warn!("Failed to parse mDNS packet {}", err);
+            return;
+        }
+
+        if let Err(err) = p.skip_additional() {
+            log::warn!("Failed to parse mDNS packet {}", err);
+            return;
+        }
+    }
+}
+
+// authority_section returns the records in the authority section of msg:
+// the records proposed for the names probed for, for a probe.
+fn authority_section(msg: &[u8]) -> Vec<Resource> {
+    let mut p = Parser::default();
+    match p
+        .start(msg)
+        .and_then(|_| p.skip_all_questions())
+        .and_then(|_| p.skip_all_answers())
+        .and_then(|_| p.all_authorities())
+    {
+        Ok(authorities) => authorities,
+        Err(err) => {
+            log::warn!("Failed to parse authority section {}", err);
+            vec![]
+        }
+    }
+}
+
+// tie_break compares two sets of records proposed for a name by hosts
+// probing for it at once (RFC 6762 Section 8.2): each is sorted by class,
+// type and uncompressed rdata, and they are then compared record by record,
+// a set running out first being the earlier. The host whose set is later
+// wins.
+fn tie_break(ours: &[Resource], theirs: &[Resource]) -> std::cmp::Ordering {
+    let sorted = |records: &[Resource]| {
+        let mut keys: Vec<(u16, u16, Vec<u8>)> = records
+            .iter()
+            .map(|r| {
+                let mut rdata = vec![];
+                if let Some(body) = &r.body {
+                    if body.pack(&mut rdata, &mut None, 0).is_err() {
+                        rdata.clear();
+                    }
+                }
+                (r.header.class.0, r.header.typ.0, rdata)
+            })
+            .collect();
+        keys.sort();
+        keys
+    };
+    sorted(ours).cmp(&sorted(theirs))
+}
+
+// answer_section returns the records in the answer section of msg: the
+// records the querier already knows, for a query.
+fn answer_section(msg: &[u8]) -> Vec<Resource> {
+    let mut p = Parser::default();
+    match p
+        .start(msg)
+        .and_then(|_| p.skip_all_questions())
+        .and_then(|_| p.all_answers())
+    {
+        Ok(answers) => answers,
+        Err(err) => {
+            log::warn!("Failed to parse answer section {}", err);
+            vec![]
         }
+    }
+}
+
+// known_answer reports whether known_answers holds r with at least half of
+// its TTL left, in which case the querier needs no answer with it (RFC 6762
+// Section 7.1).
+fn known_answer(r: &Resource, known_answers: &[Resource]) -> bool {
+    known_answers
+        .iter()
+        .any(|k| same_record(k, r) && k.header.ttl >= r.header.ttl / 2)
+}
+
+// same_record reports whether a and b are the same record, that is they
+// have the same name, type, class and rdata, whatever their TTLs.
+fn same_record(a: &Resource, b: &Resource) -> bool {
+    a.header.name.eq_ignore_ascii_case(&b.header.name)
+        && a.header.typ == b.header.typ
+        && a.header.class == b.header.class
+        && a.body == b.body
+}
+
+// truncated_query_deadline is when to answer a truncated query if no more
+// known answers arrive, 400-500ms from now (RFC 6762 Section 7.2).
+fn truncated_query_deadline() -> Instant {
+    Instant::now() + Duration::from_millis(rand::thread_rng().gen_range(400..=500))
+}
+
+// handle_answer resolves the pending queries matched by the record whose
+// header was just parsed by p from a response, and passes it on to the
+// observers. The record body is left for the caller to skip.
+async fn handle_answer(
+    p: &Parser<'_>,
+    a: &ResourceHeader,
+    src: SocketAddr,
+    queries: &Arc<Mutex<Vec<Query>>>,
+    observers: &Arc<Mutex<Vec<mpsc::Sender<QueryResult>>>>,
+    follow_cname: bool,
+) -> Result<(), Error> {
+    if follow_cname && a.typ == DNSType::CNAME {
+        let mut cname = CNAMEResource::default();
+        cname.unpack(p.msg, p.off, a.length as usize)?;
 
         let mut qs = queries.lock().await;
-        for j in (0..qs.len()).rev() {
-            if qs[j].name_with_suffix == a.name.data {
-                let _ = qs[j]
-                    .query_result_chan
-                    .send(QueryResult {
-                        answer: a.clone(),
-                        addr: src,
-                    })
-                    .await;
+        for q in qs.iter_mut() {
+            if q.name_with_suffix.eq_ignore_ascii_case(&a.name) && !q.matches_type(a.typ) {
+                log::trace!("Following CNAME {} to {}", a.name, cname.cname);
+                q.name_with_suffix = cname.cname.clone();
+            }
+        }
+    }
+
+    let (body, _) = unpack_resource_body(a.typ, p.msg, p.off, a.length as usize)?;
+    let answer = Resource {
+        header: a.clone(),
+        body: Some(body),
+    };
+
+    let result = QueryResult {
+        ip: answer.ip(),
+        answer,
+        ttl: Duration::from_secs(a.ttl.into()),
+        received: std::time::Instant::now(),
+        addr: src,
+    };
+
+    {
+        let mut observers = observers.lock().await;
+        observers.retain(|o| !o.is_closed());
+        for observer in observers.iter() {
+            let _ = observer.try_send(result.clone());
+        }
+    }
+
+    let mut qs = queries.lock().await;
+    prune_queries(&mut qs);
+    for j in (0..qs.len()).rev() {
+        if qs[j].name_with_suffix.eq_ignore_ascii_case(&a.name) && qs[j].matches_type(a.typ) {
+            if let Some(cache) = &mut qs[j].cache {
+                let rdata = p
+                    .msg
+                    .get(p.off..p.off + a.length as usize)
+                    .ok_or_else(|| ERR_RESOURCE_LEN.to_owned())?
+                    .to_vec();
+                cache.retain(|c| c.result.answer.header.typ != a.typ || c.rdata != rdata);
+                cache.push(CachedAnswer::new(result.clone(), rdata));
+            }
+
+            for chan in &qs[j].query_result_chans {
+                send_event(chan, BrowseEvent::Answer(result.clone()));
+            }
+            if !qs[j].multiple_answers {
                 qs.remove(j);
             }
         }
     }
+
+    Ok(())
+}
+
+// socket_addrs returns the address to bind a socket to, and the multicast
+// group and port it sends to, for each version of IP mDNS is run over.
+fn socket_addrs(
+    addr: SocketAddr,
+    ip_version: Option<IpVersion>,
+    multicast_addr: Option<SocketAddr>,
+) -> Result<Vec<(SocketAddr, SocketAddr)>, Error> {
+    let ipv6 = match ip_version {
+        Some(IpVersion::V4) => vec![false],
+        Some(IpVersion::V6) => vec![true],
+        Some(IpVersion::Dual) => vec![addr.is_ipv6(), !addr.is_ipv6()],
+        None => vec![addr.is_ipv6()],
+    };
+
+    let mut addrs = vec![];
+    for ipv6 in ipv6 {
+        let (unspecified, default_dst_addr): (IpAddr, SocketAddr) = if ipv6 {
+            (Ipv6Addr::UNSPECIFIED.into(), DEFAULT_DEST_ADDR_V6.parse()?)
+        } else {
+            (Ipv4Addr::UNSPECIFIED.into(), DEFAULT_DEST_ADDR.parse()?)
+        };
+        let local_addr = if addr.is_ipv6() == ipv6 {
+            addr
+        } else {
+            SocketAddr::new(unspecified, addr.port())
+        };
+        let dst_addr = match multicast_addr {
+            Some(dst_addr) if dst_addr.is_ipv6() == ipv6 => dst_addr,
+            Some(dst_addr) => SocketAddr::new(default_dst_addr.ip(), dst_addr.port()),
+            None => default_dst_addr,
+        };
+        if !dst_addr.ip().is_multicast() {
+            return Err(ERR_INVALID_MULTICAST_ADDR.to_owned());
+        }
+        addrs.push((local_addr, dst_addr));
+    }
+
+    // the multicast address must be of a version of IP in use
+    if let Some(multicast_addr) = multicast_addr {
+        if !addrs
+            .iter()
+            .any(|(_, dst_addr)| *dst_addr == multicast_addr)
+        {
+            return Err(ERR_INVALID_MULTICAST_ADDR.to_owned());
+        }
+    }
+    Ok(addrs)
 }
 
-async fn interface_for_remote(remote: String) -> Result<std::net::IpAddr, Error> {
-    let conn = UdpSocket::bind(remote).await?;
-    let local_addr = conn.local_addr()?;
+// bind_links binds a socket for each of addrs, the address to bind it to and
+// the multicast group and port it sends to, which joins the group if
+// join_multicast is set. With socket_per_interface set as well, there is a
+// socket for each interface with an address of the version of IP of the
+// group instead, joined to the group on that interface only. Port 0 in addrs
+// is replaced by the port bound. It returns the links of the sockets, and
+// the (group, interface address) pairs joined. Only the interfaces filter
+// allows are joined on.
+#[allow(clippy::type_complexity)]
+fn bind_links(
+    addrs: &mut [(SocketAddr, SocketAddr)],
+    join_multicast: bool,
+    socket_per_interface: bool,
+    filter: &InterfaceFilter,
+) -> Result<(Vec<Link>, Vec<(IpAddr, IpAddr)>), Error> {
+    let mut links = vec![];
+    let mut multicast_groups = vec![];
+    for (addr, dst_addr) in addrs.iter_mut() {
+        if !(join_multicast && socket_per_interface) {
+            let (socket, memberships) =
+                bind(*addr, join_multicast.then_some(dst_addr.ip()), None, filter)?;
+            *addr = socket.local_addr()?;
+            multicast_groups.extend(memberships.iter().map(|m| (m.group, m.addr)));
+            let mut link = Link::new(Arc::new(socket), *dst_addr);
+            link.memberships = Mutex::new(memberships);
+            links.push(link);
+            continue;
+        }
 
-    Ok(local_addr.ip())
+        let mut interfaces = link_interfaces(dst_addr.is_ipv6())?;
+        interfaces.retain(|i| filter.allows(&i.name));
+        let mut bound = 0;
+        for interface in &interfaces {
+            let (socket, memberships) =
+                match bind(*addr, Some(dst_addr.ip()), Some(interface), filter) {
+                    Ok(b) => b,
+                    Err(err) => {
+                        log::error!("Error binding socket for {}: {:?}", interface.name, err);
+                        continue;
+                    }
+                };
+            // the sockets of the other interfaces share the port bound
+            *addr = socket.local_addr()?;
+            multicast_groups.extend(memberships.iter().map(|m| (m.group, m.addr)));
+            let mut link = Link::new(Arc::new(socket), *dst_addr);
+            link.interface = Some(interface.clone());
+            link.memberships = Mutex::new(memberships);
+            links.push(link);
+            bound += 1;
+        }
+        if bound == 0 && !interfaces.is_empty() {
+            return Err(ERR_JOINING_MULTICAST_GROUP.to_owned());
+        }
+    }
+    Ok((links, multicast_groups))
 }
 
-async fn send_answer(
-    socket: &Arc<UdpSocket>,
-    name: &str,
-    dst: IpAddr,
-    dst_addr: SocketAddr,
-) -> Result<(), Error> {
-    let raw_answer = {
-        let mut msg = Message {
-            header: Header {
-                response: true,
-                authoritative: true,
-                ..Default::default()
-            },
+// link_interfaces returns the interfaces with an IPv6 address if ipv6 is
+// set, or with an IPv4 one if not.
+fn link_interfaces(ipv6: bool) -> Result<Vec<LinkInterface>, Error> {
+    let mut interfaces = all_link_interfaces()?;
+    interfaces.retain(|l| l.addrs.iter().any(|ip| ip.is_ipv6() == ipv6));
+    Ok(interfaces)
+}
 
-            answers: vec![Resource {
-                header: ResourceHeader {
-                    typ: DNSType::A,
-                    class: DNSCLASS_INET,
-                    name: Name::new(name)?,
-                    ttl: RESPONSE_TTL,
-                    ..Default::default()
-                },
-                body: Some(Box::new(AResource {
-                    a: match dst {
-                        IpAddr::V4(ip) => ip.octets(),
-                        IpAddr::V6(_) => return Err(Error::new("unexpected IpV6 addr".to_owned())),
-                    },
-                })),
-            }],
-            ..Default::default()
+// all_link_interfaces returns the interfaces with an address.
+fn all_link_interfaces() -> Result<Vec<LinkInterface>, Error> {
+    let mut interfaces: Vec<LinkInterface> = vec![];
+    for i in ifaces::ifaces().map_err(|e| Error::new(e.to_string()))? {
+        let addr = match i.addr {
+            Some(addr) => addr.ip(),
+            None => continue,
         };
+        match interfaces.iter_mut().find(|l| l.name == i.name) {
+            Some(l) => l.addrs.push(addr),
+            None => interfaces.push(LinkInterface {
+                index: interface_index(&i.name),
+                name: i.name,
+                addrs: vec![addr],
+            }),
+        }
+    }
+    Ok(interfaces)
+}
+
+// bind creates a socket bound to addr that, if a group is given, has joined
+// it on interface, or if there is none on every interface with an address of
+// its version of IP that filter allows. It returns the socket and the
+// memberships it has.
+fn bind(
+    addr: SocketAddr,
+    group: Option<IpAddr>,
+    interface: Option<&LinkInterface>,
+    filter: &InterfaceFilter,
+) -> Result<(UdpSocket, Vec<Membership>), Error> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+
+    // Other responders on the host, such as the one of the system, are
+    // bound to the mDNS port too. SO_REUSEPORT has sockets share it on Unix;
+    // Windows has no SO_REUSEPORT, SO_REUSEADDR alone sharing it there with
+    // every socket bound without exclusive use (SO_EXCLUSIVEADDRUSE), which
+    // we leave unset so as not to lock others out in turn.
+    socket.set_reuse_address(true)?;
+    #[cfg(target_family = "unix")]
+    socket.set_reuse_port(true)?;
+
+    // mDNS packets are sent with an IP TTL of 255, so that receivers can
+    // tell they come from the local link (RFC 6762 Section 11).
+    if addr.is_ipv6() {
+        // Keep IPv4 packets, mapped to IPv6 addresses, off the socket.
+        socket.set_only_v6(true)?;
+        socket.set_unicast_hops_v6(255)?;
+        socket.set_multicast_hops_v6(255)?;
+    } else {
+        socket.set_ttl(255)?;
+        socket.set_multicast_ttl_v4(255)?;
+    }
+    socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+    socket.set_nonblocking(true)?;
+    if let Some(device) = &filter.device {
+        bind_device(&socket, device)?;
+    }
+    socket.bind(&SockAddr::from(addr)).map_err(bind_error)?;
+
+    let mut joined = vec![];
+    if let (Some(group), Some(interface)) = (group, interface) {
+        let addr = join_interface(&socket, group, interface)?;
+        joined.push(Membership {
+            group,
+            addr,
+            index: interface.index,
+        });
+        log::trace!("Connected to interface {}", interface.name);
+    } else if let Some(group) = group {
+        let (memberships, interface_count) = memberships(group, filter)?;
+        let mut join_error_count = 0;
+        for m in memberships {
+            if let Err(e) = m.join(socket2::SockRef::from(&socket)) {
+                log::error!("Error connecting multicast, error: {:?}", e);
+                join_error_count += 1;
+                continue;
+            }
+            log::trace!("Connected to interface address {:?}", m.addr);
+            joined.push(m);
+        }
+
+        if join_error_count >= interface_count {
+            return Err(ERR_JOINING_MULTICAST_GROUP.to_owned());
+        }
+    }
 
-        msg.pack()?
+    Ok((UdpSocket::from_std(socket.into())?, joined))
+}
+
+// memberships returns the memberships of group to have on every interface
+// filter allows with an address of its version of IP: one for each address
+// for IPv4, and one for each interface, whatever its number of addresses,
+// for IPv6. It also returns the number of interface addresses filter allows,
+// of either version.
+fn memberships(group: IpAddr, filter: &InterfaceFilter) -> Result<(Vec<Membership>, usize), Error> {
+    let mut interfaces = match ifaces::ifaces() {
+        Ok(e) => e,
+        Err(e) => {
+            log::error!("Error getting interfaces: {:?}", e);
+            return Err(Error::new(e.to_string()));
+        }
     };
+    interfaces.retain(|i| filter.allows(&i.name));
+
+    let mut memberships: Vec<Membership> = vec![];
+    for interface in &interfaces {
+        let addr = match interface.addr {
+            Some(addr) if addr.is_ipv6() == group.is_ipv6() => addr.ip(),
+            _ => continue,
+        };
+        let m = Membership {
+            group,
+            addr,
+            index: interface_index(&interface.name),
+        };
+        if !memberships.iter().any(|j| j.same(&m)) {
+            memberships.push(m);
+        }
+    }
+    Ok((memberships, interfaces.len()))
+}
 
-    socket.send_to(&raw_answer, dst_addr).await?;
-    log::trace!("sent answer from {} to {}", dst, dst_addr);
+// bind_error is the error for binding a socket failing with err. On
+// Windows, the port being in exclusive use by another socket fails it with
+// WSAEACCES.
+#[cfg(windows)]
+fn bind_error(err: std::io::Error) -> Error {
+    const WSAEACCES: i32 = 10013;
+    if err.raw_os_error() == Some(WSAEACCES) {
+        return ERR_PORT_IN_EXCLUSIVE_USE.to_owned();
+    }
+    err.into()
+}
 
-    Ok(())
+#[cfg(not(windows))]
+fn bind_error(err: std::io::Error) -> Error {
+    err.into()
+}
+
+// bind_device binds socket to the device named device (SO_BINDTODEVICE).
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(socket: &socket2::Socket, device: &str) -> Result<(), Error> {
+    socket.bind_device(Some(device.as_bytes()))?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_device(_socket: &socket2::Socket, _device: &str) -> Result<(), Error> {
+    Err(ERR_BIND_DEVICE_UNSUPPORTED.to_owned())
+}
+
+// join_interface joins group on interface only, and has socket send its
+// multicast packets out of it. It returns the address of the interface the
+// group is joined on.
+fn join_interface(
+    socket: &socket2::Socket,
+    group: IpAddr,
+    interface: &LinkInterface,
+) -> Result<IpAddr, Error> {
+    let addr = interface
+        .addrs
+        .iter()
+        .find(|ip| ip.is_ipv6() == group.is_ipv6())
+        .copied()
+        .ok_or_else(|| ERR_JOINING_MULTICAST_GROUP.to_owned())?;
+    match (group, addr) {
+        (IpAddr::V4(group), IpAddr::V4(addr)) => {
+            socket.join_multicast_v4(&group, &addr)?;
+            socket.set_multicast_if_v4(&addr)?;
+        }
+        (IpAddr::V6(group), _) => {
+            socket.join_multicast_v6(&group, interface.index)?;
+            socket.set_multicast_if_v6(interface.index)?;
+        }
+        _ => return Err(ERR_JOINING_MULTICAST_GROUP.to_owned()),
+    }
+    // Otherwise Linux hands the socket the packets for the group arriving
+    // on any interface it has been joined on, by whichever socket.
+    recv::only_joined_groups(socket, group.is_ipv6())?;
+    Ok(addr)
+}
+
+// interface_index returns the index of the interface called name, or 0,
+// which leaves the choice of interface to the system, if it is unknown.
+#[cfg(unix)]
+fn interface_index(name: &str) -> u32 {
+    match std::ffi::CString::new(name) {
+        Ok(name) => unsafe { libc::if_nametoindex(name.as_ptr()) },
+        Err(_) => 0,
+    }
+}
+
+#[cfg(not(unix))]
+fn interface_index(_name: &str) -> u32 {
+    0
+}
+
+// static_records fills in the name, type, class and TTL of the records of
+// the Config.
+fn static_records(
+    records: &HashMap<String, Vec<Resource>>,
+    response_ttl: u32,
+) -> Result<Vec<Resource>, Error> {
+    let mut static_records = vec![];
+    for (name, rs) in records {
+        let name = Name::new(&(name.to_owned() + "."))?;
+        for r in rs {
+            let mut r = r.clone();
+            r.header.name = name.clone();
+            match &r.body {
+                Some(body) => r.header.typ = body.real_type(),
+                None => return Err(ERR_NIL_RESOURCE_BODY.to_owned()),
+            }
+            if r.header.class == DNSClass(0) {
+                r.header.class = DNSCLASS_INET;
+            }
+            if r.header.ttl == 0 {
+                r.header.ttl = response_ttl;
+            }
+            static_records.push(r);
+        }
+    }
+    Ok(static_records)
+}
+
+// reverse_name returns the name a PTR record for ip is found under, without
+// the trailing dot (RFC 1035 Section 3.5, RFC 3596 Section 2.5).
+fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mut labels: Vec<String> = ip.octets().iter().rev().map(|o| o.to_string()).collect();
+            labels.push("in-addr.arpa".to_owned());
+            labels.join(".")
+        }
+        IpAddr::V6(ip) => {
+            let mut labels: Vec<String> = ip
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|o| vec![format!("{:x}", o & 0xf), format!("{:x}", o >> 4)])
+                .collect();
+            labels.push("ip6.arpa".to_owned());
+            labels.join(".")
+        }
+    }
+}
+
+// questions builds a question for name of each type.
+fn questions(name: &Name, types: &[DNSType]) -> Vec<Question> {
+    types
+        .iter()
+        .map(|typ| Question {
+            typ: *typ,
+            class: DNSCLASS_INET,
+            name: name.clone(),
+            unicast_response: false,
+        })
+        .collect()
+}
+
+// handle_due_queries retransmits the questions of every query that is due,
+// or nearly so, together in as few packets as possible (RFC 6762 Section 7),
+// and refreshes or expires the cached answers of browses. Queries due again
+// after their last attempt are given up on, which closes their channels.
+async fn handle_due_queries(responder: &Responder, queries: &Arc<Mutex<Vec<Query>>>) {
+    let mut due: Vec<Question> = vec![];
+    let mut known_answers: Vec<Resource> = vec![];
+    let mut expired = vec![];
+    {
+        let mut qs = queries.lock().await;
+        prune_queries(&mut qs);
+
+        let now = Instant::now();
+        qs.retain(|q| {
+            let give_up = q.exhausted() && q.next_query <= now;
+            if give_up {
+                log::trace!("Giving up on query for {}", q.name_with_suffix);
+            }
+            !give_up
+        });
+
+        let horizon = now + QUERY_AGGREGATION_WINDOW;
+        for q in qs.iter_mut() {
+            let mut send = false;
+            if q.next_query <= horizon && !q.exhausted() {
+                q.next_query = now + q.interval;
+                q.interval = std::cmp::min(q.interval * 2, q.max_interval);
+                send = true;
+            }
+
+            if let Some(cache) = &mut q.cache {
+                for c in cache.iter_mut() {
+                    if c.refreshes.first().is_some_and(|r| *r <= horizon) {
+                        c.refreshes.remove(0);
+                        send = true;
+                    }
+                }
+
+                let chans = &q.query_result_chans;
+                cache.retain(|c| {
+                    if c.expires > now {
+                        return true;
+                    }
+                    for chan in chans {
+                        expired.push((chan.clone(), BrowseEvent::Expired(c.result.clone())));
+                    }
+                    false
+                });
+            }
+
+            if !send {
+                continue;
+            }
+            q.attempts += 1;
+            for c in q.cache.iter().flatten() {
+                if let Some(a) = c.known_answer(now) {
+                    if !known_answers.contains(&a) {
+                        known_answers.push(a);
+                    }
+                }
+            }
+            for question in questions(&q.name_with_suffix, &q.types) {
+                if !due
+                    .iter()
+                    .any(|d| d.typ == question.typ && d.name.eq_ignore_ascii_case(&question.name))
+                {
+                    due.push(question);
+                }
+            }
+        }
+    }
+
+    for (chan, event) in expired {
+        send_event(&chan, event);
+    }
+
+    if !due.is_empty() {
+        log::trace!("Sending {} due questions", due.len());
+        send_questions(responder, due, known_answers).await;
+    }
+}
+
+// send_event passes event on to the caller of a query without waiting for
+// it, dropping the event if the caller is behind; a caller that never reads
+// its events must not hold up the server. A caller gone is pruned with its
+// channel.
+fn send_event(chan: &mpsc::Sender<BrowseEvent>, event: BrowseEvent) {
+    if let Err(mpsc::error::TrySendError::Full(event)) = chan.try_send(event) {
+        log::warn!("Dropping {:?}, its query is not being read", event);
+    }
+}
+
+// send_questions sends the questions, split over as many packets as needed.
+async fn send_questions(
+    responder: &Responder,
+    questions: Vec<Question>,
+    known_answers: Vec<Resource>,
+) {
+    let raw_query = {
+        let mut msg = Message {
+            header: Header::default(),
+            questions,
+            answers: known_answers,
+            ..Default::default()
+        };
+
+        match msg.pack_split(responder.max_message_size) {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("Failed to construct mDNS packet {}", err);
+                return;
+            }
+        }
+    };
+
+    for raw_query in raw_query {
+        log::trace!("sending {:?}...", raw_query);
+        if let Err(err) = responder.multicast(&raw_query).await {
+            log::error!("Failed to send mDNS packet {}", err);
+        }
+    }
+}
+
+// prune_queries drops the callers that have gone away, which happens when
+// the future returned by query is dropped, and the queries left without any.
+fn prune_queries(queries: &mut Vec<Query>) {
+    for q in queries.iter_mut() {
+        q.query_result_chans.retain(|c| !c.is_closed());
+    }
+    queries.retain(|q| !q.query_result_chans.is_empty());
+}
+
+// interface_addresses returns the addresses of the interfaces filter allows
+// with an address on the subnet of remote, or of every one of them but
+// loopback if there are none.
+fn interface_addresses(remote: IpAddr, filter: &InterfaceFilter) -> Result<Vec<IpAddr>, Error> {
+    let mut interfaces = ifaces::ifaces().map_err(|e| Error::new(e.to_string()))?;
+    interfaces.retain(|i| filter.allows(&i.name));
+
+    let on_subnet: Vec<&str> = interfaces
+        .iter()
+        .filter(|i| match (i.addr, i.mask) {
+            (Some(addr), Some(mask)) => same_subnet(addr.ip(), mask.ip(), remote),
+            _ => false,
+        })
+        .map(|i| i.name.as_str())
+        .collect();
+
+    let mut ips = vec![];
+    for interface in &interfaces {
+        if let Some(addr) = interface.addr {
+            let relevant = if on_subnet.is_empty() {
+                !addr.ip().is_loopback()
+            } else {
+                on_subnet.contains(&interface.name.as_str())
+            };
+            if relevant && !ips.contains(&addr.ip()) {
+                ips.push(addr.ip());
+            }
+        }
+    }
+    Ok(ips)
+}
+
+// on_local_link reports whether a packet from src, received with the IP
+// TTL ttl if known, came from the local link: sent with a TTL of 255 (RFC
+// 6762 Section 11), from a loopback or link-local address or one on the
+// subnet of one of our interfaces.
+fn on_local_link(src: IpAddr, ttl: Option<u8>) -> bool {
+    if ttl.is_some_and(|ttl| ttl != 255) {
+        return false;
+    }
+    let link_local = match src {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_link_local(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.segments()[0] & 0xffc0 == 0xfe80,
+    };
+    if link_local {
+        return true;
+    }
+
+    match ifaces::ifaces() {
+        Ok(interfaces) => interfaces.iter().any(|i| match (i.addr, i.mask) {
+            (Some(addr), Some(mask)) => same_subnet(addr.ip(), mask.ip(), src),
+            _ => false,
+        }),
+        Err(err) => {
+            log::error!("Error getting interfaces: {:?}", err);
+            false
+        }
+    }
+}
+
+// local_address reports whether ip is an address of this host.
+fn local_address(ip: IpAddr) -> bool {
+    if ip.is_loopback() {
+        return true;
+    }
+    match ifaces::ifaces() {
+        Ok(interfaces) => interfaces
+            .iter()
+            .any(|i| i.addr.map(|addr| addr.ip()) == Some(ip)),
+        Err(err) => {
+            log::error!("Error getting interfaces: {:?}", err);
+            false
+        }
+    }
+}
+
+// renamed returns name with a number appended to its first label, or that
+// number increased if it has one: host.local. becomes host-2.local., and
+// host-2.local. host-3.local. An escaped dot is part of the label it is in,
+// so my\.host.local. becomes my\.host-2.local.
+fn renamed(name: &str) -> String {
+    let (ranges, _) = split_labels(name);
+    let first = ranges.first().cloned().unwrap_or(0..name.len());
+    let label = match unescape_label(&name[first.clone()]) {
+        Ok(label) => label.into_owned(),
+        Err(_) => name[first.clone()].as_bytes().to_vec(),
+    };
+    let (base, n) = match label.iter().rposition(|&c| c == b'-') {
+        Some(i) => match std::str::from_utf8(&label[i + 1..]).map(str::parse::<u32>) {
+            Ok(Ok(n)) if n >= 2 => (&label[..i], n + 1),
+            _ => (&label[..], 2),
+        },
+        None => (&label[..], 2),
+    };
+    let mut label = base.to_vec();
+    label.extend_from_slice(format!("-{}", n).as_bytes());
+    format!("{}{}", escape_label_bytes(&label), &name[first.end..])
+}
+
+// same_subnet reports whether ip and remote are on the same subnet, given
+// the netmask of ip.
+fn same_subnet(ip: IpAddr, mask: IpAddr, remote: IpAddr) -> bool {
+    match (ip, mask, remote) {
+        (IpAddr::V4(ip), IpAddr::V4(mask), IpAddr::V4(remote)) => {
+            u32::from(ip) & u32::from(mask) == u32::from(remote) & u32::from(mask)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(mask), IpAddr::V6(remote)) => {
+            u128::from(ip) & u128::from(mask) == u128::from(remote) & u128::from(mask)
+        }
+        _ => false,
+    }
+}
+
+// interface_for_remote returns the local address that packets to remote are
+// sent from, as chosen by the routing table.
+async fn interface_for_remote(remote: SocketAddr) -> Result<IpAddr, Error> {
+    let unspecified: IpAddr = match remote {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let conn = UdpSocket::bind(SocketAddr::new(unspecified, 0)).await?;
+    conn.connect(remote).await?;
+    let local_addr = conn.local_addr()?;
+
+    Ok(local_addr.ip())
+}
+
+impl Responder {
+    // answer_questions multicasts the answers to questions from src on the
+    // link they came in on, except those to questions with the QU bit set,
+    // which go straight back to src (RFC 6762 Section 5.4).
+    async fn answer_questions(
+        &self,
+        link: &Link,
+        questions: &[Question],
+        known_answers: &[Resource],
+        src: SocketAddr,
+    ) {
+        self.respond(link, questions, known_answers, src, false)
+            .await
+    }
+
+    // answer_probe answers the questions of a probe from src like
+    // answer_questions, except that the answers defend our names, so they are
+    // multicast however recently they were.
+    async fn answer_probe(&self, link: &Link, questions: &[Question], src: SocketAddr) {
+        self.respond(link, questions, &[], src, true).await
+    }
+
+    // respond answers questions from src. Unless they defend our names, the
+    // multicast answers are rate limited, and delayed if any are shared.
+    async fn respond(
+        &self,
+        link: &Link,
+        questions: &[Question],
+        known_answers: &[Resource],
+        src: SocketAddr,
+        defend: bool,
+    ) {
+        let (unicast, multicast): (Vec<Question>, Vec<Question>) =
+            questions.iter().cloned().partition(|q| q.unicast_response);
+        for (questions, dst) in [(unicast, src), (multicast, link.dst_addr)] {
+            if questions.is_empty() {
+                continue;
+            }
+            let answers = self.answers(link, &questions, known_answers, src).await;
+            if answers.is_empty() {
+                continue;
+            }
+            if !defend && dst == link.dst_addr && answers.iter().any(shared) {
+                self.delay_answers(link, answers).await;
+                continue;
+            }
+
+            if let Err(e) = self.send_answers(link, answers, dst, defend).await {
+                log::error!("Error sending answer to client: {:?}", e);
+                return;
+            }
+            log::trace!("Sent answer for {} to dst addr {:?}", src, dst);
+        }
+    }
+
+    // handle_due_probes sends the probes that are due, in one message, and
+    // claims the names probed for PROBE_COUNT times without conflict.
+    async fn handle_due_probes(&self) {
+        let now = Instant::now();
+        let mut due = vec![];
+        let claimed = {
+            let mut probes = self.probes.lock().await;
+            let (claimed, probing): (Vec<Probe>, Vec<Probe>) = probes
+                .drain(..)
+                .partition(|p| p.sent >= PROBE_COUNT && p.next_probe <= now);
+            *probes = probing;
+
+            for p in probes.iter_mut().filter(|p| p.next_probe <= now) {
+                // the first probe asks for unicast answers (RFC 6762
+                // Section 8.1)
+                due.push((p.name.clone(), p.sent == 0));
+                p.sent += 1;
+                p.next_probe = now + PROBE_INTERVAL;
+            }
+            claimed
+        };
+
+        if !claimed.is_empty() {
+            let mut local_names = self.local_names.lock().await;
+            let mut announcements = self.announcements.lock().await;
+            for p in claimed {
+                log::trace!("Claimed local name {}", p.name);
+                announcements.push(Announcement {
+                    name: p.name.clone(),
+                    sent: 0,
+                    next_announcement: now,
+                });
+                if !local_names.iter().any(|l| l.eq_ignore_ascii_case(&p.name)) {
+                    local_names.push(p.name.clone());
+                }
+                for chan in &p.result_chans {
+                    let _ = chan.try_send(p.name.clone());
+                }
+            }
+        }
+
+        if !due.is_empty() {
+            self.send_probes(due).await;
+        }
+    }
+
+    // handle_due_announcements multicasts the records of the claimed local
+    // names due to be announced, in one unsolicited response with the
+    // cache-flush bit set, so that peers replace what they had cached for
+    // them.
+    async fn handle_due_announcements(&self) {
+        let now = Instant::now();
+        let mut due = vec![];
+        {
+            let mut announcements = self.announcements.lock().await;
+            for a in announcements
+                .iter_mut()
+                .filter(|a| a.next_announcement <= now)
+            {
+                due.push(a.name.clone());
+                a.sent += 1;
+                a.next_announcement = now + ANNOUNCE_INTERVAL;
+            }
+            announcements.retain(|a| a.sent < ANNOUNCE_COUNT);
+        }
+        if due.is_empty() {
+            return;
+        }
+
+        log::trace!("Announcing {} local names", due.len());
+        self.assert_names(&due).await;
+    }
+
+    // assert_names multicasts the records of names with the cache-flush bit
+    // set, so that peers replace whatever they had cached for them. They
+    // announce or defend the names, so are not rate limited. It returns the
+    // names whose records were multicast.
+    async fn assert_names(&self, names: &[String]) -> Vec<String> {
+        let mut answers = vec![];
+        for link in &self.links {
+            let addresses = self.link_addresses(link).await;
+            let mut records = address_records(names, &addresses, self.response_ttl);
+            for a in &mut records {
+                a.header.cache_flush = true;
+            }
+            answers.push((link, records));
+        }
+
+        let (sent, result) = self.multicast_answers(answers, true).await;
+        if let Err(err) = result {
+            log::error!("Failed to send mDNS announcement {}", err);
+        }
+        names
+            .iter()
+            .filter(|name| {
+                sent.iter()
+                    .any(|a| a.header.name.data.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+            .collect()
+    }
+
+    // say_goodbye says goodbye for every local name and static record, as
+    // the server shuts down.
+    async fn say_goodbye(&self) {
+        let names = self.local_names.lock().await.clone();
+        self.send_goodbyes(&names, true).await;
+    }
+
+    // send_goodbyes multicasts the records of names, and the static records
+    // too if with_records is set, with a TTL of zero, so that peers flush
+    // them from their caches at once instead of when they expire (RFC 6762
+    // Section 10.1).
+    async fn send_goodbyes(&self, names: &[String], with_records: bool) {
+        let mut answers = vec![];
+        for link in &self.links {
+            let addresses = self.link_addresses(link).await;
+            let mut records = address_records(names, &addresses, 0);
+            if with_records {
+                for r in self.records.iter() {
+                    let mut r = r.clone();
+                    r.header.ttl = 0;
+                    records.push(r);
+                }
+            }
+            answers.push((link, records));
+        }
+        if answers.iter().all(|(_, records)| records.is_empty()) {
+            return;
+        }
+
+        log::trace!("Sending goodbye records for {} names", names.len());
+        if let Err(err) = self.multicast_answers(answers, false).await.1 {
+            log::error!("Failed to send mDNS goodbye {}", err);
+        }
+    }
+
+    // send_probes sends a probe for each name on every link, a question for
+    // any record of it, with the records we propose for it on the link in
+    // the authority section.
+    async fn send_probes(&self, names: Vec<(String, bool)>) {
+        for link in &self.links {
+            let addresses = self.link_addresses(link).await;
+            let mut msg = Message::default();
+            for (name, unicast_response) in &names {
+                let question_name = match Name::new(name) {
+                    Ok(n) => n,
+                    Err(err) => {
+                        log::error!("Error probing for {}: {:?}", name, err);
+                        continue;
+                    }
+                };
+                msg.questions.push(Question {
+                    name: question_name,
+                    typ: DNSType::ALL,
+                    class: DNSCLASS_INET,
+                    unicast_response: *unicast_response,
+                });
+                msg.authorities.extend(address_records(
+                    std::slice::from_ref(name),
+                    &addresses,
+                    self.response_ttl,
+                ));
+            }
+
+            let raw_probes = match msg.pack_split(self.max_message_size) {
+                Ok(raw_probes) => raw_probes,
+                Err(err) => {
+                    log::error!("Failed to construct mDNS probe {}", err);
+                    return;
+                }
+            };
+            log::trace!("Sending probes for {} names", msg.questions.len());
+            for raw_probe in raw_probes {
+                if let Err(err) = self.multicast_on(link, &raw_probe).await {
+                    log::error!("Failed to send mDNS probe {}", err);
+                }
+            }
+        }
+    }
+
+    // link_addresses returns the addresses the local names are proposed,
+    // announced and defended with on link: those the answers multicast on
+    // it are given. The Querier mode answers each querier with its own
+    // address, so has none.
+    async fn link_addresses(&self, link: &Link) -> Vec<IpAddr> {
+        if self.answer_addresses == AnswerAddresses::Querier {
+            return vec![];
+        }
+        self.addresses(link, link.dst_addr).await
+    }
+
+    // check_simultaneous_probes breaks the tie with another host probing
+    // for a name we are probing for too, as its probe msg from src on link
+    // shows: if our records are the earlier, we wait PROBE_DEFER and probe
+    // again.
+    async fn check_simultaneous_probes(&self, link: &Link, msg: &[u8], src: SocketAddr) {
+        if self.probes.lock().await.is_empty() {
+            return;
+        }
+
+        let addresses = self.link_addresses(link).await;
+        let authorities = authority_section(msg);
+        let mut probes = self.probes.lock().await;
+        for p in probes.iter_mut() {
+            let theirs: Vec<Resource> = authorities
+                .iter()
+                .filter(|a| a.header.name.data.eq_ignore_ascii_case(&p.name))
+                .cloned()
+                .collect();
+            if theirs.is_empty() {
+                continue;
+            }
+
+            let ours =
+                address_records(std::slice::from_ref(&p.name), &addresses, self.response_ttl);
+            if tie_break(&ours, &theirs) == std::cmp::Ordering::Less {
+                log::warn!(
+                    "Lost probe tie-break for {} to {}, probing again later",
+                    p.name,
+                    src
+                );
+                p.sent = 0;
+                p.next_probe = Instant::now() + PROBE_DEFER;
+            }
+        }
+    }
+
+    // check_conflicts handles the local names another host answers for in
+    // the response msg from src on link (RFC 6762 Section 9). Any answer
+    // conflicts with a name still being probed for, which is renamed and
+    // probed for again. A claimed name only conflicts with the addresses of
+    // another host, if the local names are probed for; it is defended by
+    // asserting our records again, and only renamed if the other host still
+    // answers for it within DEFENSE_WINDOW.
+    async fn check_conflicts(&self, link: &Link, msg: &[u8], src: SocketAddr) {
+        let answers = answer_section(msg);
+        if answers.is_empty() {
+            return;
+        }
+        let ours = self.link_addresses(link).await;
+
+        let mut renames = vec![];
+        let mut defend = vec![];
+        {
+            let mut probes = self.probes.lock().await;
+            for p in probes.iter_mut() {
+                if answers
+                    .iter()
+                    .any(|a| a.header.name.data.eq_ignore_ascii_case(&p.name))
+                {
+                    let name = renamed(&p.name);
+                    renames.push((p.name.clone(), name.clone()));
+                    *p = Probe {
+                        result_chans: std::mem::take(&mut p.result_chans),
+                        ..Probe::new(name)
+                    };
+                }
+            }
+
+            if self.probe_local_names && !local_address(src.ip()) {
+                let now = Instant::now();
+                let mut defended = self.defended.lock().await;
+                defended.retain(|(_, at)| now.duration_since(*at) < DEFENSE_WINDOW);
+
+                let mut local_names = self.local_names.lock().await;
+                local_names.retain(|l| {
+                    let conflict = answers.iter().any(|a| {
+                        a.header.name.data.eq_ignore_ascii_case(l)
+                            && a.ip().is_some_and(|ip| !ours.contains(&ip))
+                    });
+                    if !conflict {
+                        return true;
+                    }
+                    if !defended.iter().any(|(name, _)| name == l) {
+                        defend.push(l.clone());
+                        return true;
+                    }
+
+                    let name = renamed(l);
+                    renames.push((l.clone(), name.clone()));
+                    probes.push(Probe::new(name));
+                    false
+                });
+                defended.retain(|(name, _)| !renames.iter().any(|(l, _)| l == name));
+            }
+        }
+
+        if !defend.is_empty() {
+            log::warn!("Defending local names {:?} against {}", defend, src);
+            // Only a name whose records went out has been defended.
+            let sent = self.assert_names(&defend).await;
+            let now = Instant::now();
+            self.defended
+                .lock()
+                .await
+                .extend(sent.into_iter().map(|name| (name, now)));
+        }
+        if renames.is_empty() {
+            return;
+        }
+
+        let mut announcements = self.announcements.lock().await;
+        announcements.retain(|a| !renames.iter().any(|(name, _)| *name == a.name));
+        drop(announcements);
+
+        // The handler is called without holding its lock, so it can set
+        // another one.
+        let handler = self.on_name_conflict_handler.lock().await.clone();
+        for (name, new_name) in renames {
+            log::warn!(
+                "Local name {} is already in use by {}, renaming it {}",
+                name,
+                src,
+                new_name
+            );
+            if let Some(handler) = &handler {
+                handler(
+                    name.trim_end_matches('.').to_owned(),
+                    new_name.trim_end_matches('.').to_owned(),
+                )
+                .await;
+            }
+        }
+    }
+
+    // delay_answers holds answers to be multicast on link back until its
+    // pending delayed answers are sent, or for 20-120ms if there are none.
+    async fn delay_answers(&self, link: &Link, answers: Vec<Resource>) {
+        let mut delayed = link.delayed.lock().await;
+        match &mut *delayed {
+            Some(d) => {
+                for a in answers {
+                    if !d.answers.contains(&a) {
+                        d.answers.push(a);
+                    }
+                }
+            }
+            None => {
+                let delay = Duration::from_millis(rand::thread_rng().gen_range(20..=120));
+                log::trace!("Delaying {} answers by {:?}", answers.len(), delay);
+                *delayed = Some(DelayedAnswers {
+                    answers,
+                    deadline: Instant::now() + delay,
+                });
+            }
+        }
+    }
+
+    // send_delayed_answers multicasts the delayed answers once they are due.
+    async fn send_delayed_answers(&self) {
+        for link in &self.all_links().await {
+            let answers = {
+                let mut delayed = link.delayed.lock().await;
+                if !delayed
+                    .as_ref()
+                    .is_some_and(|d| d.deadline <= Instant::now())
+                {
+                    continue;
+                }
+                delayed.take().map(|d| d.answers).unwrap_or_default()
+            };
+
+            if let Err(e) = self.send_answers(link, answers, link.dst_addr, false).await {
+                log::error!("Error sending delayed answers: {:?}", e);
+            }
+        }
+    }
+
+    // rescan_interfaces joins the multicast group on the interfaces come up
+    // since it was last joined, and leaves it on those gone, on each socket
+    // joined on every interface. The links learned for interfaces that are
+    // gone or have new addresses are forgotten, to be learned afresh.
+    async fn rescan_interfaces(&self) {
+        let current = match all_link_interfaces() {
+            Ok(interfaces) => interfaces,
+            Err(err) => {
+                log::error!("Error getting interfaces: {:?}", err);
+                return;
+            }
+        };
+
+        let mut multicast_groups = vec![];
+        for link in &self.links {
+            let mut joined = link.memberships.lock().await;
+            if let Some(interface) = &link.interface {
+                if !current.iter().any(|i| i.index == interface.index) {
+                    log::warn!("Interface {} is gone", interface.name);
+                }
+            } else {
+                let wanted = match memberships(link.dst_addr.ip(), &self.interfaces) {
+                    Ok((wanted, _)) => wanted,
+                    Err(err) => {
+                        log::error!("Error getting interfaces: {:?}", err);
+                        return;
+                    }
+                };
+                let socket = link.socket.as_ref();
+                joined.retain(|m| {
+                    if wanted.iter().any(|w| w.same(m)) {
+                        return true;
+                    }
+                    log::trace!("Leaving {} on interface address {}", m.group, m.addr);
+                    if let Err(err) = m.leave(socket2::SockRef::from(socket)) {
+                        log::warn!("Error leaving {} on {}: {:?}", m.group, m.addr, err);
+                    }
+                    false
+                });
+                for m in wanted {
+                    if joined.iter().any(|j| j.same(&m)) {
+                        continue;
+                    }
+                    log::trace!("Joining {} on interface address {}", m.group, m.addr);
+                    match m.join(socket2::SockRef::from(socket)) {
+                        Ok(()) => joined.push(m),
+                        Err(err) => log::error!("Error connecting multicast, error: {:?}", err),
+                    }
+                }
+            }
+            multicast_groups.extend(joined.iter().map(|m| (m.group, m.addr)));
+        }
+        *self.multicast_groups.lock().unwrap() = multicast_groups;
+
+        self.interface_links.lock().await.retain(|l| {
+            l.interface.as_ref().is_some_and(|interface| {
+                current
+                    .iter()
+                    .any(|i| i.index == interface.index && i.addrs == interface.addrs)
+            })
+        });
+    }
+
+    // refresh_memberships leaves the multicast group and joins it again on
+    // every interface it is joined on, which has the kernel report the
+    // membership unsolicited. A membership that can't be joined again is
+    // dropped, for rescan_interfaces to retry.
+    async fn refresh_memberships(&self) {
+        for link in &self.links {
+            let socket = link.socket.as_ref();
+            link.memberships.lock().await.retain(|m| {
+                if let Err(err) = m.leave(socket2::SockRef::from(socket)) {
+                    log::warn!("Error leaving {} on {}: {:?}", m.group, m.addr, err);
+                }
+                match m.join(socket2::SockRef::from(socket)) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        log::error!("Error connecting multicast, error: {:?}", err);
+                        false
+                    }
+                }
+            });
+        }
+    }
+
+    // all_links returns links and interface_links.
+    async fn all_links(&self) -> Vec<Arc<Link>> {
+        let mut links = self.links.clone();
+        links.extend(self.interface_links.lock().await.iter().cloned());
+        links
+    }
+
+    // receiving_link returns the link a packet received on link, over the
+    // interface with index interface if reported, is to be answered on:
+    // link itself if it is dedicated to an interface, or otherwise the
+    // interface's link over the socket of link.
+    async fn receiving_link(&self, link: &Arc<Link>, interface: Option<u32>) -> Arc<Link> {
+        let index = match (&link.interface, interface) {
+            (None, Some(index)) => index,
+            _ => return Arc::clone(link),
+        };
+        let mut interface_links = self.interface_links.lock().await;
+        if let Some(l) = interface_links.iter().find(|l| {
+            Arc::ptr_eq(&l.socket, &link.socket)
+                && l.interface.as_ref().is_some_and(|i| i.index == index)
+        }) {
+            return Arc::clone(l);
+        }
+
+        let interface = match all_link_interfaces() {
+            Ok(interfaces) => match interfaces.into_iter().find(|i| i.index == index) {
+                Some(i) => i,
+                None => return Arc::clone(link),
+            },
+            Err(err) => {
+                log::error!("Error getting interfaces: {:?}", err);
+                return Arc::clone(link);
+            }
+        };
+        log::trace!(
+            "Answering packets from {} on their own link",
+            interface.name
+        );
+        let mut l = Link::new(Arc::clone(&link.socket), link.dst_addr);
+        l.interface = Some(interface);
+        let l = Arc::new(l);
+        interface_links.push(Arc::clone(&l));
+        l
+    }
+
+    // answer_legacy_unicast answers a query sent from a port other than the
+    // mDNS port by a resolver that is not a full mDNS querier. The answers
+    // go straight back to it, repeating the query ID and questions, with
+    // their TTLs capped at LEGACY_UNICAST_TTL (RFC 6762 Section 6.7).
+    async fn answer_legacy_unicast(
+        &self,
+        link: &Link,
+        id: u16,
+        questions: &[Question],
+        src: SocketAddr,
+    ) {
+        let mut answers = self.answers(link, questions, &[], src).await;
+        if answers.is_empty() {
+            return;
+        }
+        for a in &mut answers {
+            a.header.ttl = std::cmp::min(a.header.ttl, LEGACY_UNICAST_TTL);
+            a.header.cache_flush = false;
+        }
+
+        let mut msg = Message {
+            header: Header {
+                id,
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            questions: questions.to_vec(),
+            answers,
+            ..Default::default()
+        };
+        let raw_answers = match msg.pack_split(self.max_message_size) {
+            Ok(raw_answers) => raw_answers,
+            Err(e) => {
+                log::error!("Error answering legacy unicast query: {:?}", e);
+                return;
+            }
+        };
+        for raw_answer in raw_answers {
+            if let Err(e) = link.socket.send_to(&raw_answer, src).await {
+                log::error!("Error sending answer to client: {:?}", e);
+                return;
+            }
+        }
+        log::trace!("Sent legacy unicast answer to {}", src);
+    }
+
+    // answers returns the records answering questions from src, received on
+    // link.
+    async fn answers(
+        &self,
+        link: &Link,
+        questions: &[Question],
+        known_answers: &[Resource],
+        src: SocketAddr,
+    ) -> Vec<Resource> {
+        let mut answers = vec![];
+        // The handler is called without the locks held, so that it may set
+        // another handler or change the local names.
+        let handler = self.on_question_handler.lock().await.clone();
+        for q in questions {
+            if let Some(handler) = &handler {
+                if !handler(q.clone(), src).await {
+                    log::trace!("Question for {} from {} not to be answered", q.name, src);
+                    continue;
+                }
+            }
+
+            let local_names = self.local_names.lock().await.clone();
+            for local_name in &local_names {
+                if local_name.eq_ignore_ascii_case(&q.name.data) {
+                    log::trace!(
+                        "Found local name: {} to send answer ({} known answers)",
+                        local_name,
+                        known_answers.len()
+                    );
+                    for ip in self.addresses(link, src).await {
+                        let asked = match ip {
+                            IpAddr::V4(_) => q.typ == DNSType::A,
+                            IpAddr::V6(_) => q.typ == DNSType::AAAA,
+                        };
+                        if !asked && q.typ != DNSType::ALL {
+                            continue;
+                        }
+                        match answer(&q.name.data, ip, self.response_ttl) {
+                            Ok(mut a) => {
+                                // Our addresses are unique records (RFC 6762
+                                // Section 10.2).
+                                a.header.cache_flush = true;
+                                if !answers.contains(&a) {
+                                    answers.push(a);
+                                }
+                            }
+                            Err(e) => log::error!("Error answering {}: {:?}", local_name, e),
+                        }
+                    }
+                }
+            }
+
+            for r in self.records.iter() {
+                if r.header.name.eq_ignore_ascii_case(&q.name)
+                    && (q.typ == r.header.typ || q.typ == DNSType::ALL)
+                    && !answers.contains(r)
+                {
+                    log::trace!("Found record {} to send answer", r);
+                    answers.push(r.clone());
+                }
+            }
+        }
+        answers.retain(|a| {
+            let known = known_answer(a, known_answers);
+            if known {
+                log::trace!("Suppressing answer {} known to {}", a, src);
+            }
+            !known
+        });
+        answers
+    }
+
+    // addresses returns the addresses to answer a question from src,
+    // received on link, with. On a link dedicated to an interface, those
+    // facing the querier are the interface's own.
+    async fn addresses(&self, link: &Link, src: SocketAddr) -> Vec<IpAddr> {
+        if let Some(interface) = &link.interface {
+            match &self.answer_addresses {
+                AnswerAddresses::FacingQuerier => {
+                    return interface
+                        .addrs
+                        .iter()
+                        .find(|ip| ip.is_ipv6() == src.is_ipv6())
+                        .into_iter()
+                        .copied()
+                        .collect();
+                }
+                AnswerAddresses::Interfaces => return interface.addrs.clone(),
+                _ => {}
+            }
+        }
+        match &self.answer_addresses {
+            AnswerAddresses::Querier => vec![src.ip()],
+            AnswerAddresses::FacingQuerier => match interface_for_remote(src).await {
+                Ok(ip) => vec![ip],
+                Err(err) => {
+                    log::error!("Error finding the interface facing {}: {:?}", src, err);
+                    vec![]
+                }
+            },
+            AnswerAddresses::Fixed(ips) => ips.clone(),
+            AnswerAddresses::Interfaces => match interface_addresses(src.ip(), &self.interfaces) {
+                Ok(ips) => ips,
+                Err(err) => {
+                    log::error!("Error getting interfaces: {:?}", err);
+                    vec![]
+                }
+            },
+        }
+    }
+
+    // rate_limit drops the answers multicast on link less than
+    // MIN_MULTICAST_INTERVAL ago, unless they defend our names.
+    async fn rate_limit(&self, link: &Link, answers: Vec<Resource>, defend: bool) -> Vec<Resource> {
+        let now = Instant::now();
+        let mut recent = link.recent_multicasts.lock().await;
+        recent.retain(|(_, at)| now.duration_since(*at) < MIN_MULTICAST_INTERVAL);
+
+        answers
+            .into_iter()
+            .filter(|a| {
+                let allowed = defend || !recent.iter().any(|(r, _)| r == a);
+                if !allowed {
+                    log::trace!("Not multicasting {} again so soon", a);
+                }
+                allowed
+            })
+            .collect()
+    }
+
+    // note_multicasts notes answers as multicast on link now, for rate_limit.
+    async fn note_multicasts(&self, link: &Link, answers: &[Resource]) {
+        let now = Instant::now();
+        let mut recent = link.recent_multicasts.lock().await;
+        for a in answers {
+            recent.retain(|(r, _)| r != a);
+            recent.push((a.clone(), now));
+        }
+    }
+
+    // multicast_answers multicasts the answers of each link on it, rate
+    // limited unless they defend our names. It returns the answers multicast
+    // on any link, and the error sending them on one if there was any.
+    async fn multicast_answers(
+        &self,
+        answers: Vec<(&Arc<Link>, Vec<Resource>)>,
+        defend: bool,
+    ) -> (Vec<Resource>, Result<(), Error>) {
+        let mut sent: Vec<Resource> = vec![];
+        let mut result = Ok(());
+        for (link, answers) in answers {
+            match self
+                .send_answers(link, answers, link.dst_addr, defend)
+                .await
+            {
+                Ok(answers) => {
+                    for a in answers {
+                        if !sent.contains(&a) {
+                            sent.push(a);
+                        }
+                    }
+                }
+                Err(err) => result = Err(err),
+            }
+        }
+        (sent, result)
+    }
+
+    // send_answers sends answers to dst over link, split over several
+    // messages if they don't fit in one. Multicast answers are rate limited
+    // unless they defend our names. It returns the answers sent.
+    async fn send_answers(
+        &self,
+        link: &Link,
+        answers: Vec<Resource>,
+        dst: SocketAddr,
+        defend: bool,
+    ) -> Result<Vec<Resource>, Error> {
+        let answers = if dst == link.dst_addr {
+            self.rate_limit(link, answers, defend).await
+        } else {
+            answers
+        };
+        if answers.is_empty() {
+            return Ok(answers);
+        }
+        let sent = answers.clone();
+
+        let mut msg = Message {
+            header: Header {
+                response: true,
+                authoritative: true,
+                ..Default::default()
+            },
+            answers,
+            ..Default::default()
+        };
+
+        let mut buf = self.send_buf.lock().await;
+        buf.clear();
+        msg.append(&mut buf)?;
+        let split;
+        let raw_answers: Vec<&[u8]> = if buf.len() <= self.max_message_size {
+            vec![&buf]
+        } else {
+            split = msg.pack_split(self.max_message_size)?;
+            split.iter().map(Vec::as_slice).collect()
+        };
+        for raw_answer in raw_answers {
+            if dst == link.dst_addr {
+                self.multicast_on(link, raw_answer).await?;
+            } else {
+                link.socket.send_to(raw_answer, dst).await?;
+            }
+        }
+        if dst == link.dst_addr {
+            self.note_multicasts(link, &sent).await;
+        }
+
+        Ok(sent)
+    }
+
+    // source_allowed reports whether packets from src are accepted: those
+    // from none of the denied sources and, if any are allowed, one of them.
+    fn source_allowed(&self, src: IpAddr) -> bool {
+        if self.denied_sources.iter().any(|s| s.contains(src)) {
+            return false;
+        }
+        self.allowed_sources.is_empty() || self.allowed_sources.iter().any(|s| s.contains(src))
+    }
+
+    // question_rate_allowed counts the count questions of a query from src,
+    // reporting whether they are within max_questions_per_source.
+    async fn question_rate_allowed(&self, src: IpAddr, count: u16) -> bool {
+        if count == 0 {
+            return true;
+        }
+        let mut counters = self.question_counters.lock().await;
+        counters.received += u64::from(count);
+        if self.max_questions_per_source == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut source_questions = self.source_questions.lock().await;
+        source_questions.retain(|_, (_, start)| now.duration_since(*start) < QUESTION_RATE_WINDOW);
+        let (asked, _) = source_questions.entry(src).or_insert((0, now));
+        *asked = asked.saturating_add(u32::from(count));
+        if *asked > self.max_questions_per_source {
+            counters.dropped += u64::from(count);
+            return false;
+        }
+        true
+    }
+
+    // multicast sends raw to the multicast group of every link.
+    async fn multicast(&self, raw: &[u8]) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for link in &self.links {
+            if let Err(err) = self.multicast_on(link, raw).await {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    // multicast_on sends raw to the multicast group of link, out of its
+    // interface if it is on just the one, remembering it so that own_packet
+    // can tell it apart when it is looped back to us.
+    async fn multicast_on(&self, link: &Link, raw: &[u8]) -> std::io::Result<()> {
+        let now = Instant::now();
+        {
+            let mut sent = self.sent.lock().await;
+            sent.retain(|(_, at)| now.duration_since(*at) < OWN_PACKET_WINDOW);
+            sent.push((packet_hash(raw), now));
+        }
+
+        // The socket of an interface link is shared by every interface, so
+        // it is only sending out of the link's for this packet.
+        let interface_link = self
+            .interface_links
+            .lock()
+            .await
+            .iter()
+            .any(|l| std::ptr::eq(l.as_ref(), link));
+        let ipv6 = link.dst_addr.is_ipv6();
+        if interface_link {
+            set_multicast_interface(&link.socket, ipv6, link.interface.as_ref())?;
+        }
+        let result = link.socket.send_to(raw, link.dst_addr).await;
+        if interface_link {
+            set_multicast_interface(&link.socket, ipv6, None)?;
+        }
+        result?;
+        Ok(())
+    }
+
+    // own_packet reports whether raw, received from src, is one of the
+    // packets we multicast ourselves, looped back to us. Other responders on
+    // this host may share our address and port, so it is the contents that
+    // tell our packets apart.
+    async fn own_packet(&self, raw: &[u8], src: SocketAddr) -> bool {
+        let hash = packet_hash(raw);
+        let now = Instant::now();
+        let ours = self
+            .sent
+            .lock()
+            .await
+            .iter()
+            .any(|(h, at)| *h == hash && now.duration_since(*at) < OWN_PACKET_WINDOW);
+        ours && local_address(src.ip())
+    }
+}
+
+// set_multicast_interface has socket send its multicast packets out of
+// interface (IP_MULTICAST_IF, IPV6_MULTICAST_IF), or if there is none, out
+// of whichever the kernel picks.
+fn set_multicast_interface(
+    socket: &UdpSocket,
+    ipv6: bool,
+    interface: Option<&LinkInterface>,
+) -> std::io::Result<()> {
+    let socket = socket2::SockRef::from(socket);
+    if ipv6 {
+        return socket.set_multicast_if_v6(interface.map_or(0, |i| i.index));
+    }
+    let addr = interface
+        .and_then(|i| {
+            i.addrs.iter().find_map(|ip| match ip {
+                IpAddr::V4(ip) => Some(*ip),
+                IpAddr::V6(_) => None,
+            })
+        })
+        .unwrap_or(Ipv4Addr::UNSPECIFIED);
+    socket.set_multicast_if_v4(&addr)
+}
+
+// packet_hash hashes the contents of a packet.
+fn packet_hash(raw: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    raw.hash(&mut hasher);
+    hasher.finish()
+}
+
+// shared reports whether r is a shared record, one that several responders
+// may answer with, like the PTR records naming DNS-SD service instances.
+// Unique records, like the address records of the local names, are sent with
+// the cache-flush bit set (RFC 6762 Section 10.2).
+fn shared(r: &Resource) -> bool {
+    !r.header.cache_flush
+}
+
+// address_records returns the address records of names with ttl, one for
+// each of addresses.
+fn address_records(names: &[String], addresses: &[IpAddr], ttl: u32) -> Vec<Resource> {
+    let mut records = vec![];
+    for name in names {
+        for ip in addresses {
+            match answer(name, *ip, ttl) {
+                Ok(a) => records.push(a),
+                Err(err) => log::error!("Error making records for {}: {:?}", name, err),
+            }
+        }
+    }
+    records
+}
+
+// answer returns the A or AAAA record of name for ip.
+fn answer(name: &str, ip: IpAddr, ttl: u32) -> Result<Resource, Error> {
+    let (typ, body) = match ip {
+        IpAddr::V4(ip) => (DNSType::A, ResourceData::A(AResource { a: ip.octets() })),
+        IpAddr::V6(ip) => (
+            DNSType::AAAA,
+            ResourceData::AAAA(AAAAResource { aaaa: ip.octets() }),
+        ),
+    };
+    Ok(Resource {
+        header: ResourceHeader {
+            typ,
+            class: DNSCLASS_INET,
+            name: Name::new(name)?,
+            ttl,
+            ..Default::default()
+        },
+        body: Some(body),
+    })
 }
@@ -0,0 +1,157 @@
+use crate::config::Config;
+use crate::conn::*;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+use util::Error;
+
+/// MdnsQuerier is the client half of a DNSConn: it sends queries and collects
+/// their answers, but answers no questions. It has its own socket, so it is
+/// started and closed independently of any MdnsResponder bound to the same
+/// address; use a DNSConn to have both roles share one socket.
+pub struct MdnsQuerier {
+    pub(super) conn: DNSConn,
+}
+
+impl MdnsQuerier {
+    /// new starts a querier bound to addr. It runs in querier-only mode,
+    /// whatever the config says, so its local names and records are ignored.
+    pub fn new(addr: SocketAddr, mut config: Config) -> Result<Self, Error> {
+        config.querier_only = true;
+        Ok(MdnsQuerier {
+            conn: DNSConn::server(addr, config)?,
+        })
+    }
+
+    /// one_shot starts a querier on an ephemeral port that does not join the
+    /// multicast group, for when another responder already owns port 5353.
+    /// Queries from a port other than 5353 are answered straight back to
+    /// that port (RFC 6762 Section 5.1), so it only sees the answers to its
+    /// own queries. It runs in querier-only mode, whatever the config says.
+    pub fn one_shot(mut config: Config) -> Result<Self, Error> {
+        config.querier_only = true;
+        Ok(MdnsQuerier {
+            conn: DNSConn::new(
+                SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 0),
+                config,
+                false,
+            )?,
+        })
+    }
+
+    /// query is DNSConn::query
+    pub async fn query(&self, name: &str) -> Result<QueryResult, Error> {
+        self.conn.query(name).await
+    }
+
+    /// query_with_timeout is DNSConn::query_with_timeout
+    pub async fn query_with_timeout(
+        &self,
+        name: &str,
+        timeout: Duration,
+    ) -> Result<QueryResult, Error> {
+        self.conn.query_with_timeout(name, timeout).await
+    }
+
+    /// query_with_cancel is DNSConn::query_with_cancel
+    pub async fn query_with_cancel(
+        &self,
+        name: &str,
+        cancellation_token: CancellationToken,
+    ) -> Result<QueryResult, Error> {
+        self.conn.query_with_cancel(name, cancellation_token).await
+    }
+
+    /// query_types is DNSConn::query_types
+    pub async fn query_types(&self, name: &str, types: &[DNSType]) -> Result<QueryResult, Error> {
+        self.conn.query_types(name, types).await
+    }
+
+    /// query_scheduled is DNSConn::query_scheduled
+    pub async fn query_scheduled(
+        &self,
+        name: &str,
+        types: &[DNSType],
+        schedule: QuerySchedule,
+    ) -> Result<QueryResult, Error> {
+        self.conn.query_scheduled(name, types, schedule).await
+    }
+
+    /// query_all is DNSConn::query_all
+    pub async fn query_all(
+        &self,
+        name: &str,
+        types: &[DNSType],
+        window: Duration,
+        max_results: usize,
+    ) -> Result<Vec<QueryResult>, Error> {
+        self.conn.query_all(name, types, window, max_results).await
+    }
+
+    /// resolve is DNSConn::resolve
+    pub async fn resolve(&self, name: &str, timeout: Duration) -> Result<Vec<IpAddr>, Error> {
+        self.conn.resolve(name, timeout).await
+    }
+
+    /// lookup_addr is DNSConn::lookup_addr
+    pub async fn lookup_addr(&self, ip: IpAddr, timeout: Duration) -> Result<Name, Error> {
+        self.conn.lookup_addr(ip, timeout).await
+    }
+
+    /// browse is DNSConn::browse
+    pub async fn browse(&self, name: &str, typ: DNSType) -> Result<Browse, Error> {
+        self.conn.browse(name, typ).await
+    }
+
+    /// browse_scheduled is DNSConn::browse_scheduled
+    pub async fn browse_scheduled(
+        &self,
+        name: &str,
+        typ: DNSType,
+        schedule: QuerySchedule,
+    ) -> Result<Browse, Error> {
+        self.conn.browse_scheduled(name, typ, schedule).await
+    }
+
+    /// observe is DNSConn::observe
+    pub async fn observe(&self) -> Result<Observe, Error> {
+        self.conn.observe().await
+    }
+
+    /// pending_queries is DNSConn::pending_queries
+    pub async fn pending_queries(&self) -> Vec<PendingQuery> {
+        self.conn.pending_queries().await
+    }
+
+    /// local_addr is DNSConn::local_addr
+    pub fn local_addr(&self) -> SocketAddr {
+        self.conn.local_addr()
+    }
+
+    /// local_addrs is DNSConn::local_addrs
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.conn.local_addrs()
+    }
+
+    /// multicast_groups is DNSConn::multicast_groups
+    pub fn multicast_groups(&self) -> Vec<(IpAddr, IpAddr)> {
+        self.conn.multicast_groups()
+    }
+
+    /// is_closed is DNSConn::is_closed
+    pub fn is_closed(&self) -> bool {
+        self.conn.is_closed()
+    }
+
+    /// close is DNSConn::close
+    pub async fn close(&self) -> Result<(), Error> {
+        self.conn.close().await
+    }
+
+    /// restart is DNSConn::restart
+    pub async fn restart(&self) -> Result<(), Error> {
+        self.conn.restart().await
+    }
+}
@@ -0,0 +1,311 @@
+use super::{Link, INBOUND_BUFFER_SIZE};
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+// PACKET_CHANNEL_SIZE is how many received packets may wait for the server
+// loop.
+pub(super) const PACKET_CHANNEL_SIZE: usize = 64;
+
+// A Packet is a packet received on a link, with what is reported of how it
+// was received.
+pub(super) struct Packet {
+    pub(super) data: Vec<u8>,
+    pub(super) src: SocketAddr,
+    pub(super) ttl: Option<u8>,
+    // interface is the index of the interface the packet arrived on
+    pub(super) interface: Option<u32>,
+    // dst is the address the packet was sent to: the multicast group, or
+    // one of our addresses
+    pub(super) dst: Option<IpAddr>,
+    pub(super) link: Arc<Link>,
+}
+
+// Ancillary is what is reported along with a packet received.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct Ancillary {
+    pub(super) ttl: Option<u8>,
+    pub(super) interface: Option<u32>,
+    pub(super) dst: Option<IpAddr>,
+}
+
+// Readers are the tasks receiving the packets of the links of a server,
+// which are aborted when the Readers are dropped.
+pub(super) struct Readers(Vec<JoinHandle<()>>);
+
+impl Readers {
+    // spawn starts a task for each link passing the packets it receives,
+    // or the error it fails with, on to packets.
+    pub(super) fn spawn(links: &[Arc<Link>], packets: &mpsc::Sender<io::Result<Packet>>) -> Self {
+        Readers(
+            links
+                .iter()
+                .map(|link| tokio::spawn(read_link(Arc::clone(link), packets.clone())))
+                .collect(),
+        )
+    }
+
+    // stop aborts the readers, returning once they are gone.
+    pub(super) async fn stop(mut self) {
+        for reader in self.0.drain(..) {
+            reader.abort();
+            let _ = reader.await;
+        }
+    }
+}
+
+impl Drop for Readers {
+    fn drop(&mut self) {
+        for reader in &self.0 {
+            reader.abort();
+        }
+    }
+}
+
+// read_link passes the packets received on link on to packets, until
+// receiving fails for good or the server loop is gone. Errors that are
+// transient are logged and receiving carries on.
+async fn read_link(link: Arc<Link>, packets: mpsc::Sender<io::Result<Packet>>) {
+    let mut b = vec![0u8; INBOUND_BUFFER_SIZE];
+    loop {
+        let result = match recv_from(&link.socket, &mut b).await {
+            Err(err) if transient(&err) => {
+                log::warn!("Error receiving from socket connection: {:?}", err);
+                continue;
+            }
+            result => result.map(|(n, src, ancillary)| Packet {
+                data: b[..n].to_vec(),
+                src,
+                ttl: ancillary.ttl,
+                interface: ancillary.interface,
+                dst: ancillary.dst,
+                link: Arc::clone(&link),
+            }),
+        };
+        let failed = result.is_err();
+        if packets.send(result).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+// transient reports whether receiving failed with err for a moment, or for
+// a single datagram, rather than for good: when interrupted, out of buffer
+// space, or reset by the ICMP port unreachable that Windows reports to a
+// socket after it sent a unicast answer to a closed port.
+pub(super) fn transient(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    if err.raw_os_error() == Some(libc::ENOBUFS) {
+        return true;
+    }
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+    )
+}
+
+// enable_recv_ttl asks for the IP TTL, or IPv6 hop limit, of each packet
+// received on socket to be reported along with it (IP_RECVTTL and
+// IPV6_RECVHOPLIMIT).
+#[cfg(unix)]
+pub(super) fn enable_recv_ttl(socket: &UdpSocket) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name) = if socket.local_addr()?.is_ipv6() {
+        (libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_RECVTTL)
+    };
+    let on: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &on as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(super) fn enable_recv_ttl(_socket: &UdpSocket) -> io::Result<()> {
+    Ok(())
+}
+
+// only_joined_groups has socket receive the multicast packets of only the
+// groups it has joined itself (IP_MULTICAST_ALL, IPV6_MULTICAST_ALL), not
+// those of any group joined on the host. Elsewhere than Linux, this is
+// already the case.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn only_joined_groups(socket: &socket2::Socket, ipv6: bool) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name) = if ipv6 {
+        (libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_ALL)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_MULTICAST_ALL)
+    };
+    let off: libc::c_int = 0;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &off as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(super) fn only_joined_groups(_socket: &socket2::Socket, _ipv6: bool) -> io::Result<()> {
+    Ok(())
+}
+
+// enable_pktinfo asks for the interface each packet received on socket
+// arrived on, and the address it was sent to, to be reported along with it
+// (IP_PKTINFO and IPV6_RECVPKTINFO). Elsewhere than Linux, they are not.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn enable_pktinfo(socket: &UdpSocket) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name) = if socket.local_addr()?.is_ipv6() {
+        (libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_PKTINFO)
+    };
+    let on: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &on as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(super) fn enable_pktinfo(_socket: &UdpSocket) -> io::Result<()> {
+    Ok(())
+}
+
+// recv_from is UdpSocket::recv_from, also returning what is reported along
+// with the packet.
+#[cfg(unix)]
+pub(super) async fn recv_from(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, Ancillary)> {
+    use std::os::unix::io::AsRawFd;
+    use tokio::io::Interest;
+
+    loop {
+        socket.readable().await?;
+        match socket.try_io(Interest::READABLE, || recvmsg(socket.as_raw_fd(), buf)) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+            result => return result,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(super) async fn recv_from(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, Ancillary)> {
+    let (n, src) = socket.recv_from(buf).await?;
+    Ok((n, src, Ancillary::default()))
+}
+
+#[cfg(unix)]
+fn recvmsg(
+    fd: std::os::unix::io::RawFd,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, Ancillary)> {
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // u64s keep the control messages aligned
+    let mut control = [0u64; 16];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = &mut storage as *mut libc::sockaddr_storage as *mut libc::c_void;
+    msg.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    let n = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut ancillary = Ancillary::default();
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let (level, typ) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+        let data = unsafe { libc::CMSG_DATA(cmsg) };
+        if level == libc::IPPROTO_IP {
+            // Linux reports the TTL as an int, the BSDs as a byte.
+            if typ == libc::IP_TTL {
+                ancillary.ttl =
+                    Some(unsafe { std::ptr::read_unaligned(data as *const libc::c_int) } as u8);
+            } else if typ == libc::IP_RECVTTL {
+                ancillary.ttl = Some(unsafe { *data });
+            }
+        } else if level == libc::IPPROTO_IPV6 && typ == libc::IPV6_HOPLIMIT {
+            ancillary.ttl =
+                Some(unsafe { std::ptr::read_unaligned(data as *const libc::c_int) } as u8);
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        read_pktinfo(level, typ, data, &mut ancillary);
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    let src = unsafe { socket2::SockAddr::new(storage, msg.msg_namelen) }
+        .as_socket()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid source address"))?;
+    Ok((n as usize, src, ancillary))
+}
+
+// read_pktinfo notes the interface and destination address of an
+// IP_PKTINFO or IPV6_PKTINFO control message.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn read_pktinfo(level: libc::c_int, typ: libc::c_int, data: *const u8, ancillary: &mut Ancillary) {
+    if level == libc::IPPROTO_IP && typ == libc::IP_PKTINFO {
+        let info = unsafe { std::ptr::read_unaligned(data as *const libc::in_pktinfo) };
+        ancillary.interface = Some(info.ipi_ifindex as u32);
+        ancillary.dst = Some(IpAddr::from(
+            u32::from_be(info.ipi_addr.s_addr).to_be_bytes(),
+        ));
+    } else if level == libc::IPPROTO_IPV6 && typ == libc::IPV6_PKTINFO {
+        let info = unsafe { std::ptr::read_unaligned(data as *const libc::in6_pktinfo) };
+        ancillary.interface = Some(info.ipi6_ifindex as u32);
+        ancillary.dst = Some(IpAddr::from(info.ipi6_addr.s6_addr));
+    }
+}
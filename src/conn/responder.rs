@@ -0,0 +1,81 @@
+use crate::config::Config;
+use crate::conn::*;
+
+use std::net::{IpAddr, SocketAddr};
+
+use util::Error;
+
+/// MdnsResponder is the server half of a DNSConn: it answers questions for
+/// its local names, but sends no queries. It has its own socket, so it is
+/// started and closed independently of any MdnsQuerier bound to the same
+/// address; use a DNSConn to have both roles share one socket.
+pub struct MdnsResponder {
+    pub(super) conn: DNSConn,
+}
+
+impl MdnsResponder {
+    /// new starts a responder bound to addr, answering for the local names
+    /// of the config. It runs in responder-only mode, whatever the config
+    /// says.
+    pub fn new(addr: SocketAddr, mut config: Config) -> Result<Self, Error> {
+        config.responder_only = true;
+        Ok(MdnsResponder {
+            conn: DNSConn::server(addr, config)?,
+        })
+    }
+
+    /// add_local_name is DNSConn::add_local_name
+    pub async fn add_local_name(&self, name: &str) -> Result<String, Error> {
+        self.conn.add_local_name(name).await
+    }
+
+    /// remove_local_name is DNSConn::remove_local_name
+    pub async fn remove_local_name(&self, name: &str) -> bool {
+        self.conn.remove_local_name(name).await
+    }
+
+    /// on_question is DNSConn::on_question
+    pub async fn on_question(&self, f: OnQuestionHdlrFn) {
+        self.conn.on_question(f).await
+    }
+
+    /// on_name_conflict is DNSConn::on_name_conflict
+    pub async fn on_name_conflict(&self, f: OnNameConflictHdlrFn) {
+        self.conn.on_name_conflict(f).await
+    }
+
+    /// question_counters is DNSConn::question_counters
+    pub async fn question_counters(&self) -> QuestionCounters {
+        self.conn.question_counters().await
+    }
+
+    /// local_addr is DNSConn::local_addr
+    pub fn local_addr(&self) -> SocketAddr {
+        self.conn.local_addr()
+    }
+
+    /// local_addrs is DNSConn::local_addrs
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.conn.local_addrs()
+    }
+
+    /// multicast_groups is DNSConn::multicast_groups
+    pub fn multicast_groups(&self) -> Vec<(IpAddr, IpAddr)> {
+        self.conn.multicast_groups()
+    }
+
+    /// is_closed is DNSConn::is_closed
+    pub fn is_closed(&self) -> bool {
+        self.conn.is_closed()
+    }
+
+    /// close is DNSConn::close
+    pub async fn close(&self) -> Result<(), Error> {
+        self.conn.close().await
+    }
+
+    /// restart is DNSConn::restart
+    pub async fn restart(&self) -> Result<(), Error> {
+        self.conn.restart().await
+    }
+}
@@ -3,10 +3,30 @@ use util::Error;
 lazy_static! {
     pub static ref ERR_JOINING_MULTICAST_GROUP: Error =
         Error::new("mDNS: failed to join multicast group".to_owned());
+    pub static ref ERR_INVALID_MULTICAST_ADDR: Error =
+        Error::new("mDNS: multicast address must be an IPv4 multicast group".to_owned());
     pub static ref ERR_CONNECTION_CLOSED: Error =
         Error::new("mDNS: connection is closed".to_owned());
     pub static ref ERR_CONTEXT_ELAPSED: Error = Error::new("mDNS: context has elapsed".to_owned());
     pub static ref ERR_NIL_CONFIG: Error = Error::new("mDNS: config must not be nil".to_owned());
+    pub static ref ERR_UNSUPPORTED_QUERY_TYPE: Error =
+        Error::new("mDNS: unsupported query type".to_owned());
+    pub static ref ERR_QUERY_TIMEOUT: Error = Error::new("mDNS: query timed out".to_owned());
+    pub static ref ERR_QUERY_NOT_FOUND: Error =
+        Error::new("mDNS: no answer after the maximum query attempts".to_owned());
+    pub static ref ERR_QUERY_CANCELLED: Error = Error::new("mDNS: query cancelled".to_owned());
+    pub static ref ERR_UNEXPECTED_ANSWER: Error =
+        Error::new("mDNS: answer is not of the type queried".to_owned());
+    pub static ref ERR_RESPONDER_ONLY: Error =
+        Error::new("mDNS: queries are disabled in responder-only mode".to_owned());
+    pub static ref ERR_QUERIER_ONLY: Error =
+        Error::new("mDNS: local names are disabled in querier-only mode".to_owned());
+    pub static ref ERR_PROBE_CANCELLED: Error =
+        Error::new("mDNS: probing for the name was cancelled".to_owned());
+    pub static ref ERR_PORT_IN_EXCLUSIVE_USE: Error =
+        Error::new("mDNS: the port is in exclusive use by another socket".to_owned());
+    pub static ref ERR_BIND_DEVICE_UNSUPPORTED: Error =
+        Error::new("mDNS: binding to a device is only supported on Linux".to_owned());
 
     // ErrNotStarted indicates that the prerequisite information isn't
     // available yet because the previous records haven't been appropriately
@@ -22,10 +42,15 @@ lazy_static! {
     pub static ref ERR_CALC_LEN             :Error = Error::new("insufficient data for calculated length type".to_owned());
     pub static ref ERR_RESERVED             :Error = Error::new("segment prefix is reserved".to_owned());
     pub static ref ERR_TOO_MANY_PTR         :Error = Error::new("too many pointers (>10)".to_owned());
+    pub static ref ERR_NAME_TOO_LONG        :Error = Error::new("name too long (>255)".to_owned());
     pub static ref ERR_INVALID_PTR          :Error = Error::new("invalid pointer".to_owned());
     pub static ref ERR_NIL_RESOURCE_BODY     :Error = Error::new("nil resource body".to_owned());
     pub static ref ERR_RESOURCE_LEN         :Error = Error::new("insufficient data for resource body length".to_owned());
     pub static ref ERR_SEG_TOO_LONG         :Error = Error::new("segment length too long".to_owned());
+    pub static ref ERR_INVALID_ESCAPE       :Error = Error::new("invalid escape sequence in name".to_owned());
+    pub static ref ERR_MESSAGE_TOO_LARGE    :Error = Error::new("record does not fit in maximum message size".to_owned());
+    pub static ref ERR_TCP_MSG_TOO_LONG     :Error = Error::new("message too long for TCP framing".to_owned());
+    pub static ref ERR_IDNA                 :Error = Error::new("invalid internationalized domain name".to_owned());
     pub static ref ERR_ZERO_SEG_LEN         :Error = Error::new("zero length segment".to_owned());
     pub static ref ERR_RES_TOO_LONG         :Error = Error::new("resource length too long".to_owned());
     pub static ref ERR_TOO_MANY_QUESTIONS   :Error = Error::new("too many Questions to pack (>65535)".to_owned());
@@ -35,5 +60,6 @@ lazy_static! {
     pub static ref ERR_NON_CANONICAL_NAME   :Error = Error::new("name is not in canonical format (it must end with a .)".to_owned());
     pub static ref ERR_STRING_TOO_LONG      :Error = Error::new("character string exceeds maximum length (255)".to_owned());
     pub static ref ERR_COMPRESSED_SRV       :Error = Error::new("compressed name in SRV resource data".to_owned());
+    pub static ref ERR_INVALID_TYPE_BITMAP  :Error = Error::new("invalid NSEC type bitmap".to_owned());
     pub static ref ERR_EMPTY_BUILDER_MSG    :Error = Error::new("empty builder msg".to_owned());
 }
@@ -8,15 +8,16 @@ use util::Error;
 
 use std::collections::HashMap;
 
-// A Builder allows incrementally packing a DNS message.
+// A Builder allows incrementally packing a DNS message. The header counts
+// are kept up to date as records are added.
 //
 // Example usage:
-//	b := NewBuilder(Header{...})
-//	b.enable_compression()
-//	// Optionally start a section and add things to that section.
-//	// Repeat adding sections as necessary.
-//	buf, err := b.Finish()
-//	// If err is nil, buf[2:] will contain the built bytes.
+//	let buf = Builder::new(&Header { response: true, ..Default::default() })
+//	    .enable_compression()
+//	    .start_questions()?
+//	    .add_question(&question)?
+//	    .add_answer(&mut answer)?
+//	    .finish()?;
 #[derive(Default)]
 pub struct Builder {
     // msg is the storage for the message being built.
@@ -79,8 +80,9 @@ impl Builder {
     // messages.
     //
     // Compression should be enabled before any sections are added for best results.
-    pub fn enable_compression(&mut self) {
+    pub fn enable_compression(&mut self) -> &mut Self {
         self.compression = Some(HashMap::new());
+        self
     }
 
     fn start_check(&self, section: Section) -> Result<(), Error> {
@@ -95,31 +97,31 @@ impl Builder {
     }
 
     // start_questions prepares the builder for packing Questions.
-    pub fn start_questions(&mut self) -> Result<(), Error> {
+    pub fn start_questions(&mut self) -> Result<&mut Self, Error> {
         self.start_check(Section::Questions)?;
         self.section = Section::Questions;
-        Ok(())
+        Ok(self)
     }
 
     // start_answers prepares the builder for packing Answers.
-    pub fn start_answers(&mut self) -> Result<(), Error> {
+    pub fn start_answers(&mut self) -> Result<&mut Self, Error> {
         self.start_check(Section::Answers)?;
         self.section = Section::Answers;
-        Ok(())
+        Ok(self)
     }
 
     // start_authorities prepares the builder for packing Authorities.
-    pub fn start_authorities(&mut self) -> Result<(), Error> {
+    pub fn start_authorities(&mut self) -> Result<&mut Self, Error> {
         self.start_check(Section::Authorities)?;
         self.section = Section::Authorities;
-        Ok(())
+        Ok(self)
     }
 
     // start_additionals prepares the builder for packing Additionals.
-    pub fn start_additionals(&mut self) -> Result<(), Error> {
+    pub fn start_additionals(&mut self) -> Result<&mut Self, Error> {
         self.start_check(Section::Additionals)?;
         self.section = Section::Additionals;
-        Ok(())
+        Ok(self)
     }
 
     fn increment_section_count(&mut self) -> Result<(), Error> {
@@ -152,7 +154,7 @@ impl Builder {
     }
 
     // question adds a single question.
-    pub fn add_question(&mut self, q: &Question) -> Result<(), Error> {
+    pub fn add_question(&mut self, q: &Question) -> Result<&mut Self, Error> {
         if self.section < Section::Questions {
             return Err(ERR_NOT_STARTED.to_owned());
         }
@@ -161,12 +163,12 @@ impl Builder {
         }
         let msg = self.msg.take();
         if let Some(mut msg) = msg {
-            msg = q.pack(msg, &mut self.compression, self.start)?;
+            q.pack(&mut msg, &mut self.compression, self.start)?;
             self.increment_section_count()?;
             self.msg = Some(msg);
         }
 
-        Ok(())
+        Ok(self)
     }
 
     fn check_resource_section(&self) -> Result<(), Error> {
@@ -179,8 +181,8 @@ impl Builder {
         Ok(())
     }
 
-    // Resource adds a single resource.
-    pub fn add_resource(&mut self, r: &mut Resource) -> Result<(), Error> {
+    // Resource adds a single resource to the current section.
+    pub fn add_resource(&mut self, r: &mut Resource) -> Result<&mut Self, Error> {
         self.check_resource_section()?;
 
         if let Some(body) = &r.body {
@@ -189,18 +191,71 @@ impl Builder {
             return Err(ERR_NIL_RESOURCE_BODY.to_owned());
         }
 
-        if let Some(msg) = self.msg.take() {
-            let (mut msg, len_off) = r.header.pack(msg, &mut self.compression, self.start)?;
+        if let Some(mut msg) = self.msg.take() {
+            let len_off = r.header.pack(&mut msg, &mut self.compression, self.start)?;
             let pre_len = msg.len();
             if let Some(body) = &r.body {
-                msg = body.pack(msg, &mut self.compression, self.start)?;
+                body.pack(&mut msg, &mut self.compression, self.start)?;
                 r.header.fix_len(&mut msg, len_off, pre_len)?;
                 self.increment_section_count()?;
             }
             self.msg = Some(msg);
         }
 
-        Ok(())
+        Ok(self)
+    }
+
+    // add_resource_within adds a single resource to the current section if
+    // the message stays within limit bytes, and reports whether it did. A
+    // resource that doesn't fit is taken back out again, along with the name
+    // suffixes it added to the compression map.
+    pub(crate) fn add_resource_within(
+        &mut self,
+        r: &mut Resource,
+        limit: usize,
+    ) -> Result<bool, Error> {
+        let len = self.msg.as_ref().map_or(0, Vec::len);
+        let header = self.header.clone();
+        self.add_resource(r)?;
+        if let Some(msg) = &mut self.msg {
+            if msg.len() <= limit {
+                return Ok(true);
+            }
+            msg.truncate(len);
+        }
+        let start = self.start;
+        if let Some(compression) = &mut self.compression {
+            compression.retain(|_, off| *off < len - start);
+        }
+        self.header = header;
+        Ok(false)
+    }
+
+    // add_answer adds a single resource to the answer section, starting the
+    // section first if needed.
+    pub fn add_answer(&mut self, r: &mut Resource) -> Result<&mut Self, Error> {
+        if self.section != Section::Answers {
+            self.start_answers()?;
+        }
+        self.add_resource(r)
+    }
+
+    // add_authority adds a single resource to the authority section, starting
+    // the section first if needed.
+    pub fn add_authority(&mut self, r: &mut Resource) -> Result<&mut Self, Error> {
+        if self.section != Section::Authorities {
+            self.start_authorities()?;
+        }
+        self.add_resource(r)
+    }
+
+    // add_additional adds a single resource to the additional section,
+    // starting the section first if needed.
+    pub fn add_additional(&mut self, r: &mut Resource) -> Result<&mut Self, Error> {
+        if self.section != Section::Additionals {
+            self.start_additionals()?;
+        }
+        self.add_resource(r)
     }
 
     // Finish ends message building and generates a binary message.
@@ -211,7 +266,8 @@ impl Builder {
         self.section = Section::Done;
 
         // Space for the header was allocated in NewBuilder.
-        let buf = self.header.pack(vec![]);
+        let mut buf = vec![];
+        self.header.pack(&mut buf);
         assert_eq!(buf.len(), HEADER_LEN);
         if let Some(mut msg) = self.msg.take() {
             msg[..HEADER_LEN].copy_from_slice(&buf[..HEADER_LEN]);
@@ -1,7 +1,9 @@
 use super::*;
 
 // Header is a representation of a DNS message header.
-#[derive(Default, Debug, Copy, Clone, PartialEq)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct Header {
     pub id: u16,
     pub response: bool,
@@ -33,7 +35,7 @@ impl fmt::Display for Header {
 impl Header {
     pub fn pack(&self) -> (u16, u16) {
         let id = self.id;
-        let mut bits = (self.op_code as u16) << 11 | self.rcode as u16;
+        let mut bits = self.op_code << 11 | self.rcode as u16;
         if self.recursion_available {
             bits |= HEADER_BIT_RA
         }
@@ -54,8 +56,9 @@ impl Header {
     }
 }
 
-#[derive(Copy, Clone, PartialOrd, PartialEq)]
+#[derive(Default, Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
 pub enum Section {
+    #[default]
     NotStarted = 0,
     Header = 1,
     Questions = 2,
@@ -65,12 +68,6 @@ pub enum Section {
     Done = 6,
 }
 
-impl Default for Section {
-    fn default() -> Self {
-        Section::NotStarted
-    }
-}
-
 impl From<u8> for Section {
     fn from(v: u8) -> Self {
         match v {
@@ -101,7 +98,7 @@ impl fmt::Display for Section {
 }
 
 // header is the wire format for a DNS message header.
-#[derive(Default)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct HeaderInternal {
     pub id: u16,
     pub bits: u16,
@@ -123,14 +120,13 @@ impl HeaderInternal {
     }
 
     // pack appends the wire format of the header to msg.
-    pub(crate) fn pack(&self, mut msg: Vec<u8>) -> Vec<u8> {
-        msg = pack_uint16(msg, self.id);
-        msg = pack_uint16(msg, self.bits);
-        msg = pack_uint16(msg, self.questions);
-        msg = pack_uint16(msg, self.answers);
-        msg = pack_uint16(msg, self.authorities);
-        msg = pack_uint16(msg, self.additionals);
-        msg
+    pub(crate) fn pack(&self, msg: &mut Vec<u8>) {
+        pack_uint16(msg, self.id);
+        pack_uint16(msg, self.bits);
+        pack_uint16(msg, self.questions);
+        pack_uint16(msg, self.answers);
+        pack_uint16(msg, self.authorities);
+        pack_uint16(msg, self.additionals);
     }
 
     pub(crate) fn unpack(&mut self, msg: &[u8], off: usize) -> Result<usize, Error> {
@@ -4,14 +4,23 @@ use super::name::*;
 use super::parser::*;
 use super::question::*;
 use super::resource::{
-    a::*, aaaa::*, cname::*, mx::*, ns::*, opt::*, ptr::*, soa::*, srv::*, txt::*, *,
+    a::*, aaaa::*, cname::*, hinfo::*, mx::*, ns::*, nsec::*, opt::*, ptr::*, soa::*, srv::*,
+    svcb::*, txt::*, unknown::*, *,
 };
 use super::*;
 use crate::errors::*;
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::IpAddr;
 use util::Error;
 
+// ParserFn, BuilderFn and BuilderEnv are the steps run by the table driven
+// parser and builder tests.
+type ParserFn = Box<dyn Fn(&mut Parser<'_>) -> Result<(), Error>>;
+type BuilderFn = Box<dyn Fn(&mut Builder) -> Result<(), Error>>;
+type BuilderEnv = Box<dyn Fn() -> Builder>;
+
 fn small_test_msg() -> Result<Message, Error> {
     let name = Name::new("example.com.")?;
     Ok(Message {
@@ -24,6 +33,7 @@ fn small_test_msg() -> Result<Message, Error> {
             name: name.clone(),
             typ: DNSType::A,
             class: DNSCLASS_INET,
+            ..Default::default()
         }],
         answers: vec![Resource {
             header: ResourceHeader {
@@ -32,7 +42,7 @@ fn small_test_msg() -> Result<Message, Error> {
                 class: DNSCLASS_INET,
                 ..Default::default()
             },
-            body: Some(Box::new(AResource { a: [127, 0, 0, 1] })),
+            body: Some(ResourceData::A(AResource { a: [127, 0, 0, 1] })),
         }],
         authorities: vec![Resource {
             header: ResourceHeader {
@@ -41,7 +51,7 @@ fn small_test_msg() -> Result<Message, Error> {
                 class: DNSCLASS_INET,
                 ..Default::default()
             },
-            body: Some(Box::new(AResource { a: [127, 0, 0, 1] })),
+            body: Some(ResourceData::A(AResource { a: [127, 0, 0, 1] })),
         }],
         additionals: vec![Resource {
             header: ResourceHeader {
@@ -50,7 +60,7 @@ fn small_test_msg() -> Result<Message, Error> {
                 class: DNSCLASS_INET,
                 ..Default::default()
             },
-            body: Some(Box::new(AResource { a: [127, 0, 0, 1] })),
+            body: Some(ResourceData::A(AResource { a: [127, 0, 0, 1] })),
         }],
     })
 }
@@ -67,6 +77,7 @@ fn large_test_msg() -> Result<Message, Error> {
             name: name.clone(),
             typ: DNSType::A,
             class: DNSCLASS_INET,
+            ..Default::default()
         }],
         answers: vec![
             Resource {
@@ -76,7 +87,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(AResource { a: [127, 0, 0, 1] })),
+                body: Some(ResourceData::A(AResource { a: [127, 0, 0, 1] })),
             },
             Resource {
                 header: ResourceHeader {
@@ -85,7 +96,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(AResource { a: [127, 0, 0, 2] })),
+                body: Some(ResourceData::A(AResource { a: [127, 0, 0, 2] })),
             },
             Resource {
                 header: ResourceHeader {
@@ -94,7 +105,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(AAAAResource {
+                body: Some(ResourceData::AAAA(AAAAResource {
                     aaaa: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
                 })),
             },
@@ -105,7 +116,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(CNAMEResource {
+                body: Some(ResourceData::CNAME(CNAMEResource {
                     cname: Name::new("alias.example.com.")?,
                 })),
             },
@@ -116,7 +127,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(SOAResource {
+                body: Some(ResourceData::SOA(SOAResource {
                     ns: Name::new("ns1.example.com.")?,
                     mbox: Name::new("mb.example.com.")?,
                     serial: 1,
@@ -133,7 +144,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(PTRResource {
+                body: Some(ResourceData::PTR(PTRResource {
                     ptr: Name::new("ptr.example.com.")?,
                 })),
             },
@@ -144,7 +155,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(MXResource {
+                body: Some(ResourceData::MX(MXResource {
                     pref: 7,
                     mx: Name::new("mx.example.com.")?,
                 })),
@@ -156,7 +167,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(SRVResource {
+                body: Some(ResourceData::SRV(SRVResource {
                     priority: 8,
                     weight: 9,
                     port: 11,
@@ -172,7 +183,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(NSResource {
+                body: Some(ResourceData::NS(NSResource {
                     ns: Name::new("ns1.example.com.")?,
                 })),
             },
@@ -183,7 +194,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(NSResource {
+                body: Some(ResourceData::NS(NSResource {
                     ns: Name::new("ns2.example.com.")?,
                 })),
             },
@@ -196,7 +207,7 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(TXTResource {
+                body: Some(ResourceData::TXT(TXTResource {
                     txt: vec!["So Long, and Thanks for All the Fish".to_owned()],
                 })),
             },
@@ -207,13 +218,13 @@ fn large_test_msg() -> Result<Message, Error> {
                     class: DNSCLASS_INET,
                     ..Default::default()
                 },
-                body: Some(Box::new(TXTResource {
+                body: Some(ResourceData::TXT(TXTResource {
                     txt: vec!["Hamster Huey and the Gooey Kablooie".to_owned()],
                 })),
             },
             Resource {
                 header: must_edns0_resource_header(4096, 0xfe0 | (RCode::Success as u32), false)?,
-                body: Some(Box::new(OPTResource {
+                body: Some(ResourceData::OPT(OPTResource {
                     options: vec![DNSOption {
                         code: 10, // see RFC 7873
                         data: vec![0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef],
@@ -248,8 +259,10 @@ fn test_question_pack_unpack() -> Result<(), Error> {
         name: Name::new(".")?,
         typ: DNSType::A,
         class: DNSCLASS_INET,
+        ..Default::default()
     };
-    let buf = want.pack(vec![0; 1], &mut Some(HashMap::new()), 1)?;
+    let mut buf = vec![0; 1];
+    want.pack(&mut buf, &mut Some(HashMap::new()), 1)?;
     let mut p = Parser {
         msg: &buf,
         header: HeaderInternal {
@@ -280,17 +293,7 @@ fn test_question_pack_unpack() -> Result<(), Error> {
 
 #[test]
 fn test_name() -> Result<(), Error> {
-    let tests = vec![
-        "",
-        ".",
-        "google..com",
-        "google.com",
-        "google..com.",
-        "google.com.",
-        ".google.com.",
-        "www..google.com.",
-        "www.google.com.",
-    ];
+    let tests = vec!["", ".", "google.com", "google.com.", "www.google.com."];
 
     for test in tests {
         let name = Name::new(test)?;
@@ -301,6 +304,276 @@ fn test_name() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_name_labels() -> Result<(), Error> {
+    let mut name = Name::from_labels(["_http", "_tcp", "local"])?;
+    assert_eq!(name.data, "_http._tcp.local.");
+    assert_eq!(
+        name.labels().collect::<Vec<_>>(),
+        vec![&b"_http"[..], b"_tcp", b"local"]
+    );
+
+    name.prepend_label("Web Server")?;
+    assert_eq!(name.data, "Web Server._http._tcp.local.");
+
+    let mut host = Name::from_labels(Vec::<&str>::new())?;
+    assert_eq!(host.data, ".");
+    host.append_label("myhost")?;
+    host.append_label("local")?;
+    assert_eq!(host.data, "myhost.local.");
+
+    let parent = name.parent().expect("expected a parent");
+    assert_eq!(parent.data, "_http._tcp.local.");
+    assert_eq!(Name::new(".")?.parent(), None);
+    assert_eq!(Name::new("local.")?.parent(), Some(Name::new(".")?));
+
+    assert!(name.is_subdomain_of(&Name::new("_HTTP._tcp.local.")?));
+    assert!(name.is_subdomain_of(&Name::new("local.")?));
+    assert!(name.is_subdomain_of(&Name::new(".")?));
+    assert!(name.is_subdomain_of(&name));
+    assert!(!name.is_subdomain_of(&Name::new("_tcp.example.")?));
+    assert!(!parent.is_subdomain_of(&name));
+
+    let long = "a".repeat(64);
+    let tests = vec![
+        ("", ERR_ZERO_SEG_LEN.to_owned()),
+        (long.as_str(), ERR_SEG_TOO_LONG.to_owned()),
+    ];
+    for (label, want) in tests {
+        if let Err(err) = host.prepend_label(label) {
+            assert_eq!(err, want, "{:?}", label);
+        } else {
+            panic!("{:?} expected error, but got ok", label);
+        }
+    }
+    assert_eq!(host.data, "myhost.local.");
+
+    Ok(())
+}
+
+#[test]
+fn test_name_escaped_labels() -> Result<(), Error> {
+    let mut name = Name::from_labels(["_ipp", "_tcp", "local"])?;
+    name.prepend_label("Living Room Printer v1.2")?;
+    assert_eq!(name.data, "Living Room Printer v1\\.2._ipp._tcp.local.");
+    assert_eq!(
+        name.labels().collect::<Vec<_>>(),
+        vec![&b"Living Room Printer v1.2"[..], b"_ipp", b"_tcp", b"local"]
+    );
+    assert_eq!(
+        name.parent(),
+        Some(Name::new("_ipp._tcp.local.")?),
+        "escaped dot must not split a label"
+    );
+
+    let mut b = vec![];
+    name.pack(&mut b, &mut None, 0)?;
+    assert_eq!(b[0] as usize, "Living Room Printer v1.2".len());
+    let mut got = Name::default();
+    got.unpack(&b, 0)?;
+    assert_eq!(got, name);
+
+    let (got, _) = NameRef::unpack(&b, 0)?;
+    assert_eq!(got.to_name(), name);
+    assert!(got.eq_name(&name));
+
+    let tests = vec![
+        ("back\\\\slash.local.", "back\\\\slash.local.", 1 + 10),
+        ("\\065\\066c.local.", "ABc.local.", 1 + 3),
+        ("x\\y.local.", "xy.local.", 1 + 2),
+    ];
+    for (input, want, want_first) in tests {
+        let mut b = vec![];
+        Name::new(input)?.pack(&mut b, &mut None, 0)?;
+        assert_eq!(b[0] as usize + 1, want_first, "{}", input);
+        let mut got = Name::default();
+        got.unpack(&b, 0)?;
+        assert_eq!(got.data, want, "{}", input);
+    }
+
+    // labels that aren't UTF-8 survive being taken apart and rebuilt
+    let mut name = Name::new("x.a\\255b.local")?;
+    assert_eq!(
+        name.labels().collect::<Vec<_>>(),
+        vec![&b"x"[..], b"a\xFFb", b"local"]
+    );
+    assert_eq!(name.parent(), Some(Name::new("a\\255b.local.")?));
+    name.prepend_label("y")?;
+    assert_eq!(name.data, "y.x.a\\255b.local.");
+    name.append_label("z")?;
+    assert_eq!(name.data, "y.x.a\\255b.local.z.");
+    let mut b = vec![];
+    name.pack(&mut b, &mut None, 0)?;
+    let (got, _) = NameRef::unpack(&b, 0)?;
+    assert!(got.eq_name(&name));
+    assert_eq!(got.to_name().data, "y.x.a\\255b.local.z.");
+
+    // and so do labels read off the wire that aren't UTF-8 or printable
+    let b = [
+        3, b'a', 0xFF, b'b', 2, 0x01, b'c', 5, b'l', b'o', b'c', b'a', b'l', 0,
+    ];
+    let mut got = Name::default();
+    assert_eq!(got.unpack(&b, 0)?, b.len());
+    assert_eq!(got.data, "a\\255b.\\001c.local.");
+    let mut buf = vec![];
+    got.pack(&mut buf, &mut None, 0)?;
+    assert_eq!(buf, b);
+    let (got_ref, _) = NameRef::unpack(&b, 0)?;
+    assert_eq!(got_ref.to_name(), got);
+
+    for input in ["bad\\", "bad\\25.", "bad\\256."].iter() {
+        if let Err(err) = Name::new(input) {
+            assert_eq!(err, *ERR_INVALID_ESCAPE, "{}", input);
+        } else {
+            panic!("{}: expected error, but got ok", input);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_name_utf8() -> Result<(), Error> {
+    let tests = vec![
+        "Büro-Drucker._ipp._tcp.local.",
+        "Jérôme’s MacBook Pro._companion-link._tcp.local.",
+        "客厅的 Apple TV._airplay._tcp.local.",
+        "Wohnzimmer 🔊._googlecast._tcp.local.",
+        "Дача-ПК.local.",
+    ];
+
+    for test in tests {
+        let name = Name::new(test)?;
+        let mut b = vec![];
+        name.pack(&mut b, &mut Some(HashMap::new()), 0)?;
+        let first = test.split('.').next().unwrap();
+        assert_eq!(b[0] as usize, first.len(), "{}", test);
+        assert_eq!(&b[1..=first.len()], first.as_bytes(), "{}", test);
+
+        let mut got = Name::default();
+        let n = got.unpack(&b, 0)?;
+        assert_eq!(n, b.len());
+        assert_eq!(got.data, test);
+
+        let (got, _) = NameRef::unpack(&b, 0)?;
+        assert_eq!(got.to_string(), test);
+        assert!(got.eq_name(&name));
+    }
+
+    // Label limits count bytes, not characters.
+    let label = "ü".repeat(32);
+    if let Err(err) = Name::new(&format!("{}.local.", label)) {
+        assert_eq!(err, *ERR_SEG_TOO_LONG);
+    } else {
+        panic!("expected error, but got ok");
+    }
+    Name::new(&format!("{}.local.", "ü".repeat(31)))?;
+
+    // Only ASCII letters fold.
+    let name = Name::new("Büro.local.")?;
+    assert!(!name.eq_ignore_ascii_case(&Name::new("bÜRO.LOCAL.")?));
+    assert!(name.eq_ignore_ascii_case(&Name::new("BüRO.LOCAL.")?));
+
+    // Labels that aren't valid UTF-8 are escaped.
+    let b = [2, 0xC3, 0x28, 0];
+    let mut got = Name::default();
+    got.unpack(&b, 0)?;
+    assert_eq!(got.data, "\\195(.");
+
+    Ok(())
+}
+
+#[cfg(feature = "idna")]
+#[test]
+fn test_name_idna() -> Result<(), Error> {
+    let tests = vec![
+        ("bücher.example.", "xn--bcher-kva.example."),
+        ("münchen.local", "xn--mnchen-3ya.local"),
+        ("_http._tcp.local.", "_http._tcp.local."),
+        (".", "."),
+    ];
+
+    for (unicode, ascii) in tests {
+        let got = Name::new(unicode)?.to_ascii()?;
+        assert_eq!(got.data, ascii, "{}.to_ascii()", unicode);
+        let got = Name::new(ascii)?.to_unicode()?;
+        assert_eq!(got.data, unicode, "{}.to_unicode()", ascii);
+    }
+
+    // Non-IDNA labels such as service instance names are left untouched.
+    let name = Name::new("My Printer._ipp._tcp.local.")?;
+    assert_eq!(name.to_ascii()?, name);
+    assert_eq!(name.to_unicode()?, name);
+
+    if let Err(err) = Name::new("xn--a.local.")?.to_unicode() {
+        assert_eq!(err, *ERR_IDNA);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_name_eq_ignore_ascii_case() -> Result<(), Error> {
+    let name = Name::new("MyHost.local.")?;
+    let tests = vec![
+        ("myhost.local.", true),
+        ("MYHOST.LOCAL.", true),
+        ("myhost.local", true),
+        ("myhost.", false),
+        ("other.local.", false),
+        ("www.myhost.local.", false),
+    ];
+
+    for (other, want) in tests {
+        let got = name.eq_ignore_ascii_case(&Name::new(other)?);
+        assert_eq!(got, want, "{} eq_ignore_ascii_case {}", name, other);
+    }
+    assert_ne!(name, Name::new("myhost.local.")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_name_new_validation() -> Result<(), Error> {
+    let long_label = "a".repeat(64);
+    let max_label = "a".repeat(63);
+    let long_name = format!("{}.{}.{}.{}.", max_label, max_label, max_label, max_label);
+    let max_name = format!(
+        "{}.{}.{}.{}.",
+        max_label,
+        max_label,
+        max_label,
+        "a".repeat(61)
+    );
+
+    let tests = vec![
+        ("google..com.", ERR_ZERO_SEG_LEN.to_owned()),
+        (".google.com.", ERR_ZERO_SEG_LEN.to_owned()),
+        ("google.com..", ERR_ZERO_SEG_LEN.to_owned()),
+        ("..", ERR_ZERO_SEG_LEN.to_owned()),
+        (&long_label, ERR_SEG_TOO_LONG.to_owned()),
+        (&long_name, ERR_NAME_TOO_LONG.to_owned()),
+    ];
+
+    for (input, want_err) in tests {
+        if let Err(err) = Name::new(input) {
+            assert_eq!(err, want_err, "Name::new({})", input);
+        } else {
+            panic!("Name::new({}): expected error, but got ok", input);
+        }
+    }
+
+    let name = Name::new(&max_name)?;
+    let mut buf = vec![];
+    name.pack(&mut buf, &mut None, 0)?;
+    assert_eq!(buf.len(), 255);
+    Name::new(&format!("{}.local.", max_label))?;
+
+    Ok(())
+}
+
 #[test]
 fn test_name_pack_unpack() -> Result<(), Error> {
     let tests = vec![
@@ -316,21 +589,23 @@ fn test_name_pack_unpack() -> Result<(), Error> {
     ];
 
     for (input, want, want_err) in tests {
-        let input = Name::new(input)?;
-        let result = input.pack(vec![], &mut Some(HashMap::new()), 0);
+        // Bypass Name::new so that pack's own checks are exercised.
+        let input = Name {
+            data: input.to_owned(),
+        };
+        let mut buf = vec![];
+        let result = input.pack(&mut buf, &mut Some(HashMap::new()), 0);
         if let Some(want_err) = want_err {
             if let Err(actual_err) = result {
                 assert_eq!(want_err, actual_err);
             } else {
-                assert!(false);
+                panic!();
             }
             continue;
         } else {
             assert!(result.is_ok());
         }
 
-        let buf = result.unwrap();
-
         let want = Name::new(want)?;
 
         let mut got = Name::default();
@@ -358,8 +633,9 @@ fn test_name_pack_unpack() -> Result<(), Error> {
 fn test_incompressible_name() -> Result<(), Error> {
     let name = Name::new("example.com.")?;
     let mut compression = Some(HashMap::new());
-    let buf = name.pack(vec![], &mut compression, 0)?;
-    let buf = name.pack(buf, &mut compression, 0)?;
+    let mut buf = vec![];
+    name.pack(&mut buf, &mut compression, 0)?;
+    name.pack(&mut buf, &mut compression, 0)?;
     let mut n1 = Name::default();
     let off = n1.unpack_compressed(&buf, 0, false /* allowCompression */)?;
     let mut n2 = Name::default();
@@ -373,9 +649,103 @@ fn test_incompressible_name() -> Result<(), Error> {
             ERR_COMPRESSED_SRV.to_owned()
         );
     } else {
-        assert!(false);
+        panic!();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_name_unpack_malicious() -> Result<(), Error> {
+    // A chain of eleven pointers, each pointing at the previous one, ending
+    // at the root name in offset 0.
+    let mut chain = vec![0x00];
+    let mut prev = 0;
+    for _ in 0..11 {
+        let off = chain.len();
+        chain.extend_from_slice(&[0xC0, prev as u8]);
+        prev = off;
+    }
+
+    // Four 63 byte labels expand to a 256 byte name.
+    let mut long = vec![];
+    for _ in 0..4 {
+        long.push(63);
+        long.extend_from_slice(&[b'a'; 63]);
+    }
+    long.push(0);
+
+    let tests = vec![
+        (
+            "pointer to itself",
+            vec![0xC0, 0x00],
+            0,
+            ERR_INVALID_PTR.to_owned(),
+        ),
+        (
+            "forward pointer loop",
+            vec![0xC0, 0x02, 0xC0, 0x00],
+            0,
+            ERR_INVALID_PTR.to_owned(),
+        ),
+        (
+            "too many pointers",
+            chain.clone(),
+            prev,
+            ERR_TOO_MANY_PTR.to_owned(),
+        ),
+        ("name too long", long, 0, ERR_NAME_TOO_LONG.to_owned()),
+    ];
+
+    for (name, msg, off, want) in tests {
+        let mut n = Name::default();
+        if let Err(err) = n.unpack(&msg, off) {
+            assert_eq!(err, want, "{}", name);
+        } else {
+            panic!("{} expected error, but got ok", name);
+        }
     }
 
+    // Ten hops are still fine.
+    let mut n = Name::default();
+    n.unpack(&chain[..chain.len() - 2], prev - 2)?;
+    assert_eq!(n.data, ".");
+
+    Ok(())
+}
+
+#[test]
+fn test_name_unpack_max_len() -> Result<(), Error> {
+    // Three 63 byte labels and a last_len byte one, with the length bytes
+    // and the root byte.
+    let wire_name = |last_len: usize| {
+        let mut b = vec![];
+        for len in [63, 63, 63, last_len] {
+            b.push(len as u8);
+            b.extend(std::iter::repeat(b'a').take(len));
+        }
+        b.push(0);
+        b
+    };
+
+    let b = wire_name(61);
+    assert_eq!(b.len(), 255);
+    let mut n = Name::default();
+    assert_eq!(n.unpack(&b, 0)?, 255);
+    assert!(Name::new(&n.data).is_ok());
+    let (got, off) = NameRef::unpack(&b, 0)?;
+    assert_eq!(off, 255);
+    assert_eq!(got.to_name(), n);
+
+    let b = wire_name(62);
+    assert_eq!(b.len(), 256);
+    let mut n = Name::default();
+    assert_eq!(n.unpack(&b, 0), Err(ERR_NAME_TOO_LONG.to_owned()));
+    assert_eq!(
+        NameRef::unpack(&b, 0).map(|(_, off)| off),
+        Err(ERR_NAME_TOO_LONG.to_owned())
+    );
+
     Ok(())
 }
 
@@ -404,7 +774,7 @@ fn test_header_unpack_error() -> Result<(), Error> {
 #[test]
 fn test_parser_start() -> Result<(), Error> {
     let mut p = Parser::default();
-    let result = p.start(&vec![]);
+    let result = p.start(&[]);
     assert!(result.is_err());
 
     Ok(())
@@ -412,7 +782,7 @@ fn test_parser_start() -> Result<(), Error> {
 
 #[test]
 fn test_resource_not_started() -> Result<(), Error> {
-    let tests: Vec<(&str, Box<dyn Fn(&mut Parser<'_>) -> Result<(), Error>>)> = vec![
+    let tests: Vec<(&str, ParserFn)> = vec![
         (
             "CNAMEResource",
             Box::new(|p: &mut Parser<'_>| -> Result<(), Error> {
@@ -505,57 +875,820 @@ fn test_resource_not_started() -> Result<(), Error> {
         ),
     ];
 
-    for (name, test_fn) in tests {
-        let mut p = Parser::default();
-        if let Err(err) = test_fn(&mut p) {
-            assert_eq!(err, ERR_NOT_STARTED.to_owned(), "{}", name);
+    for (name, test_fn) in tests {
+        let mut p = Parser::default();
+        if let Err(err) = test_fn(&mut p) {
+            assert_eq!(err, ERR_NOT_STARTED.to_owned(), "{}", name);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_srv_pack_unpack() -> Result<(), Error> {
+    let want = ResourceData::SRV(SRVResource {
+        priority: 8,
+        weight: 9,
+        port: 11,
+        target: Name::new("srv.example.com.")?,
+    });
+
+    let mut b = vec![];
+    want.pack(&mut b, &mut None, 0)?;
+    let mut got = SRVResource::default();
+    got.unpack(&b, 0, 0)?;
+    assert_eq!(got.to_string(), want.to_string(),);
+
+    Ok(())
+}
+
+#[test]
+fn test_srv_message_pack_unpack() -> Result<(), Error> {
+    let name = Name::new("_http._tcp.example.local.")?;
+    let mut want = Message {
+        header: Header {
+            response: true,
+            authoritative: true,
+            ..Default::default()
+        },
+        answers: vec![Resource {
+            header: ResourceHeader {
+                name,
+                typ: DNSType::SRV,
+                class: DNSCLASS_INET,
+                ttl: 120,
+                ..Default::default()
+            },
+            body: Some(ResourceData::SRV(SRVResource {
+                priority: 0,
+                weight: 5,
+                port: 8080,
+                target: Name::new("host.example.local.")?,
+            })),
+        }],
+        ..Default::default()
+    };
+
+    let b = want.pack()?;
+    let mut got = Message::default();
+    got.unpack(&b)?;
+    assert_eq!(got.to_string(), want.to_string());
+
+    Ok(())
+}
+
+#[test]
+fn test_srv_unpack_compressed_target() -> Result<(), Error> {
+    // priority, weight and port followed by a pointer to offset 0.
+    let b = [0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0xC0, 0x00];
+    let mut got = SRVResource::default();
+    if let Err(err) = got.unpack(&b, 0, b.len()) {
+        assert_eq!(err, *ERR_COMPRESSED_SRV);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_ptr_pack_unpack() -> Result<(), Error> {
+    let want = PTRResource {
+        ptr: Name::new("printer.local.")?,
+    };
+
+    let mut b = vec![];
+    want.pack(&mut b, &mut None, 0)?;
+    assert_eq!(
+        b,
+        vec![7, b'p', b'r', b'i', b'n', b't', b'e', b'r', 5, b'l', b'o', b'c', b'a', b'l', 0]
+    );
+
+    let mut got = PTRResource::default();
+    let off = got.unpack(&b, 0, b.len())?;
+    assert_eq!(off, b.len());
+    assert_eq!(got, want);
+
+    Ok(())
+}
+
+#[test]
+fn test_ptr_message_compression() -> Result<(), Error> {
+    let mut want = Message {
+        header: Header {
+            response: true,
+            ..Default::default()
+        },
+        answers: vec![Resource {
+            header: ResourceHeader {
+                name: Name::new("_ipp._tcp.local.")?,
+                typ: DNSType::PTR,
+                class: DNSCLASS_INET,
+                ttl: 4500,
+                ..Default::default()
+            },
+            body: Some(ResourceData::PTR(PTRResource {
+                ptr: Name::new("printer._ipp._tcp.local.")?,
+            })),
+        }],
+        ..Default::default()
+    };
+
+    let b = want.pack()?;
+    // The PTR target shares the "_ipp._tcp.local." suffix with the owner
+    // name, so it must be packed as a label followed by a pointer.
+    let rdata = &b[b.len() - 10..];
+    assert_eq!(
+        rdata,
+        &[
+            7,
+            b'p',
+            b'r',
+            b'i',
+            b'n',
+            b't',
+            b'e',
+            b'r',
+            0xC0,
+            HEADER_LEN as u8
+        ]
+    );
+
+    let mut got = Message::default();
+    got.unpack(&b)?;
+    assert_eq!(got.to_string(), want.to_string());
+
+    Ok(())
+}
+
+#[test]
+fn test_nsec_pack_unpack() -> Result<(), Error> {
+    let want = NSECResource {
+        next_domain: Name::new("host.local.")?,
+        types: vec![DNSType::AAAA, DNSType::A, DNSType::A],
+    };
+
+    let mut b = vec![];
+    want.pack(&mut b, &mut None, 0)?;
+    let bitmap = &b[b.len() - 6..];
+    assert_eq!(bitmap, &[0x00, 0x04, 0x40, 0x00, 0x00, 0x08]);
+
+    let mut got = NSECResource::default();
+    let off = got.unpack(&b, 0, b.len())?;
+    assert_eq!(off, b.len());
+    assert_eq!(got.next_domain, want.next_domain);
+    assert_eq!(got.types, vec![DNSType::A, DNSType::AAAA]);
+
+    let tests = vec![
+        ("empty bitmap", vec![0x00, 0x00, 0x00]),
+        ("bitmap too long", vec![0x00, 0x00, 0x21]),
+        (
+            "windows out of order",
+            vec![0x00, 0x01, 0x01, 0x40, 0x00, 0x01, 0x40],
+        ),
+        ("truncated window", vec![0x00, 0x00]),
+    ];
+    for (name, b) in tests {
+        let mut got = NSECResource::default();
+        if let Err(err) = got.unpack(&b, 0, b.len()) {
+            assert_eq!(err, *ERR_INVALID_TYPE_BITMAP, "{}", name);
+        } else {
+            panic!("{} expected error, but got ok", name);
+        }
+    }
+
+    // A next domain name running past the rdata.
+    let mut got = NSECResource::default();
+    if let Err(err) = got.unpack(&b, 0, 4) {
+        assert_eq!(err, *ERR_CALC_LEN);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hinfo_pack_unpack() -> Result<(), Error> {
+    let want = HINFOResource {
+        cpu: "ARM64".to_owned(),
+        os: "LINUX".to_owned(),
+    };
+
+    let mut b = vec![];
+    want.pack(&mut b, &mut None, 0)?;
+    assert_eq!(
+        b,
+        vec![5, b'A', b'R', b'M', b'6', b'4', 5, b'L', b'I', b'N', b'U', b'X']
+    );
+
+    let mut got = HINFOResource::default();
+    let off = got.unpack(&b, 0, b.len())?;
+    assert_eq!(off, b.len());
+    assert_eq!(got, want);
+
+    // The strings must fill the rdata exactly: neither leave some over nor
+    // run past it into what follows.
+    let mut trailing = b.clone();
+    trailing.extend_from_slice(&[0xDE, 0xAD]);
+    for (name, length) in [
+        ("trailing rdata", trailing.len()),
+        ("short rdata", b.len() - 1),
+    ] {
+        let mut got = HINFOResource::default();
+        if let Err(err) = got.unpack(&trailing, 0, length) {
+            assert_eq!(err, *ERR_CALC_LEN, "{}", name);
+        } else {
+            panic!("{} expected error, but got ok", name);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_soa_pack_unpack() -> Result<(), Error> {
+    let want = SOAResource {
+        ns: Name::new("ns1.example.com.")?,
+        mbox: Name::new("hostmaster.example.com.")?,
+        serial: 2021010101,
+        refresh: 7200,
+        retry: 3600,
+        expire: 1209600,
+        min_ttl: 300,
+    };
+
+    let mut compression = Some(HashMap::new());
+    let mut b = vec![];
+    want.pack(&mut b, &mut compression, 0)?;
+    let mut got = SOAResource::default();
+    let off = got.unpack(&b, 0, b.len())?;
+    assert_eq!(off, b.len());
+    assert_eq!(got, want);
+
+    // Dropping the last byte must fail rather than silently zero min_ttl.
+    let mut got = SOAResource::default();
+    if let Err(err) = got.unpack(&b[..b.len() - 1], 0, b.len() - 1) {
+        assert_eq!(err, *ERR_BASE_LEN);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_mx_pack_unpack() -> Result<(), Error> {
+    let want = MXResource {
+        pref: 10,
+        mx: Name::new("mail.example.com.")?,
+    };
+
+    let mut b = vec![];
+    want.pack(&mut b, &mut None, 0)?;
+    assert_eq!(&b[..2], &[0x00, 0x0A]);
+
+    let mut got = MXResource::default();
+    let off = got.unpack(&b, 0, b.len())?;
+    assert_eq!(off, b.len());
+    assert_eq!(got, want);
+
+    let mut msg = Message {
+        answers: vec![Resource {
+            header: ResourceHeader {
+                name: Name::new("example.com.")?,
+                typ: DNSType::MX,
+                class: DNSCLASS_INET,
+                ..Default::default()
+            },
+            body: Some(ResourceData::MX(want.clone())),
+        }],
+        ..Default::default()
+    };
+    let b = msg.pack()?;
+
+    let mut p = Parser::default();
+    p.start(&b)?;
+    p.skip_all_questions()?;
+    let h = p.answer_header()?;
+    assert_eq!(h.typ, DNSType::MX);
+    let body = p.resource_body()?;
+    assert_eq!(body.to_string(), want.to_string());
+
+    Ok(())
+}
+
+#[test]
+fn test_ns_authority_unpack() -> Result<(), Error> {
+    // A unicast response to "example.com. NS" whose authority section holds
+    // a compressed NS record.
+    let b = [
+        0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, // header
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, // name
+        0x00, 0x02, 0x00, 0x01, // type NS, class INET
+        0xC0, 0x0C, // name pointer to example.com.
+        0x00, 0x02, 0x00, 0x01, // type NS, class INET
+        0x00, 0x00, 0x0E, 0x10, // ttl
+        0x00, 0x06, // length
+        0x03, b'n', b's', b'1', 0xC0, 0x0C, // ns1.example.com.
+    ];
+
+    let mut p = Parser::default();
+    let h = p.start(&b)?;
+    assert_eq!(h.id, 0x1234);
+    p.skip_all_questions()?;
+    p.skip_all_answers()?;
+
+    let r = p.authority()?;
+    assert_eq!(r.header.name.data, "example.com.");
+    assert_eq!(r.header.typ, DNSType::NS);
+    assert_eq!(r.header.ttl, 3600);
+    let want = NSResource {
+        ns: Name::new("ns1.example.com.")?,
+    };
+    assert_eq!(r.body.map(|b| b.to_string()), Some(want.to_string()));
+
+    let mut b = vec![];
+    want.pack(&mut b, &mut None, 0)?;
+    let mut got = NSResource::default();
+    got.unpack(&b, 0, b.len())?;
+    assert_eq!(got, want);
+
+    Ok(())
+}
+
+#[test]
+fn test_svcb_pack_unpack() -> Result<(), Error> {
+    let want = HTTPSResource {
+        svcb: SVCBResource {
+            priority: 1,
+            target: Name::new(".")?,
+            params: vec![
+                SVCBParam {
+                    key: SVC_PARAM_ALPN,
+                    value: vec![2, b'h', b'2'],
+                },
+                SVCBParam {
+                    key: SVC_PARAM_PORT,
+                    value: vec![0x1F, 0x90],
+                },
+            ],
+        },
+    };
+
+    let mut b = vec![];
+    want.pack(&mut b, &mut None, 0)?;
+    assert_eq!(
+        b,
+        vec![
+            0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x03, 0x02, b'h', b'2', 0x00, 0x03, 0x00, 0x02,
+            0x1F, 0x90,
+        ]
+    );
+
+    let mut got = HTTPSResource::default();
+    let off = got.unpack(&b, 0, b.len())?;
+    assert_eq!(off, b.len());
+    assert_eq!(got, want);
+    assert_eq!(got.svcb.param(SVC_PARAM_PORT), Some(&[0x1F, 0x90][..]));
+    assert_eq!(got.svcb.param(SVC_PARAM_ECH), None);
+
+    let mut got = SVCBResource::default();
+    if let Err(err) = got.unpack(&b, 0, b.len() - 1) {
+        assert_eq!(err, *ERR_CALC_LEN);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_unknown_resource_pack_unpack() -> Result<(), Error> {
+    let mut want = Message {
+        header: Header {
+            response: true,
+            ..Default::default()
+        },
+        answers: vec![Resource {
+            header: ResourceHeader {
+                name: Name::new("host.local.")?,
+                class: DNSCLASS_INET,
+                ttl: 120,
+                ..Default::default()
+            },
+            body: Some(ResourceData::Unknown(UnknownResource {
+                typ: DNSType(0xFF00),
+                data: vec![0x01, 0x02, 0x03],
+            })),
+        }],
+        ..Default::default()
+    };
+    let b = want.pack()?;
+
+    let mut p = Parser::default();
+    p.start(&b)?;
+    p.skip_all_questions()?;
+    let r = p.answer()?;
+    assert_eq!(r.header.typ, DNSType(0xFF00));
+    assert_eq!(r.header.typ.to_string(), "TYPE65280");
+    #[allow(deprecated)]
+    let unsupported = DNSType::Unsupported;
+    assert_eq!(unsupported, DNSType::UNSUPPORTED);
+    assert_eq!(u16::from(unsupported), 0);
+    assert_eq!(
+        r.body.as_ref().map(|b| b.to_string()),
+        want.answers[0].body.as_ref().map(|b| b.to_string())
+    );
+
+    let mut got = Message::default();
+    got.unpack(&b)?;
+    assert_eq!(got.pack()?, b);
+
+    Ok(())
+}
+
+#[test]
+fn test_message_pack_compression() -> Result<(), Error> {
+    let answer = |name: &str, a: [u8; 4]| -> Result<Resource, Error> {
+        Ok(Resource {
+            header: ResourceHeader {
+                name: Name::new(name)?,
+                typ: DNSType::A,
+                class: DNSCLASS_INET,
+                ttl: 120,
+                ..Default::default()
+            },
+            body: Some(ResourceData::A(AResource { a })),
+        })
+    };
+
+    let mut msg = Message {
+        header: Header {
+            response: true,
+            ..Default::default()
+        },
+        answers: vec![
+            answer("héllo.example.local.", [10, 0, 0, 1])?,
+            answer("héllo.example.local.", [10, 0, 0, 2])?,
+            answer("other.example.local.", [10, 0, 0, 3])?,
+        ],
+        ..Default::default()
+    };
+    let b = msg.pack()?;
+
+    // The first name is written in full (22 bytes), the second is a single
+    // pointer and the third is one label followed by a pointer.
+    let rr_fixed = 2 + 2 + 4 + 2 + 4;
+    assert_eq!(
+        b.len(),
+        HEADER_LEN + (22 + rr_fixed) + (2 + rr_fixed) + (8 + rr_fixed)
+    );
+
+    let mut got = Message::default();
+    got.unpack(&b)?;
+    assert_eq!(got.to_string(), msg.to_string());
+
+    Ok(())
+}
+
+#[test]
+fn test_dns_pack_unpack() -> Result<(), Error> {
+    let wants = vec![
+        Message {
+            header: Header::default(),
+            questions: vec![Question {
+                name: Name::new(".")?,
+                typ: DNSType::AAAA,
+                class: DNSCLASS_INET,
+                ..Default::default()
+            }],
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![],
+        },
+        large_test_msg()?,
+    ];
+
+    for mut want in wants {
+        let b = want.pack()?;
+        let mut got = Message::default();
+        got.unpack(&b)?;
+        assert_eq!(got.to_string(), want.to_string());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_message_try_from() -> Result<(), Error> {
+    let mut want = large_test_msg()?;
+    let b = want.pack()?;
+
+    let got = Message::try_from(b.as_slice())?;
+    assert_eq!(got.to_string(), want.to_string());
+
+    if let Err(err) = Message::try_from(&b[..HEADER_LEN - 1]) {
+        assert_eq!(err, *ERR_BASE_LEN);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_resource_data_match() -> Result<(), Error> {
+    let mut want = large_test_msg()?;
+    let b = want.pack()?;
+    let got = Message::try_from(b.as_slice())?;
+    assert_eq!(got.answers, want.answers);
+    assert_eq!(got.authorities, want.authorities);
+    assert_eq!(got.additionals, want.additionals);
+
+    let mut addrs = vec![];
+    for answer in &got.answers {
+        match &answer.body {
+            Some(ResourceData::A(a)) => addrs.push(a.a.to_vec()),
+            Some(ResourceData::AAAA(aaaa)) => addrs.push(aaaa.aaaa.to_vec()),
+            _ => {}
+        }
+    }
+    assert_eq!(addrs.len(), 3);
+    assert_eq!(addrs[1], vec![127, 0, 0, 2]);
+
+    Ok(())
+}
+
+#[test]
+fn test_resource_accessors() -> Result<(), Error> {
+    let want = large_test_msg()?;
+
+    let ips: Vec<IpAddr> = want.answers.iter().filter_map(|r| r.ip()).collect();
+    assert_eq!(
+        ips,
+        vec![
+            IpAddr::from([127, 0, 0, 1]),
+            IpAddr::from([127, 0, 0, 2]),
+            IpAddr::from([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]),
+        ]
+    );
+
+    let a = &want.answers[0];
+    assert_eq!(a.as_a().map(|r| r.a), Some([127, 0, 0, 1]));
+    assert!(a.as_aaaa().is_none());
+    assert!(a.as_txt().is_none());
+
+    for r in want.authorities.iter().chain(want.additionals.iter()) {
+        assert!(r.ip().is_none());
+        match &r.body {
+            Some(ResourceData::NS(ns)) => assert_eq!(r.as_ns(), Some(ns)),
+            Some(ResourceData::TXT(txt)) => assert_eq!(r.as_txt(), Some(txt)),
+            _ => {}
+        }
+    }
+
+    let none = Resource::default();
+    assert!(none.ip().is_none());
+    assert!(none.as_a().is_none());
+
+    Ok(())
+}
+
+#[cfg(feature = "fuzz")]
+#[test]
+fn test_arbitrary_round_trip() -> Result<(), Error> {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    // A simple LCG keeps the inputs deterministic.
+    let mut seed: u64 = 0x5EED;
+    let mut packed = 0;
+    for _ in 0..2000 {
+        let data: Vec<u8> = (0..1024)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (seed >> 56) as u8
+            })
+            .collect();
+        let mut u = Unstructured::new(&data);
+        if let Ok(mut msg) = Message::arbitrary(&mut u) {
+            if msg.round_trip().is_ok() {
+                packed += 1;
+            }
         }
     }
+    assert!(packed > 0, "no arbitrary message could be packed");
 
     Ok(())
 }
 
 #[test]
-fn test_srv_pack_unpack() -> Result<(), Error> {
-    let want = Box::new(SRVResource {
-        priority: 8,
-        weight: 9,
-        port: 11,
-        target: Name::new("srv.example.com.")?,
-    });
+fn test_message_clone_eq() -> Result<(), Error> {
+    let mut want = large_test_msg()?;
+    let b = want.pack()?;
 
-    let b = want.pack(vec![], &mut None, 0)?;
-    let mut got = SRVResource::default();
-    got.unpack(&b, 0, 0)?;
-    assert_eq!(got.to_string(), want.to_string(),);
+    let got = Message::try_from(b.as_slice())?;
+    assert_eq!(got, want);
+
+    let mut changed = got.clone();
+    assert_eq!(changed, got);
+    changed.questions[0].typ = DNSType::AAAA;
+    assert_ne!(changed, got);
 
     Ok(())
 }
 
+#[cfg(feature = "serde")]
 #[test]
-fn test_dns_pack_unpack() -> Result<(), Error> {
-    let wants = vec![
-        Message {
-            header: Header::default(),
-            questions: vec![Question {
-                name: Name::new(".")?,
-                typ: DNSType::AAAA,
+fn test_message_serde() -> Result<(), Error> {
+    let mut want = large_test_msg()?;
+    want.pack()?;
+
+    let json = serde_json::to_string(&want).map_err(|e| Error::new(e.to_string()))?;
+    assert!(json.contains(r#""name":"foo.bar.example.com.""#));
+
+    let got: Message = serde_json::from_str(&json).map_err(|e| Error::new(e.to_string()))?;
+    assert_eq!(got, want);
+
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_message_json() -> Result<(), Error> {
+    let msg = Message {
+        header: Header {
+            id: 1,
+            response: true,
+            authoritative: true,
+            ..Default::default()
+        },
+        answers: vec![Resource {
+            header: ResourceHeader {
+                name: Name::new("myhost.local.")?,
+                typ: DNSType::A,
                 class: DNSCLASS_INET,
-            }],
-            answers: vec![],
-            authorities: vec![],
-            additionals: vec![],
+                cache_flush: true,
+                ttl: 120,
+                length: 4,
+            },
+            body: Some(ResourceData::A(AResource { a: [10, 0, 0, 1] })),
+        }],
+        ..Default::default()
+    };
+
+    // The schema is part of the API; changing it breaks stored captures.
+    let json = msg.to_json()?;
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"header":{"id":1,"response":true,"op_code":0,"authoritative":true,"#,
+            r#""truncated":false,"recursion_desired":false,"recursion_available":false,"#,
+            r#""rcode":"Success"},"questions":[],"answers":[{"header":{"name":"myhost.local.","#,
+            r#""typ":1,"class":1,"cache_flush":true,"ttl":120,"length":4},"#,
+            r#""body":{"A":{"a":[10,0,0,1]}}}],"authorities":[],"additionals":[]}"#,
+        )
+    );
+    assert_eq!(Message::from_json(&json)?, msg);
+
+    let mut want = large_test_msg()?;
+    want.pack()?;
+    assert_eq!(Message::from_json(&want.to_json()?)?, want);
+
+    if Message::from_json("{}").is_ok() {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_message_display() -> Result<(), Error> {
+    let name = Name::new("example.local.")?;
+    let resource = |typ: DNSType, body: ResourceData| Resource {
+        header: ResourceHeader {
+            name: name.clone(),
+            typ,
+            class: DNSCLASS_INET,
+            ttl: 120,
+            ..Default::default()
         },
-        large_test_msg()?,
-    ];
+        body: Some(body),
+    };
 
-    for mut want in wants {
-        let b = want.pack()?;
-        let mut got = Message::default();
-        got.unpack(&b)?;
-        assert_eq!(got.to_string(), want.to_string());
+    let msg = Message {
+        header: Header {
+            id: 7,
+            response: true,
+            authoritative: true,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: name.clone(),
+            typ: DNSType::A,
+            class: DNSCLASS_INET,
+            ..Default::default()
+        }],
+        answers: vec![
+            resource(DNSType::A, ResourceData::A(AResource { a: [10, 0, 0, 1] })),
+            resource(
+                DNSType::TXT,
+                ResourceData::TXT(TXTResource {
+                    txt: vec!["a=\"b\"".to_owned()],
+                }),
+            ),
+        ],
+        additionals: vec![resource(
+            DNSType(0xFF00),
+            ResourceData::Unknown(UnknownResource {
+                typ: DNSType(0xFF00),
+                data: vec![0xAB, 0xCD],
+            }),
+        )],
+        ..Default::default()
+    };
+
+    let want = ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 7
+;; flags: qr aa; QUERY: 1, ANSWER: 2, AUTHORITY: 0, ADDITIONAL: 1
+
+;; QUESTION SECTION:
+;example.local.\t\tIN\tA
+
+;; ANSWER SECTION:
+example.local.\t120\tIN\tA\t10.0.0.1
+example.local.\t120\tIN\tTXT\t\"a=\\\"b\\\"\"
+
+;; ADDITIONAL SECTION:
+example.local.\t120\tIN\tTYPE65280\t\\# 2 abcd
+";
+    assert_eq!(msg.to_string(), want);
+
+    let https = ResourceData::HTTPS(HTTPSResource {
+        svcb: SVCBResource {
+            priority: 1,
+            target: Name::new(".")?,
+            params: vec![
+                SVCBParam {
+                    key: SVC_PARAM_ALPN,
+                    value: vec![2, b'h', b'2', 2, b'h', b'3'],
+                },
+                SVCBParam {
+                    key: SVC_PARAM_PORT,
+                    value: vec![0x1F, 0x90],
+                },
+            ],
+        },
+    });
+    assert_eq!(
+        resource(DNSType::HTTPS, https).to_string(),
+        "example.local.\t120\tIN\tHTTPS\t1 . alpn=h2,h3 port=8080"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_parser_borrowed() -> Result<(), Error> {
+    let mut want = large_test_msg()?;
+    let b = want.pack()?;
+
+    let mut p = Parser::default();
+    p.start(&b)?;
+
+    let q = p.question_ref()?;
+    assert_eq!(q.to_question(), want.questions[0]);
+    assert!(q.name.eq_name(&want.questions[0].name));
+    assert!(!q.name.eq_name(&Name::new("bar.example.com.")?));
+    assert!(q.name.eq_name(&Name::new("FOO.bar.Example.com.")?));
+    let labels: Vec<&[u8]> = q.name.labels().collect();
+    assert_eq!(labels, vec![&b"foo"[..], b"bar", b"example", b"com"]);
+    assert_eq!(p.question_ref(), Err(ERR_SECTION_DONE.to_owned()));
+
+    for section in [&want.answers, &want.authorities, &want.additionals].iter() {
+        for r in section.iter() {
+            let got = match p.answer_ref() {
+                Err(err) if err == *ERR_SECTION_DONE => match p.authority_ref() {
+                    Err(err) if err == *ERR_SECTION_DONE => p.additional_ref()?,
+                    got => got?,
+                },
+                got => got?,
+            };
+            assert_eq!(got.name.to_string(), r.header.name.data);
+            assert_eq!(got.typ, r.header.typ);
+            assert_eq!(got.data.len(), r.header.length as usize);
+            assert_eq!(&got.to_resource()?, r);
+        }
     }
 
+    // The root name has no labels.
+    let (root, off) = NameRef::unpack(&[0], 0)?;
+    assert_eq!(off, 1);
+    assert_eq!(root.to_string(), ".");
+    assert!(root.eq_name(&Name::new(".")?));
+
     Ok(())
 }
 
@@ -568,6 +1701,7 @@ fn test_dns_append_pack_unpack() -> Result<(), Error> {
                 name: Name::new(".")?,
                 typ: DNSType::AAAA,
                 class: DNSCLASS_INET,
+                ..Default::default()
             }],
             answers: vec![],
             authorities: vec![],
@@ -587,6 +1721,246 @@ fn test_dns_append_pack_unpack() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_dns_append() -> Result<(), Error> {
+    let mut msg = large_test_msg()?;
+    let want = msg.pack()?;
+
+    let mut buf = Vec::with_capacity(PACK_STARTING_CAP);
+    let ptr = buf.as_ptr();
+    for _ in 0..3 {
+        buf.clear();
+        msg.append(&mut buf)?;
+        assert_eq!(buf, want);
+    }
+    assert_eq!(buf.as_ptr(), ptr, "buffer was reallocated");
+
+    buf.clear();
+    buf.extend_from_slice(&[0xFF, 0xFF]);
+    msg.append(&mut buf)?;
+    assert_eq!(&buf[2..], &want[..]);
+
+    // A message that fails to pack leaves what was in the buffer.
+    msg.questions[0].name = Name::new("example.com")?;
+    buf.truncate(2);
+    assert!(msg.append(&mut buf).is_err());
+    assert_eq!(buf, vec![0xFF, 0xFF]);
+
+    Ok(())
+}
+
+#[test]
+fn test_question_unicast_response() -> Result<(), Error> {
+    let mut msg = Message {
+        questions: vec![
+            Question {
+                name: Name::new("myhost.local.")?,
+                typ: DNSType::A,
+                class: DNSCLASS_INET,
+                unicast_response: true,
+            },
+            Question {
+                name: Name::new("myhost.local.")?,
+                typ: DNSType::AAAA,
+                class: DNSCLASS_INET,
+                unicast_response: false,
+            },
+        ],
+        ..Default::default()
+    };
+    let b = msg.pack()?;
+
+    // The QU bit is the top bit of the first question's class.
+    let class_off = HEADER_LEN + "myhost.local.".len() + 1 + 2;
+    assert_eq!(&b[class_off..class_off + 2], &[0x80, 0x01]);
+
+    assert_eq!(Message::try_from(&b[..])?, msg);
+
+    let mut p = Parser::default();
+    p.start(&b)?;
+    let q = p.question_ref()?;
+    assert!(q.unicast_response);
+    assert_eq!(q.class, DNSCLASS_INET);
+    assert_eq!(q.to_question(), msg.questions[0]);
+    assert!(!p.question_ref()?.unicast_response);
+
+    Ok(())
+}
+
+#[test]
+fn test_resource_cache_flush() -> Result<(), Error> {
+    // An Avahi announcement of myhost.local. A 192.168.1.10.
+    let b = [
+        0x00, 0x00, 0x84, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x06, b'm', b'y',
+        b'h', b'o', b's', b't', 0x05, b'l', b'o', b'c', b'a', b'l', 0x00, 0x00, 0x01, 0x80, 0x01,
+        0x00, 0x00, 0x00, 0x78, 0x00, 0x04, 0xC0, 0xA8, 0x01, 0x0A,
+    ];
+
+    let mut msg = Message::try_from(&b[..])?;
+    let h = &msg.answers[0].header;
+    assert_eq!(h.class, DNSCLASS_INET);
+    assert!(h.cache_flush);
+    assert_eq!(msg.pack()?, b);
+
+    let mut p = Parser::default();
+    p.start(&b)?;
+    p.skip_all_questions()?;
+    let r = p.answer_ref()?;
+    assert_eq!(r.class, DNSCLASS_INET);
+    assert!(r.cache_flush);
+    assert_eq!(r.to_resource()?, msg.answers[0]);
+
+    // The class of an OPT record is the UDP payload size, so its top bit
+    // isn't a cache-flush bit.
+    let mut opt = Resource {
+        header: ResourceHeader {
+            name: Name::new(".")?,
+            ..Default::default()
+        },
+        body: Some(ResourceData::OPT(OPTResource::default())),
+    };
+    opt.header.set_edns0(0x9000, 0, false)?;
+    opt.header.cache_flush = true;
+    let mut msg = Message {
+        additionals: vec![opt],
+        ..Default::default()
+    };
+    let got = Message::try_from(&msg.pack()?[..])?;
+    let h = &got.additionals[0].header;
+    assert_eq!(h.class, DNSClass(0x9000));
+    assert!(!h.cache_flush);
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_split() -> Result<(), Error> {
+    let known_answer = |i: usize| -> Result<Resource, Error> {
+        Ok(Resource {
+            header: ResourceHeader {
+                name: Name::new(&format!("device-{}._http._tcp.local.", i))?,
+                typ: DNSType::PTR,
+                class: DNSCLASS_INET,
+                ttl: 4500,
+                ..Default::default()
+            },
+            body: Some(ResourceData::PTR(PTRResource {
+                ptr: Name::new(&format!("device-{}._http._tcp.local.", i))?,
+            })),
+        })
+    };
+
+    let mut msg = Message {
+        header: Header {
+            id: 7,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: Name::new("_http._tcp.local.")?,
+            typ: DNSType::PTR,
+            class: DNSCLASS_INET,
+            ..Default::default()
+        }],
+        answers: (0..100).map(known_answer).collect::<Result<_, _>>()?,
+        ..Default::default()
+    };
+
+    // A message that fits is packed as is.
+    let whole = msg.pack()?;
+    assert_eq!(msg.pack_split(whole.len())?, vec![whole.clone()]);
+
+    let packets = msg.pack_split(512)?;
+    assert!(packets.len() > 1, "got {} packets", packets.len());
+    let mut answers = vec![];
+    for (i, b) in packets.iter().enumerate() {
+        assert!(b.len() <= 512);
+        let got = Message::try_from(&b[..])?;
+        assert_eq!(got.header.id, 7);
+        assert_eq!(got.header.truncated, i < packets.len() - 1);
+        assert_eq!(got.questions.len(), if i == 0 { 1 } else { 0 });
+        answers.extend(got.answers);
+    }
+    assert_eq!(answers, msg.answers);
+
+    // Responses are split without the TC bit.
+    msg.header.response = true;
+    for b in msg.pack_split(512)? {
+        assert!(!Message::try_from(&b[..])?.header.truncated);
+    }
+
+    if let Err(err) = msg.pack_split(40) {
+        assert_eq!(err, *ERR_MESSAGE_TOO_LARGE);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    // Several hundred records, with authorities kept in the first packet
+    // between its answers and additionals.
+    msg.header.response = false;
+    msg.answers = (0..600).map(known_answer).collect::<Result<_, _>>()?;
+    msg.authorities = (600..603).map(known_answer).collect::<Result<_, _>>()?;
+    msg.additionals = (603..900).map(known_answer).collect::<Result<_, _>>()?;
+    let packets = msg.pack_split(1232)?;
+    let (mut answers, mut authorities, mut additionals) = (vec![], vec![], vec![]);
+    for (i, b) in packets.iter().enumerate() {
+        assert!(b.len() <= 1232);
+        let got = Message::try_from(&b[..])?;
+        assert_eq!(got.header.truncated, i < packets.len() - 1);
+        if i > 0 {
+            assert!(got.questions.is_empty() && got.authorities.is_empty());
+        }
+        answers.extend(got.answers);
+        authorities.extend(got.authorities);
+        additionals.extend(got.additionals);
+    }
+    assert_eq!(answers, msg.answers);
+    assert_eq!(authorities, msg.authorities);
+    assert_eq!(additionals, msg.additionals);
+
+    // The packets are filled up, so there are hardly more of them than the
+    // size of the whole message needs.
+    let whole = msg.pack()?.len();
+    assert!(
+        packets.len() <= whole / 1232 + 2,
+        "{} packets",
+        packets.len()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_unpack_tcp() -> Result<(), Error> {
+    let mut msg = large_test_msg()?;
+    let udp = msg.pack()?;
+    let tcp = msg.pack_tcp()?;
+    assert_eq!(&tcp[..2], &(udp.len() as u16).to_be_bytes());
+    assert_eq!(&tcp[2..], &udp[..]);
+
+    // Two messages back to back on a stream.
+    let mut small = small_test_msg()?;
+    let mut stream = tcp.clone();
+    stream.extend(small.pack_tcp()?);
+
+    let mut got = Message::default();
+    let n = got.unpack_tcp(&stream)?;
+    assert_eq!(n, tcp.len());
+    assert_eq!(got, msg);
+    let m = got.unpack_tcp(&stream[n..])?;
+    assert_eq!(n + m, stream.len());
+    assert_eq!(got, small);
+
+    for partial in [&tcp[..1], &tcp[..tcp.len() - 1]].iter() {
+        if let Err(err) = got.unpack_tcp(partial) {
+            assert_eq!(err, *ERR_BASE_LEN);
+        } else {
+            panic!("expected error, but got ok");
+        }
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_skip_all() -> Result<(), Error> {
     let mut msg = large_test_msg()?;
@@ -626,30 +2000,59 @@ fn test_skip_each() -> Result<(), Error> {
     if let Err(err) = p.skip_question() {
         assert_eq!(err, ERR_SECTION_DONE.to_owned());
     } else {
-        assert!(false, "expected error, but got ok");
+        panic!("expected error, but got ok");
     }
 
     p.skip_answer()?;
     if let Err(err) = p.skip_answer() {
         assert_eq!(err, ERR_SECTION_DONE.to_owned());
     } else {
-        assert!(false, "expected error, but got ok");
+        panic!("expected error, but got ok");
     }
 
     p.skip_authority()?;
     if let Err(err) = p.skip_authority() {
         assert_eq!(err, ERR_SECTION_DONE.to_owned());
     } else {
-        assert!(false, "expected error, but got ok");
+        panic!("expected error, but got ok");
     }
 
     p.skip_additional()?;
     if let Err(err) = p.skip_additional() {
         assert_eq!(err, ERR_SECTION_DONE.to_owned());
     } else {
-        assert!(false, "expected error, but got ok");
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_skip_without_decoding() -> Result<(), Error> {
+    // One answer whose name isn't UTF-8 and whose A body is too short,
+    // followed by a valid additional record.
+    let b = [
+        0x00, 0x00, 0x84, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x02, 0xC3, 0x28,
+        0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x02, 0x0A, 0x00, 0x04, b'h',
+        b'o', b's', b't', 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04, 0x0A,
+        0x00, 0x00, 0x01,
+    ];
+
+    if Message::try_from(&b[..]).is_ok() {
+        panic!("expected error, but got ok");
     }
 
+    // Skipping only walks the record framing, so it gets past the bad answer.
+    let mut p = Parser::default();
+    p.start(&b)?;
+    p.skip_all_questions()?;
+    p.skip_answer()?;
+    p.skip_all_answers()?;
+    p.skip_all_authorities()?;
+    let r = p.additional()?;
+    assert_eq!(r.header.name.data, "host.");
+    assert_eq!(r.as_a().map(|a| a.a), Some([10, 0, 0, 1]));
+
     Ok(())
 }
 
@@ -660,7 +2063,7 @@ fn test_skip_after_read() -> Result<(), Error> {
     let mut p = Parser::default();
     p.start(&buf)?;
 
-    let tests: Vec<(&str, Box<dyn Fn(&mut Parser<'_>) -> Result<(), Error>>)> = vec![
+    let tests: Vec<(&str, ParserFn)> = vec![
         (
             "Question",
             Box::new(|p: &mut Parser<'_>| -> Result<(), Error> {
@@ -716,7 +2119,7 @@ fn test_skip_after_read() -> Result<(), Error> {
         if let Err(err) = result {
             assert_eq!(err, ERR_SECTION_DONE.to_owned());
         } else {
-            assert!(false, "expected error, but got ok");
+            panic!("expected error, but got ok");
         }
     }
 
@@ -725,7 +2128,7 @@ fn test_skip_after_read() -> Result<(), Error> {
 
 #[test]
 fn test_skip_not_started() -> Result<(), Error> {
-    let tests: Vec<(&str, Box<dyn Fn(&mut Parser<'_>) -> Result<(), Error>>)> = vec![
+    let tests: Vec<(&str, ParserFn)> = vec![
         (
             "SkipAllQuestions",
             Box::new(|p: &mut Parser<'_>| -> Result<(), Error> { p.skip_all_questions() }),
@@ -749,7 +2152,7 @@ fn test_skip_not_started() -> Result<(), Error> {
         if let Err(err) = test_fn(&mut p) {
             assert_eq!(err, ERR_NOT_STARTED.to_owned());
         } else {
-            assert!(false, "{} expected error, but got ok", name);
+            panic!("{} expected error, but got ok", name);
         }
     }
 
@@ -820,7 +2223,7 @@ fn test_too_many_records() -> Result<(), Error> {
                 name, got, want
             )
         } else {
-            assert!(false, "expected error, but got ok");
+            panic!("expected error, but got ok");
         }
     }
 
@@ -841,7 +2244,7 @@ fn test_very_long_txt() -> Result<(), Error> {
             class: DNSCLASS_INET,
             ..Default::default()
         },
-        body: Some(Box::new(TXTResource {
+        body: Some(ResourceData::TXT(TXTResource {
             txt: vec![
                 "".to_owned(),
                 "".to_owned(),
@@ -854,7 +2257,8 @@ fn test_very_long_txt() -> Result<(), Error> {
         })),
     };
 
-    let buf = want.pack(vec![], &mut Some(HashMap::new()), 0)?;
+    let mut buf = vec![];
+    want.pack(&mut buf, &mut Some(HashMap::new()), 0)?;
     let mut got = Resource::default();
     let off = got.header.unpack(&buf, 0, 0)?;
     let (body, n) = unpack_resource_body(got.header.typ, &buf, off, got.header.length as usize)?;
@@ -871,6 +2275,38 @@ fn test_very_long_txt() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_txt_pairs() -> Result<(), Error> {
+    let want = TXTResource::from_pairs(&[
+        ("txtvers", Some("1")),
+        ("path", Some("/index.html")),
+        ("empty", Some("")),
+        ("flag", None),
+    ]);
+    assert_eq!(
+        want.txt,
+        vec!["txtvers=1", "path=/index.html", "empty=", "flag"]
+    );
+
+    let mut b = vec![];
+    want.pack(&mut b, &mut None, 0)?;
+    let mut got = TXTResource::default();
+    got.unpack(&b, 0, b.len())?;
+
+    assert_eq!(got.get("TXTVERS"), Some(Some("1")));
+    assert_eq!(got.get("path"), Some(Some("/index.html")));
+    assert_eq!(got.get("empty"), Some(Some("")));
+    assert_eq!(got.get("flag"), Some(None));
+    assert_eq!(got.get("missing"), None);
+
+    let got = TXTResource {
+        txt: vec!["=ignored".to_owned(), "k=a=b".to_owned()],
+    };
+    assert_eq!(got.pairs(), vec![("k", Some("a=b"))]);
+
+    Ok(())
+}
+
 #[test]
 fn test_too_long_txt() -> Result<(), Error> {
     let mut str256 = String::new();
@@ -878,10 +2314,10 @@ fn test_too_long_txt() -> Result<(), Error> {
         str256.push('.');
     }
     let rb = TXTResource { txt: vec![str256] };
-    if let Err(err) = rb.pack(vec![], &mut Some(HashMap::new()), 0) {
+    if let Err(err) = rb.pack(&mut vec![], &mut Some(HashMap::new()), 0) {
         assert_eq!(err, ERR_STRING_TOO_LONG.to_owned());
     } else {
-        assert!(false, "expected error, but got ok");
+        panic!("expected error, but got ok");
     }
 
     Ok(())
@@ -889,26 +2325,26 @@ fn test_too_long_txt() -> Result<(), Error> {
 
 #[test]
 fn test_start_error() -> Result<(), Error> {
-    let tests: Vec<(&str, Box<dyn Fn(&mut Builder) -> Result<(), Error>>)> = vec![
+    let tests: Vec<(&str, BuilderFn)> = vec![
         (
             "Questions",
-            Box::new(|b: &mut Builder| -> Result<(), Error> { b.start_questions() }),
+            Box::new(|b: &mut Builder| -> Result<(), Error> { b.start_questions().map(|_| ()) }),
         ),
         (
             "Answers",
-            Box::new(|b: &mut Builder| -> Result<(), Error> { b.start_answers() }),
+            Box::new(|b: &mut Builder| -> Result<(), Error> { b.start_answers().map(|_| ()) }),
         ),
         (
             "Authorities",
-            Box::new(|b: &mut Builder| -> Result<(), Error> { b.start_authorities() }),
+            Box::new(|b: &mut Builder| -> Result<(), Error> { b.start_authorities().map(|_| ()) }),
         ),
         (
             "Additionals",
-            Box::new(|b: &mut Builder| -> Result<(), Error> { b.start_additionals() }),
+            Box::new(|b: &mut Builder| -> Result<(), Error> { b.start_additionals().map(|_| ()) }),
         ),
     ];
 
-    let envs: Vec<(&str, Box<dyn Fn() -> Builder>, Error)> = vec![
+    let envs: Vec<(&str, BuilderEnv, Error)> = vec![
         (
             "sectionNotStarted",
             Box::new(|| -> Builder {
@@ -941,11 +2377,7 @@ fn test_start_error() -> Result<(), Error> {
                     env_name, test_name, got_err, *env_err
                 );
             } else {
-                assert!(
-                    false,
-                    "{}.{}expected error, but got ok",
-                    env_name, test_name
-                );
+                panic!("{}.{}expected error, but got ok", env_name, test_name);
             }
         }
     }
@@ -955,14 +2387,15 @@ fn test_start_error() -> Result<(), Error> {
 
 #[test]
 fn test_builder_resource_error() -> Result<(), Error> {
-    let tests: Vec<(&str, Box<dyn Fn(&mut Builder) -> Result<(), Error>>)> = vec![
+    let tests: Vec<(&str, BuilderFn)> = vec![
         (
             "CNAMEResource",
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(CNAMEResource::default())),
+                    body: Some(ResourceData::CNAME(CNAMEResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -970,8 +2403,9 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(MXResource::default())),
+                    body: Some(ResourceData::MX(MXResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -979,8 +2413,9 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(NSResource::default())),
+                    body: Some(ResourceData::NS(NSResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -988,8 +2423,9 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(PTRResource::default())),
+                    body: Some(ResourceData::PTR(PTRResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -997,8 +2433,9 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(SOAResource::default())),
+                    body: Some(ResourceData::SOA(SOAResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -1006,8 +2443,9 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(TXTResource::default())),
+                    body: Some(ResourceData::TXT(TXTResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -1015,8 +2453,9 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(SRVResource::default())),
+                    body: Some(ResourceData::SRV(SRVResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -1024,8 +2463,9 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(AResource::default())),
+                    body: Some(ResourceData::A(AResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -1033,8 +2473,9 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(AAAAResource::default())),
+                    body: Some(ResourceData::AAAA(AAAAResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
         (
@@ -1042,13 +2483,14 @@ fn test_builder_resource_error() -> Result<(), Error> {
             Box::new(|b: &mut Builder| -> Result<(), Error> {
                 b.add_resource(&mut Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(OPTResource::default())),
+                    body: Some(ResourceData::OPT(OPTResource::default())),
                 })
+                .map(|_| ())
             }),
         ),
     ];
 
-    let envs: Vec<(&str, Box<dyn Fn() -> Builder>, Error)> = vec![
+    let envs: Vec<(&str, BuilderEnv, Error)> = vec![
         (
             "sectionNotStarted",
             Box::new(|| -> Builder {
@@ -1101,11 +2543,7 @@ fn test_builder_resource_error() -> Result<(), Error> {
                     env_name, test_name, got_err, *env_err
                 );
             } else {
-                assert!(
-                    false,
-                    "{}.{}expected error, but got ok",
-                    env_name, test_name
-                );
+                panic!("{}.{}expected error, but got ok", env_name, test_name);
             }
         }
     }
@@ -1120,7 +2558,7 @@ fn test_finish_error() -> Result<(), Error> {
     if let Err(got) = b.finish() {
         assert_eq!(got, want, "got Builder.Finish() = {}, want = {}", got, want);
     } else {
-        assert!(false, "expected error, but got ok");
+        panic!("expected error, but got ok");
     }
 
     Ok(())
@@ -1166,6 +2604,52 @@ fn test_builder() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_builder_fluent() -> Result<(), Error> {
+    let mut msg = large_test_msg()?;
+    let want = msg.pack()?;
+
+    let mut b = Builder::new(&msg.header);
+    b.enable_compression().start_questions()?;
+    for q in &msg.questions {
+        b.add_question(q)?;
+    }
+    for r in &mut msg.answers {
+        b.add_answer(r)?;
+    }
+    for r in &mut msg.authorities {
+        b.add_authority(r)?;
+    }
+    for r in &mut msg.additionals {
+        b.add_additional(r)?;
+    }
+    assert_eq!(b.finish()?, want);
+
+    let got = Builder::new(&msg.header)
+        .enable_compression()
+        .start_questions()?
+        .add_question(&msg.questions[0])?
+        .add_answer(&mut msg.answers[0])?
+        .add_additional(&mut msg.additionals[0])?
+        .finish()?;
+    let got = Message::try_from(&got[..])?;
+    assert_eq!(got.questions, msg.questions[..1]);
+    assert_eq!(got.answers, msg.answers[..1]);
+    assert!(got.authorities.is_empty());
+    assert_eq!(got.additionals, msg.additionals[..1]);
+
+    let mut b = Builder::new(&msg.header);
+    b.start_questions()?
+        .add_additional(&mut msg.additionals[0])?;
+    if let Err(err) = b.add_answer(&mut msg.answers[0]) {
+        assert_eq!(err, *ERR_SECTION_DONE);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_resource_pack() -> Result<(), Error> {
     let tests = vec![
@@ -1175,6 +2659,7 @@ fn test_resource_pack() -> Result<(), Error> {
                     name: Name::new(".")?,
                     typ: DNSType::AAAA,
                     class: DNSCLASS_INET,
+                    ..Default::default()
                 }],
                 answers: vec![Resource {
                     header: ResourceHeader::default(),
@@ -1190,10 +2675,11 @@ fn test_resource_pack() -> Result<(), Error> {
                     name: Name::new(".")?,
                     typ: DNSType::AAAA,
                     class: DNSCLASS_INET,
+                    ..Default::default()
                 }],
                 authorities: vec![Resource {
                     header: ResourceHeader::default(),
-                    body: Some(Box::new(NSResource::default())),
+                    body: Some(ResourceData::NS(NSResource::default())),
                 }],
                 ..Default::default()
             },
@@ -1205,6 +2691,7 @@ fn test_resource_pack() -> Result<(), Error> {
                     name: Name::new(".")?,
                     typ: DNSType::A,
                     class: DNSCLASS_INET,
+                    ..Default::default()
                 }],
                 additionals: vec![Resource {
                     header: ResourceHeader::default(),
@@ -1220,7 +2707,7 @@ fn test_resource_pack() -> Result<(), Error> {
         if let Err(err) = m.pack() {
             assert_eq!(err, want_err);
         } else {
-            assert!(false, "expected error, but got ok");
+            panic!("expected error, but got ok");
         }
     }
 
@@ -1236,11 +2723,13 @@ fn test_resource_pack_length() -> Result<(), Error> {
             class: DNSCLASS_INET,
             ..Default::default()
         },
-        body: Some(Box::new(AResource { a: [127, 0, 0, 2] })),
+        body: Some(ResourceData::A(AResource { a: [127, 0, 0, 2] })),
     };
 
-    let (hb, _) = r.header.pack(vec![], &mut None, 0)?;
-    let buf = r.pack(vec![], &mut None, 0)?;
+    let mut hb = vec![];
+    r.header.pack(&mut hb, &mut None, 0)?;
+    let mut buf = vec![];
+    r.pack(&mut buf, &mut None, 0)?;
 
     let mut hdr = ResourceHeader::default();
     hdr.unpack(&buf, 0, 0)?;
@@ -1268,6 +2757,7 @@ fn test_option_pack_unpack() -> Result<(), Error> {
                     name: Name::new(".")?,
                     typ: DNSType::A,
                     class: DNSCLASS_INET,
+                    ..Default::default()
                 }],
                 additionals: vec![Resource {
                     header: must_edns0_resource_header(
@@ -1275,7 +2765,7 @@ fn test_option_pack_unpack() -> Result<(), Error> {
                         0xfe0 | RCode::FormatError as u32,
                         true,
                     )?,
-                    body: Some(Box::new(OPTResource::default())),
+                    body: Some(ResourceData::OPT(OPTResource::default())),
                 }],
                 ..Default::default()
             },
@@ -1297,6 +2787,7 @@ fn test_option_pack_unpack() -> Result<(), Error> {
                     name: Name::new(".")?,
                     typ: DNSType::AAAA,
                     class: DNSCLASS_INET,
+                    ..Default::default()
                 }],
                 additionals: vec![Resource {
                     header: must_edns0_resource_header(
@@ -1304,7 +2795,7 @@ fn test_option_pack_unpack() -> Result<(), Error> {
                         0xff0 | RCode::ServerFailure as u32,
                         false,
                     )?,
-                    body: Some(Box::new(OPTResource {
+                    body: Some(ResourceData::OPT(OPTResource {
                         options: vec![
                             DNSOption {
                                 code: 12, // see RFC 7828
@@ -1341,6 +2832,7 @@ fn test_option_pack_unpack() -> Result<(), Error> {
                     name: Name::new(".")?,
                     typ: DNSType::AAAA,
                     class: DNSCLASS_INET,
+                    ..Default::default()
                 }],
                 additionals: vec![
                     Resource {
@@ -1349,7 +2841,7 @@ fn test_option_pack_unpack() -> Result<(), Error> {
                             0xff0 | RCode::NameError as u32,
                             false,
                         )?,
-                        body: Some(Box::new(OPTResource {
+                        body: Some(ResourceData::OPT(OPTResource {
                             options: vec![DNSOption {
                                 code: 11, // see RFC 7830
                                 data: vec![0x12, 0x34],
@@ -1362,7 +2854,7 @@ fn test_option_pack_unpack() -> Result<(), Error> {
                             0xff0 | RCode::NameError as u32,
                             false,
                         )?,
-                        body: Some(Box::new(OPTResource {
+                        body: Some(ResourceData::OPT(OPTResource {
                             options: vec![DNSOption {
                                 code: 12, // see RFC 7828
                                 data: vec![0x00, 0x00],
@@ -1390,3 +2882,45 @@ fn test_option_pack_unpack() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_option_unknown() -> Result<(), Error> {
+    let mut opt = OPTResource {
+        options: vec![
+            DNSOption {
+                code: EDNS0_OPTION_OWNER,
+                data: vec![0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06],
+            },
+            DNSOption {
+                code: 65001, // local/experimental use
+                data: vec![0xde, 0xad],
+            },
+        ],
+    };
+
+    let mut b = vec![];
+    opt.pack(&mut b, &mut None, 0)?;
+    let mut got = OPTResource::default();
+    got.unpack(&b, 0, b.len())?;
+    assert_eq!(got, opt);
+
+    assert!(got.option(EDNS0_OPTION_OWNER).is_some());
+    assert!(got.option(EDNS0_OPTION_COOKIE).is_none());
+    assert_eq!(got.unknown_options(), vec![&opt.options[1]]);
+
+    opt.skip_unknown_options();
+    assert_eq!(opt.options.len(), 1);
+    assert_eq!(opt.options[0].code, EDNS0_OPTION_OWNER);
+
+    // An option claiming more data than the RDATA holds is an error even if
+    // the message itself is long enough.
+    let b = [0x00, 0x04, 0x00, 0x04, 0x01, 0x02, 0x03, 0x04];
+    let mut got = OPTResource::default();
+    if let Err(err) = got.unpack(&b, 0, 6) {
+        assert_eq!(err, *ERR_CALC_LEN);
+    } else {
+        panic!("expected error, but got ok");
+    }
+
+    Ok(())
+}
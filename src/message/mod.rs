@@ -9,6 +9,7 @@ pub mod parser;
 pub mod question;
 pub mod resource;
 
+use builder::*;
 use header::*;
 use packer::*;
 use parser::*;
@@ -17,6 +18,7 @@ use resource::*;
 
 use crate::errors::*;
 
+use std::convert::TryFrom;
 use std::fmt;
 
 use std::collections::HashMap;
@@ -25,59 +27,55 @@ use util::Error;
 // Message formats
 
 // A Type is a type of DNS request and response.
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum DNSType {
+//
+// Types this package doesn't know about keep their wire value, so records of
+// those types can be passed through unchanged.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct DNSType(pub u16);
+
+impl DNSType {
     // ResourceHeader.Type and question.Type
-    A = 1,
-    NS = 2,
-    CNAME = 5,
-    SOA = 6,
-    PTR = 12,
-    MX = 15,
-    TXT = 16,
-    AAAA = 28,
-    SRV = 33,
-    OPT = 41,
+    pub const A: DNSType = DNSType(1);
+    pub const NS: DNSType = DNSType(2);
+    pub const CNAME: DNSType = DNSType(5);
+    pub const SOA: DNSType = DNSType(6);
+    pub const PTR: DNSType = DNSType(12);
+    pub const MX: DNSType = DNSType(15);
+    pub const TXT: DNSType = DNSType(16);
+    pub const AAAA: DNSType = DNSType(28);
+    pub const SRV: DNSType = DNSType(33);
+    pub const OPT: DNSType = DNSType(41);
+    pub const NSEC: DNSType = DNSType(47);
+    pub const SVCB: DNSType = DNSType(64);
+    pub const HTTPS: DNSType = DNSType(65);
 
     // question.Type
-    WKS = 11,
-    HINFO = 13,
-    MINFO = 14,
-    AXFR = 252,
-    ALL = 255,
-
-    Unsupported = 0,
+    pub const WKS: DNSType = DNSType(11);
+    pub const HINFO: DNSType = DNSType(13);
+    pub const MINFO: DNSType = DNSType(14);
+    pub const AXFR: DNSType = DNSType(252);
+    pub const ALL: DNSType = DNSType(255);
+
+    pub const UNSUPPORTED: DNSType = DNSType(0);
+
+    // Unsupported is the name UNSUPPORTED had when DNSType was an enum.
+    #[deprecated(since = "0.3.0", note = "use DNSType::UNSUPPORTED")]
+    #[allow(non_upper_case_globals)]
+    pub const Unsupported: DNSType = DNSType::UNSUPPORTED;
 }
 
-impl Default for DNSType {
-    fn default() -> Self {
-        DNSType::Unsupported
+impl From<u16> for DNSType {
+    fn from(v: u16) -> Self {
+        DNSType(v)
     }
 }
 
-impl From<u16> for DNSType {
-    fn from(v: u16) -> Self {
-        match v {
-            1 => DNSType::A,
-            2 => DNSType::NS,
-            5 => DNSType::CNAME,
-            6 => DNSType::SOA,
-            12 => DNSType::PTR,
-            15 => DNSType::MX,
-            16 => DNSType::TXT,
-            28 => DNSType::AAAA,
-            33 => DNSType::SRV,
-            41 => DNSType::OPT,
-
-            // question.Type
-            11 => DNSType::WKS,
-            13 => DNSType::HINFO,
-            14 => DNSType::MINFO,
-            252 => DNSType::AXFR,
-            255 => DNSType::ALL,
-
-            _ => DNSType::Unsupported,
-        }
+impl From<DNSType> for u16 {
+    fn from(t: DNSType) -> Self {
+        t.0
     }
 }
 
@@ -94,21 +92,32 @@ impl fmt::Display for DNSType {
             DNSType::AAAA => "AAAA",
             DNSType::SRV => "SRV",
             DNSType::OPT => "OPT",
+            DNSType::NSEC => "NSEC",
+            DNSType::SVCB => "SVCB",
+            DNSType::HTTPS => "HTTPS",
             DNSType::WKS => "WKS",
             DNSType::HINFO => "HINFO",
             DNSType::MINFO => "MINFO",
             DNSType::AXFR => "AXFR",
             DNSType::ALL => "ALL",
-            _ => "Unsupported",
+            DNSType::UNSUPPORTED => "Unsupported",
+            // RFC 3597 Section 5 presentation of unknown types.
+            _ => return write!(f, "TYPE{}", self.0),
         };
         write!(f, "{}", s)
     }
 }
 
+impl fmt::Debug for DNSType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
 impl DNSType {
     // pack_type appends the wire format of field to msg.
-    pub(crate) fn pack(&self, msg: Vec<u8>) -> Vec<u8> {
-        pack_uint16(msg, *self as u16)
+    pub(crate) fn pack(&self, msg: &mut Vec<u8>) {
+        pack_uint16(msg, self.0);
     }
 
     pub(crate) fn unpack(&mut self, msg: &[u8], off: usize) -> Result<usize, Error> {
@@ -123,7 +132,10 @@ impl DNSType {
 }
 
 // A Class is a type of network.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct DNSClass(pub u16);
 
 // ResourceHeader.Class and question.Class
@@ -134,12 +146,6 @@ pub const DNSCLASS_HESIOD: DNSClass = DNSClass(4);
 // question.Class
 pub const DNSCLASS_ANY: DNSClass = DNSClass(255);
 
-impl Default for DNSClass {
-    fn default() -> Self {
-        DNSClass(0)
-    }
-}
-
 impl fmt::Display for DNSClass {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let other = format!("{}", self.0);
@@ -156,9 +162,21 @@ impl fmt::Display for DNSClass {
 }
 
 impl DNSClass {
+    // presentation returns the class mnemonic used in zone files.
+    pub(crate) fn presentation(&self) -> String {
+        match *self {
+            DNSCLASS_INET => "IN".to_owned(),
+            DNSCLASS_CSNET => "CS".to_owned(),
+            DNSCLASS_CHAOS => "CH".to_owned(),
+            DNSCLASS_HESIOD => "HS".to_owned(),
+            DNSCLASS_ANY => "ANY".to_owned(),
+            DNSClass(c) => format!("CLASS{}", c),
+        }
+    }
+
     // pack_class appends the wire format of field to msg.
-    pub(crate) fn pack(&self, msg: Vec<u8>) -> Vec<u8> {
-        pack_uint16(msg, self.0)
+    pub(crate) fn pack(&self, msg: &mut Vec<u8>) {
+        pack_uint16(msg, self.0);
     }
 
     pub(crate) fn unpack(&mut self, msg: &[u8], off: usize) -> Result<usize, Error> {
@@ -176,9 +194,12 @@ impl DNSClass {
 pub type OpCode = u16;
 
 // An RCode is a DNS response status code.
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub enum RCode {
     // Message.Rcode
+    #[default]
     Success = 0,
     FormatError = 1,
     ServerFailure = 2,
@@ -188,12 +209,6 @@ pub enum RCode {
     Unsupported,
 }
 
-impl Default for RCode {
-    fn default() -> Self {
-        RCode::Success
-    }
-}
-
 impl From<u8> for RCode {
     fn from(v: u8) -> Self {
         match v {
@@ -208,6 +223,21 @@ impl From<u8> for RCode {
     }
 }
 
+impl RCode {
+    // presentation returns the status mnemonic printed by dig.
+    pub(crate) fn presentation(&self) -> &'static str {
+        match *self {
+            RCode::Success => "NOERROR",
+            RCode::FormatError => "FORMERR",
+            RCode::ServerFailure => "SERVFAIL",
+            RCode::NameError => "NXDOMAIN",
+            RCode::NotImplemented => "NOTIMP",
+            RCode::Refused => "REFUSED",
+            RCode::Unsupported => "RESERVED",
+        }
+    }
+}
+
 impl fmt::Display for RCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match *self {
@@ -249,8 +279,18 @@ const HEADER_BIT_TC: u16 = 1 << 9; // truncated
 const HEADER_BIT_RD: u16 = 1 << 8; // recursion desired
 const HEADER_BIT_RA: u16 = 1 << 7; // recursion available
 
+// CLASS_BIT_QU is the top bit of a question's class, which mDNS uses to ask
+// for a unicast response (RFC 6762 Section 5.4).
+const CLASS_BIT_QU: u16 = 1 << 15;
+
+// CLASS_BIT_CACHE_FLUSH is the top bit of a record's class, which mDNS uses
+// to mark a record as replacing all cached ones (RFC 6762 Section 10.2).
+const CLASS_BIT_CACHE_FLUSH: u16 = 1 << 15;
+
 // Message is a representation of a DNS message.
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct Message {
     pub header: Header,
     pub questions: Vec<Question>,
@@ -260,27 +300,77 @@ pub struct Message {
 }
 
 impl fmt::Display for Message {
+    // fmt writes the Message in the layout used by dig, with one zone file
+    // line per Resource.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut s = "dnsmessage.Message{Header: ".to_owned();
-        s += self.header.to_string().as_str();
-
-        s += ", Questions: ";
-        let v: Vec<String> = self.questions.iter().map(|q| q.to_string()).collect();
-        s += &v.join(", ");
+        let h = &self.header;
+        let op_code = match h.op_code {
+            0 => "QUERY".to_owned(),
+            op => op.to_string(),
+        };
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            op_code,
+            h.rcode.presentation(),
+            h.id
+        )?;
+
+        let mut flags = vec![];
+        for (set, flag) in &[
+            (h.response, "qr"),
+            (h.authoritative, "aa"),
+            (h.truncated, "tc"),
+            (h.recursion_desired, "rd"),
+            (h.recursion_available, "ra"),
+        ] {
+            if *set {
+                flags.push(*flag);
+            }
+        }
+        writeln!(
+            f,
+            ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            flags.join(" "),
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len()
+        )?;
+
+        if !self.questions.is_empty() {
+            write!(f, "\n;; QUESTION SECTION:\n")?;
+            for q in &self.questions {
+                writeln!(f, "{}", q)?;
+            }
+        }
 
-        s += ", Answers: ";
-        let v: Vec<String> = self.answers.iter().map(|q| q.to_string()).collect();
-        s += &v.join(", ");
+        for (section, resources) in &[
+            ("ANSWER", &self.answers),
+            ("AUTHORITY", &self.authorities),
+            ("ADDITIONAL", &self.additionals),
+        ] {
+            if resources.is_empty() {
+                continue;
+            }
+            write!(f, "\n;; {} SECTION:\n", section)?;
+            for r in resources.iter() {
+                writeln!(f, "{}", r)?;
+            }
+        }
 
-        s += ", Authorities: ";
-        let v: Vec<String> = self.authorities.iter().map(|q| q.to_string()).collect();
-        s += &v.join(", ");
+        Ok(())
+    }
+}
 
-        s += ", Additionals: ";
-        let v: Vec<String> = self.additionals.iter().map(|q| q.to_string()).collect();
-        s += &v.join(", ");
+impl TryFrom<&[u8]> for Message {
+    type Error = Error;
 
-        write!(f, "{}", s)
+    // try_from parses a full Message in one shot; see Message::unpack.
+    fn try_from(msg: &[u8]) -> Result<Self, Self::Error> {
+        let mut m = Message::default();
+        m.unpack(msg)?;
+        Ok(m)
     }
 }
 
@@ -301,9 +391,177 @@ impl Message {
         self.append_pack(vec![])
     }
 
+    // pack_tcp packs the Message for DNS over TCP, prefixed with its
+    // two-byte length (RFC 1035 Section 4.2.2).
+    pub fn pack_tcp(&mut self) -> Result<Vec<u8>, Error> {
+        let mut msg = self.append_pack(vec![0, 0])?;
+        let len = msg.len() - 2;
+        if len > u16::MAX as usize {
+            return Err(ERR_TCP_MSG_TOO_LONG.to_owned());
+        }
+        msg[..2].copy_from_slice(&(len as u16).to_be_bytes());
+        Ok(msg)
+    }
+
+    // unpack_tcp parses a length-prefixed Message from the start of buf, as
+    // read from a DNS over TCP stream, and returns the number of bytes used.
+    // ERR_BASE_LEN means buf doesn't hold the whole message yet.
+    pub fn unpack_tcp(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.len() < 2 {
+            return Err(ERR_BASE_LEN.to_owned());
+        }
+        let end = 2 + u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        if buf.len() < end {
+            return Err(ERR_BASE_LEN.to_owned());
+        }
+        self.unpack(&buf[2..end])?;
+        Ok(end)
+    }
+
+    // round_trip packs the Message, unpacks the result and packs that again,
+    // returning the packed bytes. It panics if the packed Message can't be
+    // unpacked or doesn't pack the same way twice, so it can serve as the
+    // body of a fuzz target; an error packing the original is returned.
+    #[cfg(feature = "fuzz")]
+    pub fn round_trip(&mut self) -> Result<Vec<u8>, Error> {
+        let b = self.pack()?;
+        let mut m = Message::default();
+        if let Err(err) = m.unpack(&b) {
+            panic!("unpacking {:?} packed as {:?}: {}", self, b, err);
+        }
+        match m.pack() {
+            Ok(b2) if b2 == b => Ok(b),
+            Ok(b2) => panic!("{:?} packed as {:?}, then as {:?}", self, b, b2),
+            Err(err) => panic!("repacking {:?} unpacked from {:?}: {}", m, b, err),
+        }
+    }
+
+    // to_json encodes the Message as JSON. The schema follows the field names
+    // of the message types: names are strings, types and classes numbers,
+    // and each body is an object keyed by its ResourceData variant.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(|e| Error::new(e.to_string()))
+    }
+
+    // from_json decodes a Message encoded by to_json.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<Message, Error> {
+        serde_json::from_str(json).map_err(|e| Error::new(e.to_string()))
+    }
+
+    // pack_split packs the Message into as many packets of at most max_size
+    // bytes as needed, so nothing relies on IP fragmentation. The first packet
+    // carries the questions and authorities; answers and then additionals are
+    // spread over the packets in order. When a query is split, every packet
+    // but the last has the TC bit set to tell responders that more known
+    // answers follow (RFC 6762 Section 7.2).
+    pub fn pack_split(&mut self, max_size: usize) -> Result<Vec<Vec<u8>>, Error> {
+        let b = self.pack()?;
+        if b.len() <= max_size {
+            return Ok(vec![b]);
+        }
+
+        // The authorities are packed after the answers, so room is kept for
+        // them in the first packet. Compressed against the questions alone,
+        // they take at least as much as they will after the answers.
+        let mut first = Message {
+            header: self.header,
+            questions: self.questions.clone(),
+            authorities: self.authorities.clone(),
+            ..Default::default()
+        };
+        let first_len = first.pack()?.len();
+        if first_len > max_size {
+            return Err(ERR_MESSAGE_TOO_LARGE.to_owned());
+        }
+        first.authorities.clear();
+        let mut reserved = first_len - first.pack()?.len();
+
+        // Each packet is built up a record at a time, a record that doesn't
+        // fit being taken back out for the next one, so every record is
+        // packed just the once.
+        let header = self.header;
+        let new_packet = || {
+            let mut b = Builder::new(&header);
+            b.enable_compression();
+            b
+        };
+        let mut packets = vec![];
+        let mut b = new_packet();
+        b.start_questions()?;
+        for q in &self.questions {
+            b.add_question(q)?;
+        }
+        let mut authorities = Some(&mut self.authorities);
+        let records = self
+            .answers
+            .iter_mut()
+            .map(|r| (Section::Answers, r))
+            .chain(
+                self.additionals
+                    .iter_mut()
+                    .map(|r| (Section::Additionals, r)),
+            );
+        for (sec, r) in records {
+            if sec == Section::Additionals {
+                if let Some(authorities) = authorities.take() {
+                    add_authorities(&mut b, authorities, max_size)?;
+                    reserved = 0;
+                }
+            }
+            if b.section != sec {
+                start_section(&mut b, sec)?;
+            }
+            if b.add_resource_within(r, max_size - reserved)? {
+                continue;
+            }
+
+            // The record doesn't fit; finish this packet and move the record
+            // to a new one.
+            if let Some(authorities) = authorities.take() {
+                add_authorities(&mut b, authorities, max_size)?;
+                reserved = 0;
+            }
+            packets.push(std::mem::replace(&mut b, new_packet()));
+            start_section(&mut b, sec)?;
+            if !b.add_resource_within(r, max_size)? {
+                return Err(ERR_MESSAGE_TOO_LARGE.to_owned());
+            }
+        }
+        if let Some(authorities) = authorities.take() {
+            add_authorities(&mut b, authorities, max_size)?;
+        }
+        packets.push(b);
+
+        let last = packets.len() - 1;
+        packets
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut b)| {
+                if !header.response {
+                    if i < last {
+                        b.header.bits |= HEADER_BIT_TC;
+                    } else {
+                        b.header.bits &= !HEADER_BIT_TC;
+                    }
+                }
+                b.finish()
+            })
+            .collect()
+    }
+
     // append_pack is like Pack but appends the full Message to b and returns the
     // extended buffer.
-    pub fn append_pack(&mut self, b: Vec<u8>) -> Result<Vec<u8>, Error> {
+    pub fn append_pack(&mut self, mut b: Vec<u8>) -> Result<Vec<u8>, Error> {
+        self.append(&mut b)?;
+        Ok(b)
+    }
+
+    // append is like append_pack but extends a buffer owned by the caller,
+    // so a single allocation can be reused for many messages. If packing
+    // fails, buf is truncated back to its original length.
+    pub fn append(&mut self, buf: &mut Vec<u8>) -> Result<(), Error> {
         // Validate the lengths. It is very unlikely that anyone will try to
         // pack more than 65535 of any particular type, but it is possible and
         // we should fail gracefully.
@@ -336,8 +594,8 @@ impl Message {
             additionals,
         };
 
-        let compression_off = b.len();
-        let mut msg = h.pack(b);
+        let len = buf.len();
+        h.pack(buf);
 
         // RFC 1035 allows (but does not require) compression for packing. RFC
         // 1035 requires unpacking implementations to support compression, so
@@ -349,19 +607,63 @@ impl Message {
         // compression will help ensure compliance.
         let mut compression = Some(HashMap::new());
 
+        if let Err(err) = self.pack_sections(buf, &mut compression, len) {
+            buf.truncate(len);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    // pack_sections appends the questions and resources of the Message to
+    // msg, which already holds its header at compression_off.
+    fn pack_sections(
+        &mut self,
+        msg: &mut Vec<u8>,
+        compression: &mut Option<HashMap<String, usize>>,
+        compression_off: usize,
+    ) -> Result<(), Error> {
         for question in &self.questions {
-            msg = question.pack(msg, &mut compression, compression_off)?;
+            question.pack(msg, compression, compression_off)?;
         }
         for answer in &mut self.answers {
-            msg = answer.pack(msg, &mut compression, compression_off)?;
+            answer.pack(msg, compression, compression_off)?;
         }
         for authority in &mut self.authorities {
-            msg = authority.pack(msg, &mut compression, compression_off)?;
+            authority.pack(msg, compression, compression_off)?;
         }
         for additional in &mut self.additionals {
-            msg = additional.pack(msg, &mut compression, compression_off)?;
+            additional.pack(msg, compression, compression_off)?;
         }
+        Ok(())
+    }
+}
 
-        Ok(msg)
+// start_section moves the packet being built by b on to the answers or
+// additionals.
+fn start_section(b: &mut Builder, sec: Section) -> Result<(), Error> {
+    if sec == Section::Answers {
+        b.start_answers()?;
+    } else {
+        b.start_additionals()?;
+    }
+    Ok(())
+}
+
+// add_authorities adds the authorities to the first packet of a split
+// message, which was built with room kept for them.
+fn add_authorities(
+    b: &mut Builder,
+    authorities: &mut [Resource],
+    max_size: usize,
+) -> Result<(), Error> {
+    if authorities.is_empty() {
+        return Ok(());
+    }
+    b.start_authorities()?;
+    for r in authorities {
+        if !b.add_resource_within(r, max_size)? {
+            return Err(ERR_MESSAGE_TOO_LARGE.to_owned());
+        }
     }
+    Ok(())
 }
@@ -1,16 +1,31 @@
 //use super::*;
 use crate::errors::*;
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
+use std::ops::Range;
 
 use util::Error;
 
 const NAME_LEN: usize = 255;
 
+// MAX_PTR_HOPS is the maximum number of compression pointers followed while
+// unpacking a single name.
+const MAX_PTR_HOPS: usize = 10;
+
 // A Name is a non-encoded domain name. It is used instead of strings to avoid
 // allocations.
-#[derive(Default, PartialEq, Debug, Clone)]
+//
+// The data is in presentation format: labels are separated by dots, and a dot
+// or backslash inside a label is escaped with a backslash, e.g.
+// "Living Room\. 2._ipp._tcp.local.". \DDD decimal escapes are accepted too.
+//
+// As in mDNS (RFC 6762 Section 16), labels are plain UTF-8 rather than IDNA.
+// Length limits count bytes, and only ASCII letters compare case-insensitively.
+#[derive(Default, PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Name {
     pub data: String,
 }
@@ -22,15 +37,152 @@ impl fmt::Display for Name {
     }
 }
 
+// Arbitrary Names are always valid: up to four labels of 1 to 40 bytes.
+#[cfg(feature = "fuzz")]
+impl<'a> arbitrary::Arbitrary<'a> for Name {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut labels = vec![];
+        for _ in 0..u.int_in_range(0..=4)? {
+            let mut label: String = u.arbitrary()?;
+            while label.len() > 40 {
+                label.pop();
+            }
+            if label.is_empty() {
+                label.push('a');
+            }
+            labels.push(label);
+        }
+        Name::from_labels(labels).map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
+
 impl Name {
+    // new validates data as a domain name: every label must be 1 to 63 bytes
+    // and the encoded name must fit in 255 bytes. The root name may be given
+    // as "" or ".".
     pub fn new(data: &str) -> Result<Self, Error> {
-        if data.len() > NAME_LEN {
-            Err(ERR_CALC_LEN.to_owned())
-        } else {
-            Ok(Name {
-                data: data.to_owned(),
-            })
+        let (ranges, _) = split_labels(data);
+        let mut wire_len = 1;
+        for r in ranges {
+            let label = unescape_label(&data[r])?;
+            check_label_len(label.len())?;
+            wire_len += 1 + label.len();
+        }
+        if wire_len > NAME_LEN {
+            return Err(ERR_NAME_TOO_LONG.to_owned());
+        }
+
+        Ok(Name {
+            data: data.to_owned(),
+        })
+    }
+
+    // from_labels builds a canonical Name (ending with a dot) from its
+    // labels, most specific first. Dots and backslashes within a label are
+    // escaped. No labels yields the root name.
+    pub fn from_labels<I, S>(labels: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut data = String::new();
+        for label in labels {
+            let label = label.as_ref();
+            check_label_len(label.len())?;
+            data.push_str(&escape_label(label));
+            data.push('.');
+        }
+        if data.is_empty() {
+            data.push('.');
+        }
+        Name::new(&data)
+    }
+
+    // labels returns an iterator over the labels of the name, most specific
+    // first, unescaped to their wire bytes. A \DDD escape may make a label
+    // that isn't UTF-8. The root name has no labels.
+    pub fn labels(&self) -> impl Iterator<Item = Cow<'_, [u8]>> + '_ {
+        let (ranges, _) = split_labels(&self.data);
+        ranges.into_iter().filter(|r| !r.is_empty()).map(move |r| {
+            let label = &self.data[r];
+            unescape_label(label).unwrap_or(Cow::Borrowed(label.as_bytes()))
+        })
+    }
+
+    // prepend_label adds label in front of the name, e.g. prepending
+    // "instance" to "_http._tcp.local." gives "instance._http._tcp.local.".
+    // The labels already there are kept as they are written.
+    pub fn prepend_label(&mut self, label: &str) -> Result<(), Error> {
+        check_label_len(label.len())?;
+        let data = escape_label(label).into_owned() + "." + &self.qualified_labels();
+        *self = Name::new(&data)?;
+        Ok(())
+    }
+
+    // append_label adds label at the end of the name, e.g. appending "local"
+    // to "host." gives "host.local.".
+    pub fn append_label(&mut self, label: &str) -> Result<(), Error> {
+        check_label_len(label.len())?;
+        let data = self.qualified_labels().into_owned() + &escape_label(label) + ".";
+        *self = Name::new(&data)?;
+        Ok(())
+    }
+
+    // parent returns the name with its first label removed, or None for the
+    // root name. The remaining labels are kept as they are written.
+    pub fn parent(&self) -> Option<Name> {
+        let (ranges, _) = split_labels(&self.data);
+        let first = ranges.first()?;
+        let rest = Name {
+            data: self
+                .data
+                .get(first.end + 1..)
+                .unwrap_or_default()
+                .to_owned(),
+        };
+        let mut data = rest.qualified_labels().into_owned();
+        if data.is_empty() {
+            data.push('.');
         }
+        Some(Name { data })
+    }
+
+    // qualified_labels returns the labels of the name in presentation
+    // format, each followed by a dot, or nothing for the root name.
+    fn qualified_labels(&self) -> Cow<'_, str> {
+        match split_labels(&self.data) {
+            (ranges, _) if ranges.is_empty() => Cow::Borrowed(""),
+            (_, true) => Cow::Borrowed(&self.data),
+            (_, false) => Cow::Owned(self.data.clone() + "."),
+        }
+    }
+
+    // eq_ignore_ascii_case reports whether the name equals other, comparing
+    // labels case-insensitively as DNS requires. A missing trailing dot is
+    // ignored.
+    pub fn eq_ignore_ascii_case(&self, other: &Name) -> bool {
+        let mut labels = self.labels();
+        for want in other.labels() {
+            match labels.next() {
+                Some(got) if got.eq_ignore_ascii_case(&want) => {}
+                _ => return false,
+            }
+        }
+        labels.next().is_none()
+    }
+
+    // is_subdomain_of reports whether the name is equal to or below parent.
+    // Labels are compared case-insensitively.
+    pub fn is_subdomain_of(&self, parent: &Name) -> bool {
+        let labels: Vec<Cow<'_, [u8]>> = self.labels().collect();
+        let parent_labels: Vec<Cow<'_, [u8]>> = parent.labels().collect();
+        if parent_labels.len() > labels.len() {
+            return false;
+        }
+        labels[labels.len() - parent_labels.len()..]
+            .iter()
+            .zip(parent_labels.iter())
+            .all(|(a, b)| a.eq_ignore_ascii_case(b))
     }
 
     // pack appends the wire format of the Name to msg.
@@ -42,72 +194,60 @@ impl Name {
     // is nil, compression will not be used.
     pub fn pack(
         &self,
-        mut msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
-        let data = self.data.as_bytes();
+    ) -> Result<(), Error> {
+        let (ranges, fully_qualified) = split_labels(&self.data);
 
         // Add a trailing dot to canonicalize name.
-        if data.is_empty() || data[data.len() - 1] != b'.' {
+        if !fully_qualified {
             return Err(ERR_NON_CANONICAL_NAME.to_owned());
         }
 
-        // Allow root domain.
-        if data.len() == 1 && data[0] == b'.' {
-            msg.push(0);
-            return Ok(msg);
-        }
-
-        // Emit sequence of counted strings, chopping at dots.
-        let mut begin = 0;
-        for i in 0..data.len() {
-            // Check for the end of the segment.
-            if data[i] == b'.' {
-                // The two most significant bits have special meaning.
-                // It isn't allowed for segments to be long enough to
-                // need them.
-                if i - begin >= (1 << 6) {
-                    return Err(ERR_SEG_TOO_LONG.to_owned());
-                }
+        // Emit sequence of counted strings, chopping at dots. The root domain
+        // has no labels.
+        for r in ranges {
+            let label = unescape_label(&self.data[r.clone()])?;
 
-                // Segments must have a non-zero length.
-                if i - begin == 0 {
-                    return Err(ERR_ZERO_SEG_LEN.to_owned());
-                }
-
-                msg.push((i - begin) as u8);
-                msg.extend_from_slice(&data[begin..i]);
+            // The two most significant bits have special meaning.
+            // It isn't allowed for segments to be long enough to
+            // need them.
+            if label.len() >= (1 << 6) {
+                return Err(ERR_SEG_TOO_LONG.to_owned());
+            }
 
-                begin = i + 1;
-                continue;
+            // Segments must have a non-zero length.
+            if label.is_empty() {
+                return Err(ERR_ZERO_SEG_LEN.to_owned());
             }
 
             // We can only compress domain suffixes starting with a new
             // segment. A pointer is two bytes with the two most significant
             // bits set to 1 to indicate that it is a pointer.
-            if i == 0 || data[i - 1] == b'.' {
-                if let Some(compression) = compression {
-                    let key: String = self.data.chars().skip(i).collect();
-                    if let Some(ptr) = compression.get(&key) {
-                        // Hit. Emit a pointer instead of the rest of
-                        // the domain.
-                        msg.push(((ptr >> 8) | 0xC0) as u8);
-                        msg.push((ptr & 0xFF) as u8);
-                        return Ok(msg);
-                    }
+            if let Some(compression) = compression {
+                let key = self.data[r.start..].to_owned();
+                if let Some(ptr) = compression.get(&key) {
+                    // Hit. Emit a pointer instead of the rest of
+                    // the domain.
+                    msg.push(((ptr >> 8) | 0xC0) as u8);
+                    msg.push((ptr & 0xFF) as u8);
+                    return Ok(());
+                }
 
-                    // Miss. Add the suffix to the compression table if the
-                    // offset can be stored in the available 14 bytes.
-                    if msg.len() <= 0x3FFF {
-                        compression.insert(key, msg.len() - compression_off);
-                    }
+                // Miss. Add the suffix to the compression table if the
+                // offset can be stored in the available 14 bytes.
+                if msg.len() <= 0x3FFF {
+                    compression.insert(key, msg.len() - compression_off);
                 }
             }
+
+            msg.push(label.len() as u8);
+            msg.extend_from_slice(&label);
         }
 
         msg.push(0);
-        Ok(msg)
+        Ok(())
     }
 
     // unpack unpacks a domain name.
@@ -121,77 +261,16 @@ impl Name {
         off: usize,
         allow_compression: bool,
     ) -> Result<usize, Error> {
-        // curr_off is the current working offset.
-        let mut curr_off = off;
-
-        // new_off is the offset where the next record will start. Pointers lead
-        // to data that belongs to other names and thus doesn't count towards to
-        // the usage of this name.
-        let mut new_off = off;
-
-        // ptr is the number of pointers followed.
-        let mut ptr = 0;
-
-        // Name is a slice representation of the name data.
-        let mut name = String::new(); //n.Data[:0]
-
-        loop {
-            if curr_off >= msg.len() {
-                return Err(ERR_BASE_LEN.to_owned());
-            }
-            let c = msg[curr_off];
-            curr_off += 1;
-            match c & 0xC0 {
-                0x00 => {
-                    // String segment
-                    if c == 0x00 {
-                        // A zero length signals the end of the name.
-                        break;
-                    }
-                    let end_off = curr_off + c as usize;
-                    if end_off > msg.len() {
-                        return Err(ERR_CALC_LEN.to_owned());
-                    }
-                    name.push_str(String::from_utf8(msg[curr_off..end_off].to_vec())?.as_str());
-                    name.push('.');
-                    curr_off = end_off;
-                }
-                0xC0 => {
-                    // Pointer
-                    if !allow_compression {
-                        return Err(ERR_COMPRESSED_SRV.to_owned());
-                    }
-                    if curr_off >= msg.len() {
-                        return Err(ERR_INVALID_PTR.to_owned());
-                    }
-                    let c1 = msg[curr_off];
-                    curr_off += 1;
-                    if ptr == 0 {
-                        new_off = curr_off;
-                    }
-                    // Don't follow too many pointers, maybe there's a loop.
-                    ptr += 1;
-                    if ptr > 10 {
-                        return Err(ERR_TOO_MANY_PTR.to_owned());
-                    }
-                    curr_off = ((c ^ 0xC0) as usize) << 8 | (c1 as usize);
-                }
-                _ => {
-                    // Prefixes 0x80 and 0x40 are reserved.
-                    return Err(ERR_RESERVED.to_owned());
-                }
-            }
-        }
+        let mut name = String::new();
+        let new_off = walk_labels(msg, off, allow_compression, |label| {
+            name.push_str(&escape_label_bytes(label));
+            name.push('.');
+            Ok(())
+        })?;
         if name.is_empty() {
             name.push('.');
         }
-        if name.len() > NAME_LEN {
-            return Err(ERR_CALC_LEN.to_owned());
-        }
         self.data = name;
-        if ptr == 0 {
-            new_off = curr_off;
-        }
         Ok(new_off)
     }
 
@@ -238,3 +317,358 @@ impl Name {
         Ok(new_off)
     }
 }
+
+#[cfg(feature = "idna")]
+impl Name {
+    // to_ascii converts the UTF-8 labels of the name to their xn-- punycode
+    // form, for handing mDNS names to unicast DNS. ASCII labels are kept
+    // as they are.
+    pub fn to_ascii(&self) -> Result<Name, Error> {
+        self.map_labels(|label| {
+            if label.is_ascii() {
+                return Ok(label.to_owned());
+            }
+            match idna::domain_to_ascii(label) {
+                Ok(ascii) if !ascii.contains('.') => Ok(ascii),
+                _ => Err(ERR_IDNA.to_owned()),
+            }
+        })
+    }
+
+    // to_unicode converts xn-- punycode labels of the name back to UTF-8.
+    // Other labels are kept as they are.
+    pub fn to_unicode(&self) -> Result<Name, Error> {
+        self.map_labels(|label| {
+            if !label
+                .get(..4)
+                .is_some_and(|p| p.eq_ignore_ascii_case("xn--"))
+            {
+                return Ok(label.to_owned());
+            }
+            match idna::domain_to_unicode(label) {
+                (unicode, Ok(())) if !unicode.contains('.') => Ok(unicode),
+                _ => Err(ERR_IDNA.to_owned()),
+            }
+        })
+    }
+
+    fn map_labels<F>(&self, f: F) -> Result<Name, Error>
+    where
+        F: Fn(&str) -> Result<String, Error>,
+    {
+        let labels = self
+            .labels()
+            .map(|label| match std::str::from_utf8(&label) {
+                Ok(label) => f(label),
+                Err(_) => Err(ERR_IDNA.to_owned()),
+            })
+            .collect::<Result<Vec<String>, Error>>()?;
+        let mut name = Name::from_labels(labels)?;
+        if !split_labels(&self.data).1 && name.data.len() > 1 {
+            name.data.pop();
+        }
+        Ok(name)
+    }
+}
+
+// check_label_len validates the wire length of a single label.
+fn check_label_len(len: usize) -> Result<(), Error> {
+    if len == 0 {
+        return Err(ERR_ZERO_SEG_LEN.to_owned());
+    }
+    if len >= 1 << 6 {
+        return Err(ERR_SEG_TOO_LONG.to_owned());
+    }
+    Ok(())
+}
+
+// split_labels splits a presentation format name at its unescaped dots and
+// returns the byte range of each label in data, along with whether the name
+// ends with a dot. The root name "." has no labels.
+pub(crate) fn split_labels(data: &str) -> (Vec<Range<usize>>, bool) {
+    if data == "." {
+        return (vec![], true);
+    }
+
+    let b = data.as_bytes();
+    let mut ranges = vec![];
+    let mut begin = 0;
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'\\' => i += 1,
+            b'.' => {
+                ranges.push(begin..i);
+                begin = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if begin < b.len() {
+        ranges.push(begin..b.len());
+        (ranges, false)
+    } else {
+        (ranges, !b.is_empty())
+    }
+}
+
+// unescape_label decodes the \X and \DDD escapes of a presentation format
+// label into its wire bytes.
+pub(crate) fn unescape_label(label: &str) -> Result<Cow<'_, [u8]>, Error> {
+    let b = label.as_bytes();
+    if !b.contains(&b'\\') {
+        return Ok(Cow::Borrowed(b));
+    }
+
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] != b'\\' {
+            out.push(b[i]);
+            i += 1;
+            continue;
+        }
+        match b.get(i + 1..i + 4) {
+            Some(d) if d.iter().all(u8::is_ascii_digit) => {
+                let v = d.iter().fold(0u16, |v, c| v * 10 + u16::from(c - b'0'));
+                if v > 0xFF {
+                    return Err(ERR_INVALID_ESCAPE.to_owned());
+                }
+                out.push(v as u8);
+                i += 4;
+            }
+            _ => match b.get(i + 1) {
+                Some(c) if !c.is_ascii_digit() => {
+                    out.push(*c);
+                    i += 2;
+                }
+                _ => return Err(ERR_INVALID_ESCAPE.to_owned()),
+            },
+        }
+    }
+    Ok(Cow::Owned(out))
+}
+
+// escape_label encodes a label in presentation format, escaping dots and
+// backslashes.
+fn escape_label(label: &str) -> Cow<'_, str> {
+    if !label.contains(['.', '\\']) {
+        return Cow::Borrowed(label);
+    }
+
+    let mut out = String::with_capacity(label.len() + 2);
+    for c in label.chars() {
+        if c == '.' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    Cow::Owned(out)
+}
+
+// escape_label_bytes encodes a label of wire bytes in presentation format
+// like escape_label. A label that isn't UTF-8, or holds control characters,
+// has every byte outside printable ASCII written as a \DDD escape.
+pub(crate) fn escape_label_bytes(label: &[u8]) -> Cow<'_, str> {
+    match std::str::from_utf8(label) {
+        Ok(label) if !label.contains(|c: char| c.is_control()) => return escape_label(label),
+        _ => {}
+    }
+
+    let mut out = String::with_capacity(label.len() * 2);
+    for &b in label {
+        match b {
+            b'.' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7E => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03}", b)),
+        }
+    }
+    Cow::Owned(out)
+}
+
+// A NameRef is a domain name borrowed from a packed message. Its labels are
+// decoded on demand, following compression pointers, so scanning a message
+// doesn't allocate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NameRef<'a> {
+    msg: &'a [u8],
+    off: usize,
+}
+
+impl<'a> NameRef<'a> {
+    // unpack validates the name starting at off in msg and returns it along
+    // with the offset where the next field starts. The same limits as
+    // Name::unpack apply.
+    pub fn unpack(msg: &'a [u8], off: usize) -> Result<(Self, usize), Error> {
+        let new_off = walk_labels(msg, off, true, |_| Ok(()))?;
+        Ok((NameRef { msg, off }, new_off))
+    }
+
+    // labels returns an iterator over the raw labels of the name.
+    pub fn labels(&self) -> NameRefLabels<'a> {
+        NameRefLabels {
+            msg: self.msg,
+            off: self.off,
+        }
+    }
+
+    // eq_name reports whether the name equals name, without allocating.
+    // Labels are compared case-insensitively.
+    pub fn eq_name(&self, name: &Name) -> bool {
+        let mut labels = self.labels();
+        for want in name.labels() {
+            match labels.next() {
+                Some(got) if got.eq_ignore_ascii_case(&want) => {}
+                _ => return false,
+            }
+        }
+        labels.next().is_none()
+    }
+
+    // to_name copies the name into an owned Name.
+    pub fn to_name(&self) -> Name {
+        Name {
+            data: self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for NameRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut empty = true;
+        for label in self.labels() {
+            write!(f, "{}.", escape_label_bytes(label))?;
+            empty = false;
+        }
+        if empty {
+            write!(f, ".")?;
+        }
+        Ok(())
+    }
+}
+
+// NameRefLabels iterates over the labels of a NameRef.
+pub struct NameRefLabels<'a> {
+    msg: &'a [u8],
+    off: usize,
+}
+
+impl<'a> Iterator for NameRefLabels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The name was validated by NameRef::unpack, so the offsets are known
+        // to be in bounds.
+        loop {
+            let c = *self.msg.get(self.off)?;
+            if c & 0xC0 == 0xC0 {
+                let c1 = *self.msg.get(self.off + 1)?;
+                self.off = ((c ^ 0xC0) as usize) << 8 | (c1 as usize);
+                continue;
+            }
+            if c == 0 {
+                return None;
+            }
+            let begin = self.off + 1;
+            self.off = begin + c as usize;
+            return self.msg.get(begin..self.off);
+        }
+    }
+}
+
+// walk_labels validates the name starting at off in msg, passing each of its
+// labels to f in order, and returns the offset where the next field starts.
+// Compression pointers are followed if allow_compression is set.
+fn walk_labels<'a, F>(
+    msg: &'a [u8],
+    off: usize,
+    allow_compression: bool,
+    mut f: F,
+) -> Result<usize, Error>
+where
+    F: FnMut(&'a [u8]) -> Result<(), Error>,
+{
+    // curr_off is the current working offset.
+    let mut curr_off = off;
+
+    // new_off is the offset where the next record will start. Pointers lead
+    // to data that belongs to other names and thus doesn't count towards to
+    // the usage of this name.
+    let mut new_off = off;
+
+    // ptr is the number of pointers followed.
+    let mut ptr = 0;
+
+    // name_len is the wire length of the expanded name, counting the
+    // terminating root byte as Name::new does.
+    let mut name_len = 1;
+
+    loop {
+        if curr_off >= msg.len() {
+            return Err(ERR_BASE_LEN.to_owned());
+        }
+        let c = msg[curr_off];
+        curr_off += 1;
+        match c & 0xC0 {
+            0x00 => {
+                // String segment
+                if c == 0x00 {
+                    // A zero length signals the end of the name.
+                    break;
+                }
+                let end_off = curr_off + c as usize;
+                if end_off > msg.len() {
+                    return Err(ERR_CALC_LEN.to_owned());
+                }
+
+                // Bail out as soon as the expanded name is too long, so a
+                // chain of pointers can't make us build a huge string.
+                name_len += c as usize + 1;
+                if name_len > NAME_LEN {
+                    return Err(ERR_NAME_TOO_LONG.to_owned());
+                }
+                f(&msg[curr_off..end_off])?;
+                curr_off = end_off;
+            }
+            0xC0 => {
+                // Pointer
+                if !allow_compression {
+                    return Err(ERR_COMPRESSED_SRV.to_owned());
+                }
+                if curr_off >= msg.len() {
+                    return Err(ERR_INVALID_PTR.to_owned());
+                }
+                let c1 = msg[curr_off];
+                curr_off += 1;
+                if ptr == 0 {
+                    new_off = curr_off;
+                }
+                // Don't follow too many pointers, maybe there's a loop.
+                ptr += 1;
+                if ptr > MAX_PTR_HOPS {
+                    return Err(ERR_TOO_MANY_PTR.to_owned());
+                }
+                // Pointers may only refer to a prior occurrence of a name
+                // (RFC 1035 Section 4.1.4), which also rules out a pointer
+                // to itself.
+                let ptr_off = ((c ^ 0xC0) as usize) << 8 | (c1 as usize);
+                if ptr_off >= curr_off - 2 {
+                    return Err(ERR_INVALID_PTR.to_owned());
+                }
+                curr_off = ptr_off;
+            }
+            _ => {
+                // Prefixes 0x80 and 0x40 are reserved.
+                return Err(ERR_RESERVED.to_owned());
+            }
+        }
+    }
+    if ptr == 0 {
+        new_off = curr_off;
+    }
+    Ok(new_off)
+}
@@ -4,9 +4,8 @@ use crate::errors::*;
 use util::Error;
 
 // pack_bytes appends the wire format of field to msg.
-pub(crate) fn pack_bytes(mut msg: Vec<u8>, field: &[u8]) -> Vec<u8> {
+pub(crate) fn pack_bytes(msg: &mut Vec<u8>, field: &[u8]) {
     msg.extend_from_slice(field);
-    msg
 }
 
 pub(crate) fn unpack_bytes(msg: &[u8], off: usize, field: &mut [u8]) -> Result<usize, Error> {
@@ -19,9 +18,8 @@ pub(crate) fn unpack_bytes(msg: &[u8], off: usize, field: &mut [u8]) -> Result<u
 }
 
 // pack_uint16 appends the wire format of field to msg.
-pub(crate) fn pack_uint16(mut msg: Vec<u8>, field: u16) -> Vec<u8> {
+pub(crate) fn pack_uint16(msg: &mut Vec<u8>, field: u16) {
     msg.extend_from_slice(&field.to_be_bytes());
-    msg
 }
 
 pub(crate) fn unpack_uint16(msg: &[u8], off: usize) -> Result<(u16, usize), Error> {
@@ -43,9 +41,8 @@ pub(crate) fn skip_uint16(msg: &[u8], off: usize) -> Result<usize, Error> {
 }
 
 // pack_uint32 appends the wire format of field to msg.
-pub(crate) fn pack_uint32(mut msg: Vec<u8>, field: u32) -> Vec<u8> {
+pub(crate) fn pack_uint32(msg: &mut Vec<u8>, field: u32) {
     msg.extend_from_slice(&field.to_be_bytes());
-    msg
 }
 
 pub(crate) fn unpack_uint32(msg: &[u8], off: usize) -> Result<(u32, usize), Error> {
@@ -67,14 +64,14 @@ pub(crate) fn skip_uint32(msg: &[u8], off: usize) -> Result<usize, Error> {
 }
 
 // pack_text appends the wire format of field to msg.
-pub(crate) fn pack_str(mut msg: Vec<u8>, field: &str) -> Result<Vec<u8>, Error> {
+pub(crate) fn pack_str(msg: &mut Vec<u8>, field: &str) -> Result<(), Error> {
     let l = field.len();
     if l > 255 {
         return Err(ERR_STRING_TOO_LONG.to_owned());
     }
     msg.push(l as u8);
     msg.extend_from_slice(field.as_bytes());
-    Ok(msg)
+    Ok(())
 }
 
 pub(crate) fn unpack_str(msg: &[u8], off: usize) -> Result<(String, usize), Error> {
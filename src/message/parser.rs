@@ -1,10 +1,13 @@
 use crate::errors::*;
 use crate::message::header::{Header, HeaderInternal, Section};
-use crate::message::resource::{unpack_resource_body, Resource, ResourceBody, ResourceHeader};
+use crate::message::resource::{
+    unpack_resource_body, Resource, ResourceData, ResourceHeader, ResourceRef,
+};
 
-use crate::message::name::Name;
-use crate::message::question::Question;
-use crate::message::{DNSClass, DNSType};
+use crate::message::name::{Name, NameRef};
+use crate::message::packer::unpack_uint16;
+use crate::message::question::{Question, QuestionRef};
+use crate::message::{DNSClass, DNSType, CLASS_BIT_QU};
 use util::Error;
 
 // A Parser allows incrementally parsing a DNS message.
@@ -72,6 +75,17 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn resource_ref(&mut self, sec: Section) -> Result<ResourceRef<'a>, Error> {
+        if self.res_header_valid {
+            return Err(ERR_NOT_STARTED.to_owned());
+        }
+        self.check_advance(sec)?;
+        let (r, off) = ResourceRef::unpack(self.msg, self.off)?;
+        self.off = off;
+        self.index += 1;
+        Ok(r)
+    }
+
     fn resource_header(&mut self, sec: Section) -> Result<ResourceHeader, Error> {
         if self.res_header_valid {
             return Ok(self.res_header.clone());
@@ -107,15 +121,35 @@ impl<'a> Parser<'a> {
     // question parses a single question.
     pub fn question(&mut self) -> Result<Question, Error> {
         self.check_advance(Section::Questions)?;
-        let mut name = Name::new("")?;
+        let mut name = Name::default();
         let mut off = name.unpack(self.msg, self.off)?;
-        let mut typ = DNSType::Unsupported;
+        let mut typ = DNSType::UNSUPPORTED;
         off = typ.unpack(self.msg, off)?;
-        let mut class = DNSClass::default();
-        off = class.unpack(self.msg, off)?;
+        let (class, off) = unpack_uint16(self.msg, off)?;
         self.off = off;
         self.index += 1;
-        Ok(Question { name, typ, class })
+        Ok(Question {
+            name,
+            typ,
+            class: DNSClass(class & !CLASS_BIT_QU),
+            unicast_response: class & CLASS_BIT_QU != 0,
+        })
+    }
+
+    // question_ref parses a single question without copying its name.
+    pub fn question_ref(&mut self) -> Result<QuestionRef<'a>, Error> {
+        self.check_advance(Section::Questions)?;
+        let (name, off) = NameRef::unpack(self.msg, self.off)?;
+        let (typ, off) = unpack_uint16(self.msg, off)?;
+        let (class, off) = unpack_uint16(self.msg, off)?;
+        self.off = off;
+        self.index += 1;
+        Ok(QuestionRef {
+            name,
+            typ: DNSType(typ),
+            class: DNSClass(class & !CLASS_BIT_QU),
+            unicast_response: class & CLASS_BIT_QU != 0,
+        })
     }
 
     // all_questions parses all Questions.
@@ -175,6 +209,11 @@ impl<'a> Parser<'a> {
         self.resource(Section::Answers)
     }
 
+    // answer_ref parses a single answer Resource without copying it.
+    pub fn answer_ref(&mut self) -> Result<ResourceRef<'a>, Error> {
+        self.resource_ref(Section::Answers)
+    }
+
     // all_answers parses all answer Resources.
     pub fn all_answers(&mut self) -> Result<Vec<Resource>, Error> {
         // The most common query is for A/AAAA, which usually returns
@@ -229,6 +268,11 @@ impl<'a> Parser<'a> {
         self.resource(Section::Authorities)
     }
 
+    // authority_ref parses a single authority Resource without copying it.
+    pub fn authority_ref(&mut self) -> Result<ResourceRef<'a>, Error> {
+        self.resource_ref(Section::Authorities)
+    }
+
     // all_authorities parses all authority Resources.
     pub fn all_authorities(&mut self) -> Result<Vec<Resource>, Error> {
         // Authorities contains SOA in case of NXDOMAIN and friends,
@@ -283,6 +327,11 @@ impl<'a> Parser<'a> {
         self.resource(Section::Additionals)
     }
 
+    // additional_ref parses a single additional Resource without copying it.
+    pub fn additional_ref(&mut self) -> Result<ResourceRef<'a>, Error> {
+        self.resource_ref(Section::Additionals)
+    }
+
     // all_additionals parses all additional Resources.
     pub fn all_additionals(&mut self) -> Result<Vec<Resource>, Error> {
         // Additionals usually contain OPT, and sometimes A/AAAA
@@ -331,7 +380,7 @@ impl<'a> Parser<'a> {
     //
     // One of the XXXHeader methods must have been called before calling this
     // method.
-    pub fn resource_body(&mut self) -> Result<Box<dyn ResourceBody>, Error> {
+    pub fn resource_body(&mut self) -> Result<ResourceData, Error> {
         if !self.res_header_valid {
             return Err(ERR_NOT_STARTED.to_owned());
         }
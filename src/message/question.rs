@@ -7,19 +7,28 @@ use std::fmt;
 use util::Error;
 
 // A question is a DNS query.
-#[derive(Default, Debug, PartialEq, Clone)]
+#[derive(Default, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct Question {
     pub name: Name,
     pub typ: DNSType,
     pub class: DNSClass,
+
+    // unicast_response is the mDNS QU bit, the top bit of the class on the
+    // wire. It asks responders to reply by unicast (RFC 6762 Section 5.4).
+    pub unicast_response: bool,
 }
 
 impl fmt::Display for Question {
+    // fmt writes the Question as dig prints it in the question section.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "dnsmessage.question{{Name: {}, Type: {}, Class: {}}}",
-            self.name, self.typ, self.class
+            ";{}\t\t{}\t{}",
+            self.name,
+            self.class.presentation(),
+            self.typ
         )
     }
 }
@@ -28,12 +37,38 @@ impl Question {
     // pack appends the wire format of the question to msg.
     pub fn pack(
         &self,
-        mut msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
-        msg = self.name.pack(msg, compression, compression_off)?;
-        msg = self.typ.pack(msg);
-        Ok(self.class.pack(msg))
+    ) -> Result<(), Error> {
+        self.name.pack(msg, compression, compression_off)?;
+        self.typ.pack(msg);
+        if self.unicast_response {
+            DNSClass(self.class.0 | CLASS_BIT_QU).pack(msg);
+        } else {
+            self.class.pack(msg);
+        }
+        Ok(())
+    }
+}
+
+// A QuestionRef is a Question borrowed from a packed message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QuestionRef<'a> {
+    pub name: NameRef<'a>,
+    pub typ: DNSType,
+    pub class: DNSClass,
+    pub unicast_response: bool,
+}
+
+impl QuestionRef<'_> {
+    // to_question copies the question into an owned Question.
+    pub fn to_question(&self) -> Question {
+        Question {
+            name: self.name.to_name(),
+            typ: self.typ,
+            class: self.class,
+            unicast_response: self.unicast_response,
+        }
     }
 }
@@ -2,7 +2,9 @@ use super::*;
 use crate::message::packer::*;
 
 // An AResource is an A Resource record.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct AResource {
     pub a: [u8; 4],
 }
@@ -21,11 +23,12 @@ impl ResourceBody for AResource {
     // pack appends the wire format of the AResource to msg.
     fn pack(
         &self,
-        msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         _compression: &mut Option<HashMap<String, usize>>,
         _compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
-        Ok(pack_bytes(msg, &self.a))
+    ) -> Result<(), Error> {
+        pack_bytes(msg, &self.a);
+        Ok(())
     }
 
     fn unpack(&mut self, msg: &[u8], off: usize, _length: usize) -> Result<usize, Error> {
@@ -0,0 +1,57 @@
+use super::*;
+use crate::message::packer::*;
+
+// An HINFOResource is an HINFO Resource record.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct HINFOResource {
+    pub cpu: String,
+    pub os: String,
+}
+
+impl fmt::Display for HINFOResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dnsmessage.HINFOResource{{cpu: {}, os: {}}}",
+            self.cpu, self.os
+        )
+    }
+}
+
+impl ResourceBody for HINFOResource {
+    fn real_type(&self) -> DNSType {
+        DNSType::HINFO
+    }
+
+    // pack appends the wire format of the HINFOResource to msg.
+    fn pack(
+        &self,
+        msg: &mut Vec<u8>,
+        _compression: &mut Option<HashMap<String, usize>>,
+        _compression_off: usize,
+    ) -> Result<(), Error> {
+        pack_str(msg, &self.cpu)?;
+        pack_str(msg, &self.os)
+    }
+
+    fn unpack(&mut self, msg: &[u8], off: usize, length: usize) -> Result<usize, Error> {
+        let end = off + length;
+        if end > msg.len() {
+            return Err(ERR_CALC_LEN.to_owned());
+        }
+        let msg = &msg[..end];
+
+        let (cpu, off) = unpack_str(msg, off)?;
+        self.cpu = cpu;
+
+        let (os, off) = unpack_str(msg, off)?;
+        self.os = os;
+
+        if off != end {
+            return Err(ERR_CALC_LEN.to_owned());
+        }
+        Ok(end)
+    }
+}
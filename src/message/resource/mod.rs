@@ -1,13 +1,17 @@
 pub mod a;
 pub mod aaaa;
 pub mod cname;
+pub mod hinfo;
 pub mod mx;
 pub mod ns;
+pub mod nsec;
 pub mod opt;
 pub mod ptr;
 pub mod soa;
 pub mod srv;
+pub mod svcb;
 pub mod txt;
+pub mod unknown;
 
 use super::name::*;
 use super::packer::*;
@@ -17,16 +21,21 @@ use crate::errors::*;
 use a::*;
 use aaaa::*;
 use cname::*;
+use hinfo::*;
 use mx::*;
 use ns::*;
+use nsec::*;
 use opt::*;
 use ptr::*;
 use soa::*;
 use srv::*;
+use svcb::*;
 use txt::*;
+use unknown::*;
 
 use std::collections::HashMap;
 use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use util::Error;
 
@@ -38,24 +47,29 @@ const EDNS_VERSION_MASK: u32 = 0x00ff0000;
 const EDNS0_DNSSEC_OK_MASK: u32 = 0x00ff8000;
 
 // A Resource is a DNS resource record.
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct Resource {
     pub header: ResourceHeader,
-    pub body: Option<Box<dyn ResourceBody>>,
+    pub body: Option<ResourceData>,
 }
 
 impl fmt::Display for Resource {
+    // fmt writes the Resource as a single zone file line, as printed by dig.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "dnsmessage.Resource{{Header: {}, Body: {}}}",
-            self.header,
-            if let Some(body) = &self.body {
-                body.to_string()
-            } else {
-                "None".to_owned()
-            }
-        )
+            "{}\t{}\t{}\t{}\t",
+            self.header.name,
+            self.header.ttl,
+            self.header.class.presentation(),
+            self.header.typ
+        )?;
+        if let Some(body) = &self.body {
+            body.fmt_rdata(f)?;
+        }
+        Ok(())
     }
 }
 
@@ -63,22 +77,22 @@ impl Resource {
     // pack appends the wire format of the Resource to msg.
     pub fn pack(
         &mut self,
-        msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
+    ) -> Result<(), Error> {
         if let Some(body) = &self.body {
             self.header.typ = body.real_type();
         } else {
             return Err(ERR_NIL_RESOURCE_BODY.to_owned());
         }
-        let (mut msg, len_off) = self.header.pack(msg, compression, compression_off)?;
+        let len_off = self.header.pack(msg, compression, compression_off)?;
         let pre_len = msg.len();
         if let Some(body) = &self.body {
-            msg = body.pack(msg, compression, compression_off)?;
-            self.header.fix_len(&mut msg, len_off, pre_len)?;
+            body.pack(msg, compression, compression_off)?;
+            self.header.fix_len(msg, len_off, pre_len)?;
         }
-        Ok(msg)
+        Ok(())
     }
 
     pub fn unpack(&mut self, msg: &[u8], mut off: usize) -> Result<usize, Error> {
@@ -101,11 +115,96 @@ impl Resource {
         }
         Ok(new_off)
     }
+
+    // ip returns the address of an A or AAAA record.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match &self.body {
+            Some(ResourceData::A(r)) => Some(IpAddr::V4(Ipv4Addr::from(r.a))),
+            Some(ResourceData::AAAA(r)) => Some(IpAddr::V6(Ipv6Addr::from(r.aaaa))),
+            _ => None,
+        }
+    }
+}
+
+// A ResourceRef is a Resource borrowed from a packed message. The body is
+// left as raw RDATA; names inside it may still point elsewhere in msg.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResourceRef<'a> {
+    pub name: NameRef<'a>,
+    pub typ: DNSType,
+    pub class: DNSClass,
+    pub cache_flush: bool,
+    pub ttl: u32,
+    pub data: &'a [u8],
+
+    // msg and off locate data within the whole message.
+    msg: &'a [u8],
+    off: usize,
+}
+
+impl<'a> ResourceRef<'a> {
+    pub(crate) fn unpack(msg: &'a [u8], off: usize) -> Result<(Self, usize), Error> {
+        let (name, off) = NameRef::unpack(msg, off)?;
+        let (typ, off) = unpack_uint16(msg, off)?;
+        let (class, off) = unpack_uint16(msg, off)?;
+        let (ttl, off) = unpack_uint32(msg, off)?;
+        let (length, off) = unpack_uint16(msg, off)?;
+        let end_off = off + length as usize;
+        if end_off > msg.len() {
+            return Err(ERR_RESOURCE_LEN.to_owned());
+        }
+        let (class, cache_flush) = split_class(DNSType(typ), class);
+
+        Ok((
+            ResourceRef {
+                name,
+                typ: DNSType(typ),
+                class,
+                cache_flush,
+                ttl,
+                data: &msg[off..end_off],
+                msg,
+                off,
+            },
+            end_off,
+        ))
+    }
+
+    // to_resource decodes the record into an owned Resource.
+    pub fn to_resource(&self) -> Result<Resource, Error> {
+        let (body, _) = unpack_resource_body(self.typ, self.msg, self.off, self.data.len())?;
+        Ok(Resource {
+            header: ResourceHeader {
+                name: self.name.to_name(),
+                typ: self.typ,
+                class: self.class,
+                cache_flush: self.cache_flush,
+                ttl: self.ttl,
+                length: self.data.len() as u16,
+            },
+            body: Some(body),
+        })
+    }
+}
+
+// split_class separates the cache-flush bit from the class of a record of
+// type typ.
+fn split_class(typ: DNSType, class: u16) -> (DNSClass, bool) {
+    if typ == DNSType::OPT {
+        (DNSClass(class), false)
+    } else {
+        (
+            DNSClass(class & !CLASS_BIT_CACHE_FLUSH),
+            class & CLASS_BIT_CACHE_FLUSH != 0,
+        )
+    }
 }
 
 // A ResourceHeader is the header of a DNS resource record. There are
 // many types of DNS resource records, but they all share the same header.
-#[derive(Clone, Default, PartialEq, Debug)]
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct ResourceHeader {
     // Name is the domain name for which this resource record pertains.
     pub name: Name,
@@ -119,6 +218,11 @@ pub struct ResourceHeader {
     // pertains.
     pub class: DNSClass,
 
+    // cache_flush is the mDNS cache-flush bit, the top bit of the class on
+    // the wire (RFC 6762 Section 10.2). It is always false for OPT records,
+    // whose class holds the UDP payload size.
+    pub cache_flush: bool,
+
     // TTL is the length of time (measured in seconds) which this resource
     // record is valid for (time to live). All Resources in a set should
     // have the same TTL (RFC 2181 Section 5.2).
@@ -146,24 +250,31 @@ impl ResourceHeader {
     // lenOff is the offset in msg where the Length field was packed.
     pub fn pack(
         &self,
-        mut msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<(Vec<u8>, usize), Error> {
-        msg = self.name.pack(msg, compression, compression_off)?;
-        msg = self.typ.pack(msg);
-        msg = self.class.pack(msg);
-        msg = pack_uint32(msg, self.ttl);
+    ) -> Result<usize, Error> {
+        self.name.pack(msg, compression, compression_off)?;
+        self.typ.pack(msg);
+        if self.cache_flush && self.typ != DNSType::OPT {
+            DNSClass(self.class.0 | CLASS_BIT_CACHE_FLUSH).pack(msg);
+        } else {
+            self.class.pack(msg);
+        }
+        pack_uint32(msg, self.ttl);
         let len_off = msg.len();
-        msg = pack_uint16(msg, self.length);
-        Ok((msg, len_off))
+        pack_uint16(msg, self.length);
+        Ok(len_off)
     }
 
     pub fn unpack(&mut self, msg: &[u8], off: usize, _length: usize) -> Result<usize, Error> {
         let mut new_off = off;
         new_off = self.name.unpack(msg, new_off)?;
         new_off = self.typ.unpack(msg, new_off)?;
-        new_off = self.class.unpack(msg, new_off)?;
+        let (class, new_off) = unpack_uint16(msg, new_off)?;
+        let (class, cache_flush) = split_class(self.typ, class);
+        self.class = class;
+        self.cache_flush = cache_flush;
         let (ttl, new_off) = unpack_uint32(msg, new_off)?;
         self.ttl = ttl;
         let (l, new_off) = unpack_uint16(msg, new_off)?;
@@ -241,35 +352,303 @@ pub trait ResourceBody: fmt::Display + fmt::Debug {
     // pack packs a Resource except for its header.
     fn pack(
         &self,
-        msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<Vec<u8>, Error>;
+    ) -> Result<(), Error>;
 
     fn unpack(&mut self, msg: &[u8], off: usize, length: usize) -> Result<usize, Error>;
 }
 
+// ResourceData is the body of a Resource, one variant per supported record
+// type. Records of any other type are kept as ResourceData::Unknown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum ResourceData {
+    A(AResource),
+    NS(NSResource),
+    CNAME(CNAMEResource),
+    SOA(SOAResource),
+    PTR(PTRResource),
+    MX(MXResource),
+    TXT(TXTResource),
+    AAAA(AAAAResource),
+    SRV(SRVResource),
+    OPT(OPTResource),
+    NSEC(NSECResource),
+    HINFO(HINFOResource),
+    SVCB(SVCBResource),
+    HTTPS(HTTPSResource),
+    Unknown(UnknownResource),
+}
+
+// resource_data_match expands to a match on every variant of the
+// ResourceData $data, evaluating $arm with the body of the variant bound to
+// $rb.
+macro_rules! resource_data_match {
+    ($data:expr, $rb:ident => $arm:expr) => {
+        match $data {
+            ResourceData::A($rb) => $arm,
+            ResourceData::NS($rb) => $arm,
+            ResourceData::CNAME($rb) => $arm,
+            ResourceData::SOA($rb) => $arm,
+            ResourceData::PTR($rb) => $arm,
+            ResourceData::MX($rb) => $arm,
+            ResourceData::TXT($rb) => $arm,
+            ResourceData::AAAA($rb) => $arm,
+            ResourceData::SRV($rb) => $arm,
+            ResourceData::OPT($rb) => $arm,
+            ResourceData::NSEC($rb) => $arm,
+            ResourceData::HINFO($rb) => $arm,
+            ResourceData::SVCB($rb) => $arm,
+            ResourceData::HTTPS($rb) => $arm,
+            ResourceData::Unknown($rb) => $arm,
+        }
+    };
+}
+
+impl Resource {
+    // as_a returns the body of the Resource if it is an A record.
+    pub fn as_a(&self) -> Option<&AResource> {
+        match &self.body {
+            Some(ResourceData::A(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_ns returns the body of the Resource if it is an NS record.
+    pub fn as_ns(&self) -> Option<&NSResource> {
+        match &self.body {
+            Some(ResourceData::NS(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_cname returns the body of the Resource if it is a CNAME record.
+    pub fn as_cname(&self) -> Option<&CNAMEResource> {
+        match &self.body {
+            Some(ResourceData::CNAME(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_soa returns the body of the Resource if it is an SOA record.
+    pub fn as_soa(&self) -> Option<&SOAResource> {
+        match &self.body {
+            Some(ResourceData::SOA(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_ptr returns the body of the Resource if it is a PTR record.
+    pub fn as_ptr(&self) -> Option<&PTRResource> {
+        match &self.body {
+            Some(ResourceData::PTR(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_mx returns the body of the Resource if it is an MX record.
+    pub fn as_mx(&self) -> Option<&MXResource> {
+        match &self.body {
+            Some(ResourceData::MX(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_txt returns the body of the Resource if it is a TXT record.
+    pub fn as_txt(&self) -> Option<&TXTResource> {
+        match &self.body {
+            Some(ResourceData::TXT(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_aaaa returns the body of the Resource if it is an AAAA record.
+    pub fn as_aaaa(&self) -> Option<&AAAAResource> {
+        match &self.body {
+            Some(ResourceData::AAAA(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_srv returns the body of the Resource if it is an SRV record.
+    pub fn as_srv(&self) -> Option<&SRVResource> {
+        match &self.body {
+            Some(ResourceData::SRV(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_opt returns the body of the Resource if it is an OPT pseudo-record.
+    pub fn as_opt(&self) -> Option<&OPTResource> {
+        match &self.body {
+            Some(ResourceData::OPT(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_nsec returns the body of the Resource if it is an NSEC record.
+    pub fn as_nsec(&self) -> Option<&NSECResource> {
+        match &self.body {
+            Some(ResourceData::NSEC(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_hinfo returns the body of the Resource if it is an HINFO record.
+    pub fn as_hinfo(&self) -> Option<&HINFOResource> {
+        match &self.body {
+            Some(ResourceData::HINFO(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_svcb returns the body of the Resource if it is an SVCB record.
+    pub fn as_svcb(&self) -> Option<&SVCBResource> {
+        match &self.body {
+            Some(ResourceData::SVCB(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_https returns the body of the Resource if it is an HTTPS record.
+    pub fn as_https(&self) -> Option<&HTTPSResource> {
+        match &self.body {
+            Some(ResourceData::HTTPS(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // as_unknown returns the body of the Resource if its type has no typed
+    // variant of ResourceData.
+    pub fn as_unknown(&self) -> Option<&UnknownResource> {
+        match &self.body {
+            Some(ResourceData::Unknown(r)) => Some(r),
+            _ => None,
+        }
+    }
+}
+
+impl ResourceData {
+    // fmt_rdata writes the body in its zone file presentation format.
+    fn fmt_rdata(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceData::A(rb) => write!(f, "{}", Ipv4Addr::from(rb.a)),
+            ResourceData::NS(rb) => write!(f, "{}", rb.ns),
+            ResourceData::CNAME(rb) => write!(f, "{}", rb.cname),
+            ResourceData::SOA(rb) => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                rb.ns, rb.mbox, rb.serial, rb.refresh, rb.retry, rb.expire, rb.min_ttl
+            ),
+            ResourceData::PTR(rb) => write!(f, "{}", rb.ptr),
+            ResourceData::MX(rb) => write!(f, "{} {}", rb.pref, rb.mx),
+            ResourceData::TXT(rb) => {
+                let txt: Vec<String> = rb.txt.iter().map(|s| quote(s)).collect();
+                write!(f, "{}", txt.join(" "))
+            }
+            ResourceData::AAAA(rb) => write!(f, "{}", Ipv6Addr::from(rb.aaaa)),
+            ResourceData::SRV(rb) => {
+                write!(f, "{} {} {} {}", rb.priority, rb.weight, rb.port, rb.target)
+            }
+            ResourceData::OPT(rb) => {
+                let opts: Vec<String> = rb
+                    .options
+                    .iter()
+                    .map(|o| format!("{}:{}", o.code, hex(&o.data)))
+                    .collect();
+                write!(f, "{}", opts.join(" "))
+            }
+            ResourceData::NSEC(rb) => {
+                write!(f, "{}", rb.next_domain)?;
+                for t in &rb.types {
+                    write!(f, " {}", t)?;
+                }
+                Ok(())
+            }
+            ResourceData::HINFO(rb) => write!(f, "{} {}", quote(&rb.cpu), quote(&rb.os)),
+            ResourceData::SVCB(rb) | ResourceData::HTTPS(HTTPSResource { svcb: rb }) => {
+                write!(f, "{} {}", rb.priority, rb.target)?;
+                for p in &rb.params {
+                    write!(f, " {}", p.presentation())?;
+                }
+                Ok(())
+            }
+            // RFC 3597 Section 5 generic RDATA encoding.
+            ResourceData::Unknown(rb) => write!(f, "\\# {} {}", rb.data.len(), hex(&rb.data)),
+        }
+    }
+}
+
+// quote returns s as a quoted character-string, escaping quotes and
+// backslashes.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl fmt::Display for ResourceData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        resource_data_match!(self, rb => fmt::Display::fmt(rb, f))
+    }
+}
+
+// ResourceData is itself a ResourceBody, handing each method on to the body
+// of its variant.
+impl ResourceBody for ResourceData {
+    // real_type returns the type of the record held.
+    fn real_type(&self) -> DNSType {
+        resource_data_match!(self, rb => rb.real_type())
+    }
+
+    // pack packs the body of the record held.
+    fn pack(
+        &self,
+        msg: &mut Vec<u8>,
+        compression: &mut Option<HashMap<String, usize>>,
+        compression_off: usize,
+    ) -> Result<(), Error> {
+        resource_data_match!(self, rb => rb.pack(msg, compression, compression_off))
+    }
+
+    // unpack unpacks a body of the type of the variant, as chosen by
+    // unpack_resource_body for the type in the record header.
+    fn unpack(&mut self, msg: &[u8], off: usize, length: usize) -> Result<usize, Error> {
+        resource_data_match!(self, rb => rb.unpack(msg, off, length))
+    }
+}
+
 pub fn unpack_resource_body(
     typ: DNSType,
     msg: &[u8],
     mut off: usize,
     length: usize,
-) -> Result<(Box<dyn ResourceBody>, usize), Error> {
-    let mut rb: Box<dyn ResourceBody> = match typ {
-        DNSType::A => Box::new(AResource::default()),
-        DNSType::NS => Box::new(NSResource::default()),
-        DNSType::CNAME => Box::new(CNAMEResource::default()),
-        DNSType::SOA => Box::new(SOAResource::default()),
-        DNSType::PTR => Box::new(PTRResource::default()),
-        DNSType::MX => Box::new(MXResource::default()),
-        DNSType::TXT => Box::new(TXTResource::default()),
-        DNSType::AAAA => Box::new(AAAAResource::default()),
-        DNSType::SRV => Box::new(SRVResource::default()),
-        DNSType::OPT => Box::new(OPTResource::default()),
-        _ => return Err(ERR_NIL_RESOURCE_BODY.to_owned()),
+) -> Result<(ResourceData, usize), Error> {
+    let mut rb = match typ {
+        DNSType::A => ResourceData::A(AResource::default()),
+        DNSType::NS => ResourceData::NS(NSResource::default()),
+        DNSType::CNAME => ResourceData::CNAME(CNAMEResource::default()),
+        DNSType::SOA => ResourceData::SOA(SOAResource::default()),
+        DNSType::PTR => ResourceData::PTR(PTRResource::default()),
+        DNSType::MX => ResourceData::MX(MXResource::default()),
+        DNSType::TXT => ResourceData::TXT(TXTResource::default()),
+        DNSType::AAAA => ResourceData::AAAA(AAAAResource::default()),
+        DNSType::SRV => ResourceData::SRV(SRVResource::default()),
+        DNSType::OPT => ResourceData::OPT(OPTResource::default()),
+        DNSType::NSEC => ResourceData::NSEC(NSECResource::default()),
+        DNSType::HINFO => ResourceData::HINFO(HINFOResource::default()),
+        DNSType::SVCB => ResourceData::SVCB(SVCBResource::default()),
+        DNSType::HTTPS => ResourceData::HTTPS(HTTPSResource::default()),
+        _ => ResourceData::Unknown(UnknownResource {
+            typ,
+            ..Default::default()
+        }),
     };
-
     off = rb.unpack(msg, off, length)?;
-
     Ok((rb, off))
 }
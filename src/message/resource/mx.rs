@@ -3,7 +3,9 @@ use crate::message::name::*;
 use crate::message::packer::*;
 
 // An MXResource is an mx Resource record.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct MXResource {
     pub pref: u16,
     pub mx: Name,
@@ -27,13 +29,13 @@ impl ResourceBody for MXResource {
     // pack appends the wire format of the MXResource to msg.
     fn pack(
         &self,
-        mut msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
-        msg = pack_uint16(msg, self.pref);
-        msg = self.mx.pack(msg, compression, compression_off)?;
-        Ok(msg)
+    ) -> Result<(), Error> {
+        pack_uint16(msg, self.pref);
+        self.mx.pack(msg, compression, compression_off)?;
+        Ok(())
     }
 
     fn unpack(&mut self, msg: &[u8], off: usize, _length: usize) -> Result<usize, Error> {
@@ -0,0 +1,114 @@
+use super::*;
+use crate::errors::*;
+use crate::message::name::*;
+use crate::message::packer::*;
+
+// An NSECResource is an NSEC Resource record.
+//
+// In mDNS it is used as a negative response, asserting that the owner name
+// has no records of any type missing from types (RFC 6762 Section 6.1).
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct NSECResource {
+    pub next_domain: Name,
+    pub types: Vec<DNSType>,
+}
+
+impl fmt::Display for NSECResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let types: Vec<String> = self.types.iter().map(|t| t.to_string()).collect();
+        write!(
+            f,
+            "dnsmessage.NSECResource{{next_domain: {}, types: {{{}}}}}",
+            self.next_domain,
+            types.join(",")
+        )
+    }
+}
+
+impl ResourceBody for NSECResource {
+    fn real_type(&self) -> DNSType {
+        DNSType::NSEC
+    }
+
+    // pack appends the wire format of the NSECResource to msg.
+    fn pack(
+        &self,
+        msg: &mut Vec<u8>,
+        compression: &mut Option<HashMap<String, usize>>,
+        compression_off: usize,
+    ) -> Result<(), Error> {
+        self.next_domain.pack(msg, compression, compression_off)?;
+
+        // The type bitmap is split into windows of 256 types, each holding
+        // only as many bytes as needed to cover its highest type (RFC 4034
+        // Section 4.1.2).
+        let mut types: Vec<u16> = self.types.iter().map(|t| t.0).collect();
+        types.sort_unstable();
+        types.dedup();
+
+        let mut i = 0;
+        while i < types.len() {
+            let window = types[i] >> 8;
+            let mut bitmap = [0u8; 32];
+            let mut bitmap_len = 0;
+            while i < types.len() && types[i] >> 8 == window {
+                let bit = (types[i] & 0xFF) as usize;
+                bitmap[bit / 8] |= 0x80 >> (bit % 8);
+                bitmap_len = bit / 8 + 1;
+                i += 1;
+            }
+            msg.push(window as u8);
+            msg.push(bitmap_len as u8);
+            pack_bytes(msg, &bitmap[..bitmap_len]);
+        }
+
+        Ok(())
+    }
+
+    fn unpack(&mut self, msg: &[u8], off: usize, length: usize) -> Result<usize, Error> {
+        let end_off = off + length;
+        if end_off > msg.len() {
+            return Err(ERR_CALC_LEN.to_owned());
+        }
+
+        let mut off = self.next_domain.unpack(msg, off)?;
+        if off > end_off {
+            return Err(ERR_CALC_LEN.to_owned());
+        }
+
+        let mut types = vec![];
+        let mut last_window = None;
+        while off < end_off {
+            if off + 2 > end_off {
+                return Err(ERR_INVALID_TYPE_BITMAP.to_owned());
+            }
+            let window = msg[off];
+            let bitmap_len = msg[off + 1] as usize;
+            off += 2;
+
+            // Windows must appear in increasing order and hold 1 to 32 bytes.
+            if last_window.is_some_and(|w| window <= w) || bitmap_len == 0 || bitmap_len > 32 {
+                return Err(ERR_INVALID_TYPE_BITMAP.to_owned());
+            }
+            if off + bitmap_len > end_off {
+                return Err(ERR_CALC_LEN.to_owned());
+            }
+            last_window = Some(window);
+
+            for (i, b) in msg[off..off + bitmap_len].iter().enumerate() {
+                for bit in 0..8 {
+                    if b & (0x80 >> bit) != 0 {
+                        let t = (window as u16) << 8 | (i * 8 + bit) as u16;
+                        types.push(DNSType::from(t));
+                    }
+                }
+            }
+            off += bitmap_len;
+        }
+        self.types = types;
+
+        Ok(end_off)
+    }
+}
@@ -2,11 +2,25 @@ use super::*;
 use crate::errors::*;
 use crate::message::packer::*;
 
+// EDNS(0) option codes (RFC 6891 Section 6.1.2 and the IANA registry) that
+// are commonly seen in mDNS traffic.
+pub const EDNS0_OPTION_LLQ: u16 = 1;
+pub const EDNS0_OPTION_UL: u16 = 2;
+pub const EDNS0_OPTION_NSID: u16 = 3;
+pub const EDNS0_OPTION_OWNER: u16 = 4;
+pub const EDNS0_OPTION_CLIENT_SUBNET: u16 = 8;
+pub const EDNS0_OPTION_EXPIRE: u16 = 9;
+pub const EDNS0_OPTION_COOKIE: u16 = 10;
+pub const EDNS0_OPTION_TCP_KEEPALIVE: u16 = 11;
+pub const EDNS0_OPTION_PADDING: u16 = 12;
+
 // An OPTResource is an OPT pseudo Resource record.
 //
 // The pseudo resource record is part of the extension mechanisms for DNS
 // as defined in RFC 6891.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct OPTResource {
     pub options: Vec<DNSOption>,
 }
@@ -15,12 +29,33 @@ pub struct OPTResource {
 //
 // The message option is part of the extension mechanisms for DNS as
 // defined in RFC 6891.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct DNSOption {
     pub code: u16, // option code
     pub data: Vec<u8>,
 }
 
+impl DNSOption {
+    // is_known reports whether the option code is one of the EDNS0_OPTION_*
+    // codes understood by this package.
+    pub fn is_known(&self) -> bool {
+        matches!(
+            self.code,
+            EDNS0_OPTION_LLQ
+                | EDNS0_OPTION_UL
+                | EDNS0_OPTION_NSID
+                | EDNS0_OPTION_OWNER
+                | EDNS0_OPTION_CLIENT_SUBNET
+                | EDNS0_OPTION_EXPIRE
+                | EDNS0_OPTION_COOKIE
+                | EDNS0_OPTION_TCP_KEEPALIVE
+                | EDNS0_OPTION_PADDING
+        )
+    }
+}
+
 impl fmt::Display for DNSOption {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -38,6 +73,25 @@ impl fmt::Display for OPTResource {
     }
 }
 
+impl OPTResource {
+    // option returns the first option with the given code.
+    pub fn option(&self, code: u16) -> Option<&DNSOption> {
+        self.options.iter().find(|o| o.code == code)
+    }
+
+    // unknown_options returns the options whose codes are not understood by
+    // this package, so callers can surface or log them.
+    pub fn unknown_options(&self) -> Vec<&DNSOption> {
+        self.options.iter().filter(|o| !o.is_known()).collect()
+    }
+
+    // skip_unknown_options drops every option whose code is not understood
+    // by this package.
+    pub fn skip_unknown_options(&mut self) {
+        self.options.retain(|o| o.is_known());
+    }
+}
+
 impl ResourceBody for OPTResource {
     fn real_type(&self) -> DNSType {
         DNSType::OPT
@@ -45,16 +99,16 @@ impl ResourceBody for OPTResource {
 
     fn pack(
         &self,
-        mut msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         _compression: &mut Option<HashMap<String, usize>>,
         _compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
+    ) -> Result<(), Error> {
         for opt in &self.options {
-            msg = pack_uint16(msg, opt.code);
-            msg = pack_uint16(msg, opt.data.len() as u16);
-            msg = pack_bytes(msg, &opt.data);
+            pack_uint16(msg, opt.code);
+            pack_uint16(msg, opt.data.len() as u16);
+            pack_bytes(msg, &opt.data);
         }
-        Ok(msg)
+        Ok(())
     }
 
     fn unpack(&mut self, msg: &[u8], mut off: usize, length: usize) -> Result<usize, Error> {
@@ -71,7 +125,7 @@ impl ResourceBody for OPTResource {
                 code,
                 data: vec![0; l as usize],
             };
-            if off + l as usize > msg.len() {
+            if off + l as usize > msg.len() || off + l as usize > old_off + length {
                 return Err(ERR_CALC_LEN.to_owned());
             }
             opt.data.copy_from_slice(&msg[off..off + l as usize]);
@@ -2,7 +2,9 @@ use super::*;
 use crate::message::name::*;
 
 // A PTRResource is a PTR Resource record.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct PTRResource {
     pub ptr: Name,
 }
@@ -21,10 +23,10 @@ impl ResourceBody for PTRResource {
     // pack appends the wire format of the PTRResource to msg.
     fn pack(
         &self,
-        msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
+    ) -> Result<(), Error> {
         self.ptr.pack(msg, compression, compression_off)
     }
 
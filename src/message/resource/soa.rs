@@ -3,7 +3,9 @@ use crate::message::name::*;
 use crate::message::packer::*;
 
 // An SOAResource is an SOA Resource record.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct SOAResource {
     pub ns: Name,
     pub mbox: Name,
@@ -42,17 +44,18 @@ impl ResourceBody for SOAResource {
     // pack appends the wire format of the SOAResource to msg.
     fn pack(
         &self,
-        mut msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
-        msg = self.ns.pack(msg, compression, compression_off)?;
-        msg = self.mbox.pack(msg, compression, compression_off)?;
-        msg = pack_uint32(msg, self.serial);
-        msg = pack_uint32(msg, self.refresh);
-        msg = pack_uint32(msg, self.retry);
-        msg = pack_uint32(msg, self.expire);
-        Ok(pack_uint32(msg, self.min_ttl))
+    ) -> Result<(), Error> {
+        self.ns.pack(msg, compression, compression_off)?;
+        self.mbox.pack(msg, compression, compression_off)?;
+        pack_uint32(msg, self.serial);
+        pack_uint32(msg, self.refresh);
+        pack_uint32(msg, self.retry);
+        pack_uint32(msg, self.expire);
+        pack_uint32(msg, self.min_ttl);
+        Ok(())
     }
 
     fn unpack(&mut self, msg: &[u8], mut off: usize, _length: usize) -> Result<usize, Error> {
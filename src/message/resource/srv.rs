@@ -3,7 +3,9 @@ use crate::message::name::*;
 use crate::message::packer::*;
 
 // An SRVResource is an SRV Resource record.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct SRVResource {
     pub priority: u16,
     pub weight: u16,
@@ -29,15 +31,15 @@ impl ResourceBody for SRVResource {
     // pack appends the wire format of the SRVResource to msg.
     fn pack(
         &self,
-        mut msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         _compression: &mut Option<HashMap<String, usize>>,
         compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
-        msg = pack_uint16(msg, self.priority);
-        msg = pack_uint16(msg, self.weight);
-        msg = pack_uint16(msg, self.port);
-        msg = self.target.pack(msg, &mut None, compression_off)?;
-        Ok(msg)
+    ) -> Result<(), Error> {
+        pack_uint16(msg, self.priority);
+        pack_uint16(msg, self.weight);
+        pack_uint16(msg, self.port);
+        self.target.pack(msg, &mut None, compression_off)?;
+        Ok(())
     }
 
     fn unpack(&mut self, msg: &[u8], off: usize, _length: usize) -> Result<usize, Error> {
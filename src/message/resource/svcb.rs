@@ -0,0 +1,223 @@
+use super::*;
+use crate::errors::*;
+use crate::message::name::*;
+use crate::message::packer::*;
+
+// SvcParamKeys defined in RFC 9460 Section 14.3.2.
+pub const SVC_PARAM_MANDATORY: u16 = 0;
+pub const SVC_PARAM_ALPN: u16 = 1;
+pub const SVC_PARAM_NO_DEFAULT_ALPN: u16 = 2;
+pub const SVC_PARAM_PORT: u16 = 3;
+pub const SVC_PARAM_IPV4_HINT: u16 = 4;
+pub const SVC_PARAM_ECH: u16 = 5;
+pub const SVC_PARAM_IPV6_HINT: u16 = 6;
+
+// An SVCBParam is a single SvcParam key/value pair of an SVCB or HTTPS
+// Resource record. The value is kept in its wire format.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct SVCBParam {
+    pub key: u16,
+    pub value: Vec<u8>,
+}
+
+impl fmt::Display for SVCBParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dnsmessage.SVCBParam{{key: {}, value: {:?}}}",
+            self.key, self.value
+        )
+    }
+}
+
+impl SVCBParam {
+    // presentation returns the param in the key=value form of RFC 9460
+    // Section 2.1. Values without a defined text form are written as hex.
+    pub(crate) fn presentation(&self) -> String {
+        let key = match self.key {
+            SVC_PARAM_MANDATORY => "mandatory".to_owned(),
+            SVC_PARAM_ALPN => "alpn".to_owned(),
+            SVC_PARAM_NO_DEFAULT_ALPN => return "no-default-alpn".to_owned(),
+            SVC_PARAM_PORT => "port".to_owned(),
+            SVC_PARAM_IPV4_HINT => "ipv4hint".to_owned(),
+            SVC_PARAM_ECH => "ech".to_owned(),
+            SVC_PARAM_IPV6_HINT => "ipv6hint".to_owned(),
+            k => format!("key{}", k),
+        };
+
+        let value = match self.key {
+            SVC_PARAM_PORT if self.value.len() == 2 => {
+                u16::from_be_bytes([self.value[0], self.value[1]]).to_string()
+            }
+            SVC_PARAM_ALPN => {
+                let mut ids = vec![];
+                let mut off = 0;
+                while off < self.value.len() {
+                    let end = (off + 1 + self.value[off] as usize).min(self.value.len());
+                    ids.push(String::from_utf8_lossy(&self.value[off + 1..end]).into_owned());
+                    off = end;
+                }
+                ids.join(",")
+            }
+            SVC_PARAM_IPV4_HINT if self.value.len() % 4 == 0 => {
+                let ips: Vec<String> = self
+                    .value
+                    .chunks(4)
+                    .map(|c| std::net::Ipv4Addr::new(c[0], c[1], c[2], c[3]).to_string())
+                    .collect();
+                ips.join(",")
+            }
+            SVC_PARAM_IPV6_HINT if self.value.len() % 16 == 0 => {
+                let ips: Vec<String> = self
+                    .value
+                    .chunks(16)
+                    .map(|c| {
+                        let mut b = [0u8; 16];
+                        b.copy_from_slice(c);
+                        std::net::Ipv6Addr::from(b).to_string()
+                    })
+                    .collect();
+                ips.join(",")
+            }
+            _ => self.value.iter().map(|b| format!("{:02x}", b)).collect(),
+        };
+
+        format!("{}={}", key, value)
+    }
+}
+
+// An SVCBResource is an SVCB Resource record.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct SVCBResource {
+    pub priority: u16,
+    pub target: Name, // Not compressed as per RFC 9460.
+    pub params: Vec<SVCBParam>,
+}
+
+impl fmt::Display for SVCBResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|p| p.to_string()).collect();
+        write!(
+            f,
+            "dnsmessage.SVCBResource{{priority: {}, target: {}, params: {}}}",
+            self.priority,
+            self.target,
+            params.join(",")
+        )
+    }
+}
+
+impl SVCBResource {
+    // param returns the value of the SvcParam with the given key.
+    pub fn param(&self, key: u16) -> Option<&[u8]> {
+        self.params
+            .iter()
+            .find(|p| p.key == key)
+            .map(|p| p.value.as_slice())
+    }
+}
+
+impl ResourceBody for SVCBResource {
+    fn real_type(&self) -> DNSType {
+        DNSType::SVCB
+    }
+
+    // pack appends the wire format of the SVCBResource to msg.
+    fn pack(
+        &self,
+        msg: &mut Vec<u8>,
+        _compression: &mut Option<HashMap<String, usize>>,
+        compression_off: usize,
+    ) -> Result<(), Error> {
+        pack_uint16(msg, self.priority);
+        self.target.pack(msg, &mut None, compression_off)?;
+        for p in &self.params {
+            if p.value.len() > u16::MAX as usize {
+                return Err(ERR_RES_TOO_LONG.to_owned());
+            }
+            pack_uint16(msg, p.key);
+            pack_uint16(msg, p.value.len() as u16);
+            pack_bytes(msg, &p.value);
+        }
+        Ok(())
+    }
+
+    fn unpack(&mut self, msg: &[u8], off: usize, length: usize) -> Result<usize, Error> {
+        let end_off = off + length;
+        if end_off > msg.len() {
+            return Err(ERR_CALC_LEN.to_owned());
+        }
+
+        let (priority, off) = unpack_uint16(msg, off)?;
+        self.priority = priority;
+
+        let mut off = self
+            .target
+            .unpack_compressed(msg, off, false /* allowCompression */)?;
+
+        let mut params = vec![];
+        while off < end_off {
+            let (key, new_off) = unpack_uint16(msg, off)?;
+            let (l, new_off) = unpack_uint16(msg, new_off)?;
+            off = new_off;
+
+            if off + l as usize > end_off {
+                return Err(ERR_CALC_LEN.to_owned());
+            }
+            params.push(SVCBParam {
+                key,
+                value: msg[off..off + l as usize].to_vec(),
+            });
+            off += l as usize;
+        }
+        self.params = params;
+
+        Ok(off)
+    }
+}
+
+// An HTTPSResource is an HTTPS Resource record. It shares the wire format of
+// SVCBResource (RFC 9460 Section 9).
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct HTTPSResource {
+    pub svcb: SVCBResource,
+}
+
+impl fmt::Display for HTTPSResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.svcb.params.iter().map(|p| p.to_string()).collect();
+        write!(
+            f,
+            "dnsmessage.HTTPSResource{{priority: {}, target: {}, params: {}}}",
+            self.svcb.priority,
+            self.svcb.target,
+            params.join(",")
+        )
+    }
+}
+
+impl ResourceBody for HTTPSResource {
+    fn real_type(&self) -> DNSType {
+        DNSType::HTTPS
+    }
+
+    // pack appends the wire format of the HTTPSResource to msg.
+    fn pack(
+        &self,
+        msg: &mut Vec<u8>,
+        compression: &mut Option<HashMap<String, usize>>,
+        compression_off: usize,
+    ) -> Result<(), Error> {
+        self.svcb.pack(msg, compression, compression_off)
+    }
+
+    fn unpack(&mut self, msg: &[u8], off: usize, length: usize) -> Result<usize, Error> {
+        self.svcb.unpack(msg, off, length)
+    }
+}
@@ -3,7 +3,9 @@ use crate::errors::*;
 use crate::message::packer::*;
 
 // A TXTResource is a txt Resource record.
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
 pub struct TXTResource {
     pub txt: Vec<String>,
 }
@@ -18,6 +20,52 @@ impl fmt::Display for TXTResource {
     }
 }
 
+impl TXTResource {
+    // from_pairs builds a TXTResource from key/value pairs as described in
+    // RFC 6763 Section 6.3. A pair without a value is packed as a bare key
+    // (a boolean attribute), while Some("") is packed as "key=".
+    pub fn from_pairs(pairs: &[(&str, Option<&str>)]) -> Self {
+        TXTResource {
+            txt: pairs
+                .iter()
+                .map(|(k, v)| match v {
+                    Some(v) => format!("{}={}", k, v),
+                    None => (*k).to_owned(),
+                })
+                .collect(),
+        }
+    }
+
+    // pairs returns the key/value pairs held in the TXTResource. Strings
+    // with an empty key are ignored, as required by RFC 6763 Section 6.4.
+    pub fn pairs(&self) -> Vec<(&str, Option<&str>)> {
+        self.txt
+            .iter()
+            .filter_map(|s| {
+                let (k, v) = match s.find('=') {
+                    Some(i) => (&s[..i], Some(&s[i + 1..])),
+                    None => (s.as_str(), None),
+                };
+                if k.is_empty() {
+                    None
+                } else {
+                    Some((k, v))
+                }
+            })
+            .collect()
+    }
+
+    // get returns the value of the first pair whose key matches key. Keys
+    // are compared case-insensitively. The outer Option is None when the key
+    // is absent and the inner Option is None for a boolean attribute.
+    pub fn get(&self, key: &str) -> Option<Option<&str>> {
+        self.pairs()
+            .into_iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+}
+
 impl ResourceBody for TXTResource {
     fn real_type(&self) -> DNSType {
         DNSType::TXT
@@ -26,14 +74,14 @@ impl ResourceBody for TXTResource {
     // pack appends the wire format of the TXTResource to msg.
     fn pack(
         &self,
-        mut msg: Vec<u8>,
+        msg: &mut Vec<u8>,
         _compression: &mut Option<HashMap<String, usize>>,
         _compression_off: usize,
-    ) -> Result<Vec<u8>, Error> {
+    ) -> Result<(), Error> {
         for s in &self.txt {
-            msg = pack_str(msg, s)?
+            pack_str(msg, s)?;
         }
-        Ok(msg)
+        Ok(())
     }
 
     fn unpack(&mut self, msg: &[u8], mut off: usize, length: usize) -> Result<usize, Error> {
@@ -43,7 +91,7 @@ impl ResourceBody for TXTResource {
             let (t, new_off) = unpack_str(msg, off)?;
             off = new_off;
             // Check if we got too many bytes.
-            if length < n + t.as_bytes().len() + 1 {
+            if length < n + t.len() + 1 {
                 return Err(ERR_CALC_LEN.to_owned());
             }
             n += t.len() + 1;
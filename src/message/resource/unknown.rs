@@ -0,0 +1,60 @@
+use super::*;
+use crate::errors::*;
+use crate::message::packer::*;
+
+// An UnknownResource is a Resource record of a type this package doesn't
+// understand. Its RDATA is kept verbatim so it can be packed again unchanged.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnknownResource {
+    pub typ: DNSType,
+    pub data: Vec<u8>,
+}
+
+// Arbitrary UnknownResources use types from the private use range, so they
+// never collide with a type that unpacks into another ResourceData variant.
+#[cfg(feature = "fuzz")]
+impl<'a> arbitrary::Arbitrary<'a> for UnknownResource {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(UnknownResource {
+            typ: DNSType(0xFF00 | u16::from(u.arbitrary::<u8>()?)),
+            data: u.arbitrary()?,
+        })
+    }
+}
+
+impl fmt::Display for UnknownResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dnsmessage.UnknownResource{{typ: {}, data: {:?}}}",
+            self.typ, self.data
+        )
+    }
+}
+
+impl ResourceBody for UnknownResource {
+    fn real_type(&self) -> DNSType {
+        self.typ
+    }
+
+    // pack appends the wire format of the UnknownResource to msg.
+    fn pack(
+        &self,
+        msg: &mut Vec<u8>,
+        _compression: &mut Option<HashMap<String, usize>>,
+        _compression_off: usize,
+    ) -> Result<(), Error> {
+        pack_bytes(msg, &self.data);
+        Ok(())
+    }
+
+    fn unpack(&mut self, msg: &[u8], off: usize, length: usize) -> Result<usize, Error> {
+        let end_off = off + length;
+        if end_off > msg.len() {
+            return Err(ERR_CALC_LEN.to_owned());
+        }
+        self.data = msg[off..end_off].to_vec();
+        Ok(end_off)
+    }
+}